@@ -14,7 +14,7 @@
 *  limitations under the License.
 ********************************************************************************/
 
-//! This crate exports 4 macros that are useful if not essential for correct
+//! This crate exports some macros that are useful if not essential for correct
 //! and ergonomic rust in a ledger app
 //!
 //! The currently exported macros are:
@@ -23,6 +23,7 @@
 //! * [macro@pic_str]
 //! * [macro@lazy_static]
 //! * [macro@enum_init]
+//! * [derive@ToBytes]
 
 use proc_macro::TokenStream;
 use quote::quote;
@@ -200,3 +201,31 @@ mod enum_init;
 pub fn enum_init(metadata: TokenStream, input: TokenStream) -> TokenStream {
     enum_init::enum_init(metadata, input)
 }
+
+mod to_bytes;
+
+#[proc_macro_error]
+#[proc_macro_derive(ToBytes)]
+/// Derive `bolos::ToBytes` for a struct, serializing each field in declaration order.
+///
+/// All fields must implement `bolos::ToBytes`, and the same bound is added to
+/// every type parameter of the struct.
+///
+/// # Example
+/// ```rust
+/// #[derive(bolos_derive::ToBytes)]
+/// struct Foo<'b> {
+///     version: u8,
+///     amount: u64,
+///     memo: &'b [u8],
+/// }
+///
+/// let foo = Foo { version: 1, amount: 2, memo: b"hi" };
+///
+/// let mut out = [0; 11];
+/// assert_eq!(bolos::ToBytes::to_bytes_into(&foo, &mut out).ok(), Some(11));
+/// assert_eq!(out, [1, 0, 0, 0, 0, 0, 0, 0, 2, b'h', b'i']);
+/// ```
+pub fn to_bytes(input: TokenStream) -> TokenStream {
+    to_bytes::to_bytes(input)
+}
@@ -0,0 +1,95 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use proc_macro_error::abort;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, GenericParam, Index};
+
+pub fn to_bytes(input: TokenStream) -> TokenStream {
+    let DeriveInput {
+        ident,
+        mut generics,
+        data,
+        ..
+    } = parse_macro_input!(input as DeriveInput);
+
+    let fields = match data {
+        Data::Struct(data) => data.fields,
+        Data::Enum(data) => abort!(
+            data.enum_token.span,
+            "ToBytes can only be derived for structs"
+        ),
+        Data::Union(data) => abort!(
+            data.union_token.span,
+            "ToBytes can only be derived for structs"
+        ),
+    };
+
+    //access each field by name or by position
+    let accessors: Vec<TokenStream2> = match &fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|f| {
+                let name = f.ident.as_ref().unwrap();
+                quote! { #name }
+            })
+            .collect(),
+        Fields::Unnamed(unnamed) => (0..unnamed.unnamed.len())
+            .map(|i| {
+                let i = Index::from(i);
+                quote! { #i }
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+
+    //every type parameter needs to be serializable too
+    for param in generics.params.iter_mut() {
+        if let GenericParam::Type(ty) = param {
+            ty.bounds.push(parse_quote!(::bolos::ToBytes));
+        }
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics ::bolos::ToBytes for #ident #ty_generics #where_clause {
+            fn serialized_len(&self) -> usize {
+                0 #(+ ::bolos::ToBytes::serialized_len(&self.#accessors))*
+            }
+
+            #[inline(never)]
+            #[allow(unused_mut, unused_variables)]
+            fn to_bytes_into(
+                &self,
+                out: &mut [u8],
+            ) -> ::core::result::Result<usize, ::bolos::OutputBufferTooSmall> {
+                let mut written = 0;
+
+                #(
+                    written += ::bolos::ToBytes::to_bytes_into(
+                        &self.#accessors,
+                        out.get_mut(written..).ok_or(::bolos::OutputBufferTooSmall)?,
+                    )?;
+                )*
+
+                Ok(written)
+            }
+        }
+    }
+    .into()
+}
@@ -0,0 +1,72 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+use core::{mem::MaybeUninit, ptr::addr_of_mut};
+
+use bolos::{
+    crypto::bip32::BIP32Path,
+    parser::{assert_roundtrip, be_u16},
+    FromBytes, OutputBufferTooSmall, ToBytes,
+};
+
+#[derive(ToBytes, PartialEq)]
+struct Record<const N: usize> {
+    kind: u16,
+    path: BIP32Path<N>,
+}
+
+impl<'b, const N: usize> FromBytes<'b> for Record<N> {
+    type Error = ();
+
+    fn from_bytes_into(
+        input: &'b [u8],
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], Self::Error> {
+        let out = out.as_mut_ptr();
+
+        let (rem, kind) = be_u16(input).map_err(|_| ())?;
+        unsafe {
+            addr_of_mut!((*out).kind).write(kind);
+        }
+
+        let path = unsafe { &mut *addr_of_mut!((*out).path).cast() };
+        BIP32Path::<N>::from_bytes_into(rem, path).map_err(|_| ())
+    }
+}
+
+#[derive(ToBytes)]
+struct Tuple<'b, T>(T, &'b [u8]);
+
+#[test]
+fn roundtrip() {
+    let record = Record::<5> {
+        kind: 0x0102,
+        path: BIP32Path::new([0x8000_002c, 0x8000_0001]).unwrap(),
+    };
+
+    assert_eq!(record.serialized_len(), 2 + 1 + 2 * 4);
+    assert_roundtrip(&record, &mut [0; 32]);
+}
+
+#[test]
+fn generics() {
+    let tuple = Tuple(0xAAu8, &[1, 2, 3]);
+
+    let mut out = [0; 4];
+    assert_eq!(tuple.to_bytes_into(&mut out).ok(), Some(4));
+    assert_eq!(out, [0xAA, 1, 2, 3]);
+
+    assert!(tuple.to_bytes_into(&mut out[..3]) == Err(OutputBufferTooSmall));
+}
@@ -116,6 +116,50 @@ impl PublicKey {
         }
     }
 
+    /// Create a public key from the given uncompressed point
+    ///
+    /// Only secp256k1 and secp256r1 points are supported
+    #[inline(never)]
+    pub fn from_bytes(curve: Curve, bytes: &[u8]) -> Result<Self, Error> {
+        if !curve.is_weirstrass() || bytes.len() != 65 || bytes[0] != 0x04 {
+            return Err(Error::from(0xFFFFFF87u32)); //CX_INVALID_PARAMETER_VALUE
+        }
+
+        let mut out = MaybeUninit::uninit();
+        cx_ecfp_init_public_key_into(curve, bytes, &mut out)?;
+
+        //SAFE: initialized by the call above
+        Ok(Self(unsafe { out.assume_init() }))
+    }
+
+    /// Replace the public key `P` with `P + tweak * G`, in uncompressed form
+    ///
+    /// Only secp256k1 and secp256r1 keys are supported, and `tweak` must be
+    /// a valid scalar for the curve
+    #[inline(never)]
+    pub fn tweak_add(&mut self, tweak: &[u8; 32]) -> Result<(), Error> {
+        let curve = self.curve();
+        if !curve.is_weirstrass() || self.0.W[0] != 0x04 {
+            return Err(Error::from(0xFFFFFF87u32)); //CX_INVALID_PARAMETER_VALUE
+        }
+
+        //compute tweak * G as the public key of the tweak
+        let mut sk = MaybeUninit::uninit();
+        cx_ecfp_init_private_key_into(curve, Some(&tweak[..]), &mut sk)?;
+        //SAFE: initialized by the call above
+        let mut sk = Zeroizing::new(unsafe { sk.assume_init() });
+        let mut tweak_pk = MaybeUninit::uninit();
+        cx_ecfp_compute_public_into(curve, &mut sk, &mut tweak_pk)?;
+        //SAFE: initialized by the call above
+        let tweak_pk = unsafe { tweak_pk.assume_init() };
+
+        let point = self.0.W;
+        cx_ecfp_add_point(curve, &mut self.0.W[..65], &point[..65], &tweak_pk.W[..65])?;
+        self.0.W_len = 65;
+
+        Ok(())
+    }
+
     pub fn curve(&self) -> Curve {
         use core::convert::TryFrom;
 
@@ -143,6 +187,42 @@ impl AsRef<[u8]> for PublicKey {
     }
 }
 
+/// Output of [`SecretKey::ecdh`]
+#[derive(Clone, Copy)]
+pub enum ECDHMode {
+    /// The full shared point, uncompressed
+    Point,
+    /// Only the x coordinate of the shared point
+    X,
+}
+
+pub struct SharedSecret {
+    len: usize,
+    data: [u8; 65],
+}
+
+impl SharedSecret {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl AsRef<[u8]> for SharedSecret {
+    fn as_ref(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl Drop for SharedSecret {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
 pub struct SecretKey<const B: usize> {
     mode: Mode,
     curve: Curve,
@@ -240,6 +320,42 @@ impl<const B: usize> SecretKey<B> {
         Ok(())
     }
 
+    #[inline(never)]
+    pub fn ecdh(&self, peer: &PublicKey, mode: ECDHMode) -> Result<SharedSecret, Error> {
+        let mut out = MaybeUninit::uninit();
+
+        self.ecdh_into(peer, mode, &mut out)?;
+
+        //this is safe as the call above initialized it
+        Ok(unsafe { out.assume_init() })
+    }
+
+    #[inline(never)]
+    /// Compute the shared secret with the `peer` public key, of the same curve
+    ///
+    /// On secp256k1 and secp256r1 the peer key must be uncompressed,
+    /// while Curve25519 keys are `02 || u`, as returned by [`SecretKey::public`],
+    /// and only support [`ECDHMode::X`], as X25519 does
+    pub fn ecdh_into(
+        &self,
+        peer: &PublicKey,
+        mode: ECDHMode,
+        out: &mut MaybeUninit<SharedSecret>,
+    ) -> Result<(), Error> {
+        zemu_sys::zemu_log_stack("SecretKey::ecdh_into\x00");
+
+        *out = MaybeUninit::new(SharedSecret {
+            len: 0,
+            data: [0; 65],
+        });
+        //SAFE: initialized above
+        let out = unsafe { out.assume_init_mut() };
+
+        out.len = cx_ecdh(self, mode, peer, &mut out.data)?;
+
+        Ok(())
+    }
+
     #[inline(never)]
     pub fn sign<H>(&self, data: &[u8], out: &mut [u8]) -> Result<(BitFlags<ECCInfo>, usize), Error>
     where
@@ -264,7 +380,9 @@ impl<const B: usize> SecretKey<B> {
 mod bindings {
     #![allow(unused_imports)]
 
-    use super::{BitFlags, Curve, ECCInfo, Error, HasherId, SecretKey, CHAIN_CODE_LEN};
+    use super::{
+        BitFlags, Curve, ECCInfo, ECDHMode, Error, HasherId, PublicKey, SecretKey, CHAIN_CODE_LEN,
+    };
     use crate::{
         errors::catch,
         raw::{cx_ecfp_private_key_t, cx_ecfp_public_key_t},
@@ -337,6 +455,147 @@ mod bindings {
         Ok(())
     }
 
+    pub fn cx_ecfp_init_public_key_into(
+        curve: Curve,
+        data: &[u8],
+        out: &mut MaybeUninit<cx_ecfp_public_key_t>,
+    ) -> Result<(), Error> {
+        zemu_sys::zemu_log_stack("cx_ecfp_init_public_key_into\x00");
+        let curve: u8 = curve.into();
+
+        let out = out.as_mut_ptr();
+
+        cfg_if! {
+            if #[cfg(bolos_sdk)] {
+                match unsafe { crate::raw::cx_ecfp_init_public_key_no_throw(
+                    curve as _,
+                    data.as_ptr() as *const _,
+                    data.len() as u32 as _,
+                    out,
+                )} {
+                    0 => {},
+                    err => return Err(err.into()),
+                }
+            } else {
+                unsafe { core::hint::unreachable_unchecked() }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compute the public key of the given private key, keeping the latter intact
+    pub fn cx_ecfp_compute_public_into(
+        curve: Curve,
+        sk: &mut cx_ecfp_private_key_t,
+        out_pk: &mut MaybeUninit<cx_ecfp_public_key_t>,
+    ) -> Result<(), Error> {
+        zemu_sys::zemu_log_stack("cx_ecfp_compute_public_into\x00");
+        let curve: u8 = curve.into();
+
+        let raw_sk = sk as *mut cx_ecfp_private_key_t;
+        let pk = out_pk.as_mut_ptr();
+
+        cfg_if! {
+            if #[cfg(bolos_sdk)] {
+                match unsafe { crate::raw::cx_ecfp_generate_pair_no_throw(
+                    curve as _,
+                    pk,
+                    raw_sk,
+                    true,
+                )} {
+                    0 => (),
+                    err => return Err(err.into()),
+                }
+            } else {
+                unsafe { core::hint::unreachable_unchecked() }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write `p + q` in `r`, all points being uncompressed
+    pub fn cx_ecfp_add_point(curve: Curve, r: &mut [u8], p: &[u8], q: &[u8]) -> Result<(), Error> {
+        zemu_sys::zemu_log_stack("cx_ecfp_add_point\x00");
+        let curve: u8 = curve.into();
+
+        cfg_if! {
+            if #[cfg(bolos_sdk)] {
+                match unsafe { crate::raw::cx_ecfp_add_point_no_throw(
+                    curve as _,
+                    r.as_mut_ptr() as *mut _,
+                    p.as_ptr() as *const _,
+                    q.as_ptr() as *const _,
+                )} {
+                    0 => {},
+                    err => return Err(err.into()),
+                }
+            } else {
+                unsafe { core::hint::unreachable_unchecked() }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write in `out` the shared secret between `sk` and `peer`,
+    /// returning the number of bytes written
+    pub fn cx_ecdh<const B: usize>(
+        sk: &SecretKey<B>,
+        mode: ECDHMode,
+        peer: &PublicKey,
+        out: &mut [u8],
+    ) -> Result<usize, Error> {
+        zemu_sys::zemu_log_stack("cx_ecdh\x00");
+        use crate::raw::{CX_ECDH_POINT, CX_ECDH_X};
+
+        let curve = sk.curve();
+        //CX_INVALID_CURVE
+        let domain = curve.domain_length().ok_or(Error::from(0xFFFFFFA3u32))?;
+
+        //CX_INVALID_PARAMETER_VALUE, like the mock:
+        // the peer must be of the same curve and uncompressed on weierstrass curves
+        let (sk_curve, peer_curve): (u8, u8) = (curve.into(), peer.curve().into());
+        if sk_curve != peer_curve || (curve.is_weirstrass() && peer.len() != 1 + 2 * domain) {
+            return Err(Error::from(0xFFFFFF87u32));
+        }
+
+        let (mode, len) = match mode {
+            //CX_INVALID_PARAMETER_VALUE, montgomery curves only have the x coordinate
+            ECDHMode::Point if curve.is_montgomery() => return Err(Error::from(0xFFFFFF87u32)),
+            ECDHMode::Point => (CX_ECDH_POINT, 1 + 2 * domain),
+            ECDHMode::X => (CX_ECDH_X, domain),
+        };
+        //CX_INVALID_PARAMETER_VALUE
+        let out = out.get_mut(..len).ok_or(Error::from(0xFFFFFF87u32))?;
+
+        let raw_sk = sk.generate(None)?;
+        let raw_sk: *const cx_ecfp_private_key_t = &*raw_sk;
+
+        let (peer, peer_len) = (peer.as_ref().as_ptr(), peer.len());
+
+        cfg_if! {
+            if #[cfg(bolos_sdk)] {
+                match unsafe { crate::raw::cx_ecdh_no_throw(
+                    raw_sk,
+                    mode as _,
+                    peer,
+                    peer_len as _,
+                    out.as_mut_ptr(),
+                    len as _,
+                )} {
+                    0 => {},
+                    err => return Err(err.into()),
+                }
+            } else {
+                unsafe { core::hint::unreachable_unchecked() }
+            }
+        }
+
+        Ok(len)
+    }
+
     pub fn cx_ecfp_generate_pair_into<const B: usize>(
         sk: Option<&SecretKey<B>>,
         curve: Curve,
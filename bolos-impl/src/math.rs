@@ -64,3 +64,23 @@ pub fn modm(v: &mut [u8], m: &[u8]) -> Result<(), Error> {
         }
     }
 }
+
+/// Subtraction
+///
+/// Computes a - b, modulo 2^(8 * len), storing the result in r
+pub fn sub(r: &mut [u8], a: &[u8], b: &[u8]) -> Result<(), Error> {
+    let len = core::cmp::min(r.len(), core::cmp::min(a.len(), b.len()));
+    let r = r.as_mut_ptr();
+    let (a, b) = (a.as_ptr(), b.as_ptr());
+
+    cfg_if! {
+        if #[cfg(bolos_sdk)] {
+            match unsafe { crate::raw::cx_math_sub_no_throw(r, a, b, len as _) } {
+                0 => Ok(()),
+                err => Err(err.into())
+            }
+        } else {
+            unimplemented!("cx_math_sub called in non-bolos");
+        }
+    }
+}
@@ -27,6 +27,8 @@ pub enum Curve {
     Ed25519,
 
     Stark256,
+
+    Curve25519,
 }
 
 impl TryFrom<u8> for Curve {
@@ -39,6 +41,7 @@ impl TryFrom<u8> for Curve {
             3 => Ok(Self::Ed25519),
 
             4 => Ok(Self::Stark256),
+            5 => Ok(Self::Curve25519),
             _ => Err(()),
         }
     }
@@ -51,6 +54,7 @@ impl From<Curve> for u8 {
             Curve::Secp256R1 => 2,
             Curve::Ed25519 => 3,
             Curve::Stark256 => 4,
+            Curve::Curve25519 => 5,
         }
     }
 }
@@ -65,7 +69,7 @@ impl Curve {
     }
 
     pub fn is_montgomery(&self) -> bool {
-        false
+        matches!(self, Self::Curve25519)
     }
 }
 
@@ -105,6 +109,10 @@ impl Default for Mode {
     }
 }
 
+mod ecdsa;
+
 pub mod ecfp256;
 
+pub mod hdw;
+
 pub mod stark;
@@ -0,0 +1,351 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! `cx_ecdsa_sign` with `CX_RND_RFC6979` for the curves of the mock
+//!
+//! `data` is the digest to sign, truncated to the bit length of the curve order,
+//! while the hasher id selects the HMAC used to generate the nonce.
+//! Like the device, S is not normalised
+
+use hmac::{Hmac, Mac, NewMac};
+use k256::elliptic_curve::{
+    bigint::ArrayEncoding, group::Group, ops::Reduce, point::AffineCoordinates, CurveArithmetic,
+    Field, FieldBytes, PrimeField,
+};
+use sha2::{Sha256, Sha512};
+
+use super::ecfp256::{BitFlags, ECCInfo};
+use crate::{
+    errors::SyscallError,
+    hash::{self, HasherId},
+    math, Error,
+};
+
+//CX_INVALID_PARAMETER_VALUE
+const INVALID_PARAMETER: Error = SyscallError::Code(0xFF87);
+
+/// Maximum length of a DER signature with 32 bytes R and S
+pub(crate) const SIGNATURE_MAX_LEN: usize = 72;
+
+/// Leftmost `qlen` bits of `bytes` as a big endian integer, as per RFC6979 section 2.3.2
+pub(crate) fn bits2int(bytes: &[u8], qlen: usize) -> [u8; 32] {
+    let bytes = &bytes[..bytes.len().min(32)];
+
+    let mut out = [0; 32];
+    out[32 - bytes.len()..].copy_from_slice(bytes);
+
+    let shift = (8 * bytes.len()).saturating_sub(qlen);
+    for _ in 0..shift {
+        let mut carry = 0;
+        for b in out.iter_mut() {
+            let next = *b & 1;
+            *b = (*b >> 1) | (carry << 7);
+            carry = next;
+        }
+    }
+
+    out
+}
+
+/// Reduce `v` modulo `n`, given that `v < 2n`
+pub(crate) fn reduce(v: &mut [u8; 32], n: &[u8; 32]) {
+    if math::cmp(v, n).unwrap().is_ge() {
+        let tmp = *v;
+        math::sub(v, &tmp, n).unwrap();
+    }
+}
+
+/// HMAC of the hash functions supported by `cx_ecdsa_sign` with `CX_RND_RFC6979`
+#[derive(Clone, Copy)]
+enum Digest {
+    Sha256,
+    Sha512,
+}
+
+impl Digest {
+    fn from_id(id: u8) -> Result<Self, Error> {
+        if id == hash::Sha256::id() {
+            Ok(Self::Sha256)
+        } else if id == hash::Sha512::id() {
+            Ok(Self::Sha512)
+        } else {
+            Err(INVALID_PARAMETER)
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
+        }
+    }
+
+    fn hmac(self, key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+        let mut out = [0; 64];
+        match self {
+            Self::Sha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+                data.iter().for_each(|d| mac.update(d));
+                out[..32].copy_from_slice(&mac.finalize().into_bytes());
+            }
+            Self::Sha512 => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(key).unwrap();
+                data.iter().for_each(|d| mac.update(d));
+                out.copy_from_slice(&mac.finalize().into_bytes());
+            }
+        }
+
+        out
+    }
+}
+
+/// Deterministic nonces of RFC6979 section 3.2, for curves of at most 256 bits
+pub(crate) struct Rfc6979 {
+    digest: Digest,
+    qlen: usize,
+    k: [u8; 64],
+    v: [u8; 64],
+}
+
+impl Rfc6979 {
+    /// Prepare the nonces for signing `hash` with `secret` on the curve of order `n`,
+    /// whose bit length is `qlen`, using the HMAC of the hasher with the given `id`
+    pub(crate) fn new(
+        id: u8,
+        secret: &[u8; 32],
+        hash: &[u8],
+        n: &[u8; 32],
+        qlen: usize,
+    ) -> Result<Self, Error> {
+        let digest = Digest::from_id(id)?;
+        let len = digest.len();
+
+        let mut h1 = bits2int(hash, qlen);
+        reduce(&mut h1, n);
+
+        let mut v = [1; 64];
+        let k = digest.hmac(&[0; 64][..len], &[&v[..len], &[0], secret, &h1]);
+        v = digest.hmac(&k[..len], &[&v[..len]]);
+        let k = digest.hmac(&k[..len], &[&v[..len], &[1], secret, &h1]);
+        v = digest.hmac(&k[..len], &[&v[..len]]);
+
+        Ok(Self { digest, qlen, k, v })
+    }
+
+    /// Next candidate nonce, to be discarded by the caller if not in `[1, n)`
+    pub(crate) fn next(&mut self) -> [u8; 32] {
+        let len = self.digest.len();
+
+        self.v = self.digest.hmac(&self.k[..len], &[&self.v[..len]]);
+        let nonce = bits2int(&self.v[..len], self.qlen);
+
+        //prepare the following candidate
+        self.k = self.digest.hmac(&self.k[..len], &[&self.v[..len], &[0]]);
+        self.v = self.digest.hmac(&self.k[..len], &[&self.v[..len]]);
+
+        nonce
+    }
+}
+
+/// DER encode the big endian `r` and `s` in `out`, returning the number of bytes written
+pub(crate) fn encode_der(r: &[u8; 32], s: &[u8; 32], out: &mut [u8]) -> Result<usize, Error> {
+    let mut sig = [0; SIGNATURE_MAX_LEN];
+
+    let mut len = 2;
+    for int in [r, s] {
+        let zeros = int.iter().take_while(|b| **b == 0).count().min(31);
+        let int = &int[zeros..];
+        let pad = (int[0] & 0x80 != 0) as usize;
+
+        sig[len] = 0x02;
+        sig[len + 1] = (pad + int.len()) as u8;
+        sig[len + 2 + pad..len + 2 + pad + int.len()].copy_from_slice(int);
+        len += 2 + pad + int.len();
+    }
+    sig[0] = 0x30;
+    sig[1] = (len - 2) as u8;
+
+    out.get_mut(..len)
+        .ok_or(INVALID_PARAMETER)?
+        .copy_from_slice(&sig[..len]);
+
+    Ok(len)
+}
+
+/// Parse a big endian scalar, if in `[1, n)`
+fn scalar<C: CurveArithmetic>(bytes: &[u8; 32]) -> Option<C::Scalar> {
+    let mut repr = FieldBytes::<C>::default();
+    repr.copy_from_slice(bytes);
+
+    Option::<C::Scalar>::from(C::Scalar::from_repr(repr)).filter(|s| !bool::from(s.is_zero()))
+}
+
+/// Sign the digest `data` on a 256 bits weierstrass curve, writing the DER signature in `out`
+///
+/// Will return the parity of R and whether its x coordinate was above the curve order,
+/// together with the length of the signature
+pub(crate) fn sign<C>(
+    secret: &[u8; 32],
+    id: u8,
+    data: &[u8],
+    out: &mut [u8],
+) -> Result<(BitFlags<ECCInfo>, usize), Error>
+where
+    C: CurveArithmetic,
+    C::AffinePoint: AffineCoordinates<FieldRepr = FieldBytes<C>>,
+{
+    let mut n = [0; 32];
+    n.copy_from_slice(&C::ORDER.to_be_byte_array());
+
+    let d = scalar::<C>(secret).ok_or(INVALID_PARAMETER)?;
+    let mut z = bits2int(data, 256);
+    reduce(&mut z, &n);
+    let z = scalar::<C>(&z).unwrap_or(C::Scalar::ZERO);
+
+    let mut nonces = Rfc6979::new(id, secret, data, &n, 256)?;
+    loop {
+        let k = match scalar::<C>(&nonces.next()) {
+            Some(k) => k,
+            None => continue,
+        };
+
+        let point: C::AffinePoint = (C::ProjectivePoint::generator() * k).into();
+        let x = point.x();
+        let r = <C::Scalar as Reduce<C::Uint>>::reduce_bytes(&x);
+        let s = k.invert().unwrap() * (z + r * d);
+        if bool::from(r.is_zero() | s.is_zero()) {
+            continue;
+        }
+
+        let mut info = BitFlags::empty();
+        if bool::from(point.y_is_odd()) {
+            info |= ECCInfo::ParityOdd;
+        }
+        if r.to_repr() != x {
+            info |= ECCInfo::XGTn;
+        }
+
+        let mut rs = [[0; 32]; 2];
+        rs[0].copy_from_slice(&r.to_repr());
+        rs[1].copy_from_slice(&s.to_repr());
+        let len = encode_der(&rs[0], &rs[1], out)?;
+
+        break Ok((info, len));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::{bip32::BIP32Path, ecfp256::SecretKey, Curve, Mode},
+        hash::{Hasher, Keccak},
+    };
+    use hex::FromHex;
+
+    fn der(r: &str, s: &str) -> ([u8; SIGNATURE_MAX_LEN], usize) {
+        let mut out = [0; SIGNATURE_MAX_LEN];
+        let len = encode_der(
+            &<[u8; 32]>::from_hex(r).unwrap(),
+            &<[u8; 32]>::from_hex(s).unwrap(),
+            &mut out,
+        )
+        .unwrap();
+        (out, len)
+    }
+
+    #[test]
+    fn rfc6979_p256() {
+        //RFC6979 A.2.5, message "sample"
+        let secret = <[u8; 32]>::from_hex(
+            "c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721",
+        )
+        .unwrap();
+        let mut sig = [0; SIGNATURE_MAX_LEN];
+
+        let digest = hash::Sha256::digest(b"sample").unwrap();
+        let (_, len) =
+            sign::<p256::NistP256>(&secret, hash::Sha256::id(), &digest, &mut sig).unwrap();
+        let (expected, expected_len) = der(
+            "efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716",
+            "f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8",
+        );
+        assert_eq!(&sig[..len], &expected[..expected_len]);
+
+        //the digest is truncated, and the nonce uses HMAC-SHA512
+        let digest = hash::Sha512::digest(b"sample").unwrap();
+        let (_, len) =
+            sign::<p256::NistP256>(&secret, hash::Sha512::id(), &digest, &mut sig).unwrap();
+        let (expected, expected_len) = der(
+            "8496a60b5e9b47c825488827e0495b0e3fa109ec4568fd3f8d1097678eb97f00",
+            "2362ab1adbe2b8adf9cb9edab740ea6049c028114f2460f96554f61fae3302fe",
+        );
+        assert_eq!(&sig[..len], &expected[..expected_len]);
+
+        assert!(sign::<p256::NistP256>(&secret, Keccak::<32>::id(), &digest, &mut sig).is_err());
+    }
+
+    #[test]
+    fn secp256k1_recovery() {
+        use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+
+        let path = BIP32Path::<5>::new([0x8000_002c, 0x8000_003c, 0x8000_0000, 0, 0]).unwrap();
+        let secret = SecretKey::new(Mode::BIP32, Curve::Secp256K1, path);
+        let public = secret.public().unwrap();
+
+        let mut odd = false;
+        for message in [&b"hello"[..], b"world", b"ledger", b"zondax"] {
+            let digest = hash::Sha256::digest(message).unwrap();
+
+            let mut sig = [0; SIGNATURE_MAX_LEN];
+            let (info, len) = secret.sign::<hash::Sha256>(&digest, &mut sig).unwrap();
+            let mut again = [0; SIGNATURE_MAX_LEN];
+            assert_eq!(
+                secret.sign::<hash::Sha256>(&digest, &mut again).unwrap().1,
+                len
+            );
+            assert_eq!(sig, again);
+
+            //the flags recover the signing key, once S is normalised
+            let signature = Signature::from_der(&sig[..len]).unwrap();
+            let (signature, flipped) = match signature.normalize_s() {
+                Some(normalized) => (normalized, true),
+                None => (signature, false),
+            };
+            let recid = RecoveryId::new(
+                info.contains(ECCInfo::ParityOdd) ^ flipped,
+                info.contains(ECCInfo::XGTn),
+            );
+            let recovered = VerifyingKey::recover_from_prehash(&digest, &signature, recid).unwrap();
+            assert_eq!(
+                recovered.to_encoded_point(false).as_bytes(),
+                public.as_ref()
+            );
+
+            odd |= info.contains(ECCInfo::ParityOdd);
+        }
+        assert!(odd);
+
+        //S is not normalised
+        let digest = hash::Sha256::digest(b"hello").unwrap();
+        let mut sig = [0; SIGNATURE_MAX_LEN];
+        let (_, len) = secret.sign::<hash::Sha256>(&digest, &mut sig).unwrap();
+        let (expected, expected_len) = der(
+            "b95bc54012fd9328047209dd75b3eff18755b831179fe783e0f7d618e497f33b",
+            "d050e922c19b49aa00d29620bc35ba681e34646c2ade0dad197013c526195bc9",
+        );
+        assert_eq!(&sig[..len], &expected[..expected_len]);
+    }
+}
@@ -15,8 +15,9 @@
 ********************************************************************************/
 use bolos_common::hash::HasherId;
 use core::mem::MaybeUninit;
+use zeroize::Zeroize;
 
-use crate::Error;
+use crate::{errors::SyscallError, Error};
 
 use super::{bip32::BIP32Path, Curve, Mode, CHAIN_CODE_LEN};
 
@@ -28,11 +29,14 @@ pub use enumflags2::BitFlags;
 #[cfg_attr(test, derive(Debug))]
 pub enum ECCInfo {
     /// Tells wheter the Y component as even (0) or odd (1)
-    ParityOdd,
+    ParityOdd = 1 << 0,
     /// Tells wheter the X component was greater than the curve order
-    XGTn,
+    XGTn = 1 << 1,
 }
 
+//CX_INVALID_PARAMETER_VALUE
+const INVALID_PARAMETER: Error = SyscallError::Code(0xFF87);
+
 #[derive(Clone, Copy)]
 pub struct PublicKey {
     curve: Curve,
@@ -41,6 +45,91 @@ pub struct PublicKey {
 }
 
 impl PublicKey {
+    /// Create a public key from the given uncompressed point
+    ///
+    /// Only secp256k1 and secp256r1 points are supported
+    pub fn from_bytes(curve: Curve, bytes: &[u8]) -> Result<Self, Error> {
+        use k256::elliptic_curve::sec1::FromEncodedPoint;
+
+        let valid = match curve {
+            Curve::Secp256K1 => k256::EncodedPoint::from_bytes(bytes)
+                .ok()
+                .filter(|p| !p.is_compressed())
+                .is_some_and(|p| k256::AffinePoint::from_encoded_point(&p).is_some().into()),
+            Curve::Secp256R1 => p256::EncodedPoint::from_bytes(bytes)
+                .ok()
+                .filter(|p| !p.is_compressed())
+                .is_some_and(|p| p256::AffinePoint::from_encoded_point(&p).is_some().into()),
+            _ => false,
+        };
+        if !valid || bytes.len() != 65 {
+            return Err(INVALID_PARAMETER);
+        }
+
+        let mut data = [0; 65];
+        data.copy_from_slice(bytes);
+        Ok(Self {
+            curve,
+            len: 65,
+            data,
+        })
+    }
+
+    /// Replace the public key `P` with `P + tweak * G`, in uncompressed form
+    ///
+    /// Only secp256k1 and secp256r1 keys are supported, and `tweak` must be
+    /// a valid scalar for the curve
+    pub fn tweak_add(&mut self, tweak: &[u8; 32]) -> Result<(), Error> {
+        use k256::elliptic_curve::{
+            group::Group,
+            sec1::{FromEncodedPoint, ToEncodedPoint},
+            PrimeField,
+        };
+
+        let mut data = [0; 65];
+        match self.curve {
+            Curve::Secp256K1 => {
+                let point =
+                    k256::EncodedPoint::from_bytes(self.as_ref()).map_err(|_| INVALID_PARAMETER)?;
+                let point = Option::<k256::AffinePoint>::from(
+                    k256::AffinePoint::from_encoded_point(&point),
+                )
+                .ok_or(INVALID_PARAMETER)?;
+                let tweak = Option::<k256::Scalar>::from(k256::Scalar::from_repr((*tweak).into()))
+                    .ok_or(INVALID_PARAMETER)?;
+
+                let result =
+                    k256::ProjectivePoint::from(point) + k256::ProjectivePoint::GENERATOR * tweak;
+                if bool::from(result.is_identity()) {
+                    return Err(INVALID_PARAMETER);
+                }
+                data.copy_from_slice(result.to_affine().to_encoded_point(false).as_bytes());
+            }
+            Curve::Secp256R1 => {
+                let point =
+                    p256::EncodedPoint::from_bytes(self.as_ref()).map_err(|_| INVALID_PARAMETER)?;
+                let point = Option::<p256::AffinePoint>::from(
+                    p256::AffinePoint::from_encoded_point(&point),
+                )
+                .ok_or(INVALID_PARAMETER)?;
+                let tweak = Option::<p256::Scalar>::from(p256::Scalar::from_repr((*tweak).into()))
+                    .ok_or(INVALID_PARAMETER)?;
+
+                let result =
+                    p256::ProjectivePoint::from(point) + p256::ProjectivePoint::GENERATOR * tweak;
+                if bool::from(result.is_identity()) {
+                    return Err(INVALID_PARAMETER);
+                }
+                data.copy_from_slice(result.to_affine().to_encoded_point(false).as_bytes());
+            }
+            _ => return Err(INVALID_PARAMETER),
+        }
+
+        self.data = data;
+        self.len = 65;
+        Ok(())
+    }
+
     pub fn compress(&mut self) -> Result<(), Error> {
         match self.curve {
             Curve::Secp256K1 => {
@@ -82,65 +171,61 @@ impl AsRef<[u8]> for PublicKey {
     }
 }
 
-pub struct SecretKey<const B: usize> {
-    curve: Curve,
-    bytes: [u8; 32],
+/// Output of [`SecretKey::ecdh`]
+#[derive(Clone, Copy)]
+pub enum ECDHMode {
+    /// The full shared point, uncompressed
+    Point,
+    /// Only the x coordinate of the shared point
+    X,
 }
 
-impl<const B: usize> SecretKey<B> {
-    fn rng8(path: BIP32Path<B>) -> rand_chacha8::ChaCha8Rng {
-        use rand_chacha8::rand_core::SeedableRng;
-
-        let mut seed = [0; 32 / 4];
-        for (i, c) in path.components().iter().enumerate().take(32 / 4) {
-            seed[i] = *c;
-        }
-
-        let seed: [u8; 32] = unsafe { std::mem::transmute(seed) };
+pub struct SharedSecret {
+    len: usize,
+    data: [u8; 65],
+}
 
-        rand_chacha8::ChaCha8Rng::from_seed(seed)
+impl SharedSecret {
+    pub fn len(&self) -> usize {
+        self.len
     }
 
-    fn rng7(path: BIP32Path<B>) -> rand_chacha7::ChaCha8Rng {
-        use rand_chacha7::rand_core::SeedableRng;
-
-        let mut seed = [0; 32 / 4];
-        for (i, c) in path.components().iter().enumerate().take(32 / 4) {
-            seed[i] = *c;
-        }
-
-        let seed: [u8; 32] = unsafe { std::mem::transmute(seed) };
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
 
-        rand_chacha7::ChaCha8Rng::from_seed(seed)
+impl AsRef<[u8]> for SharedSecret {
+    fn as_ref(&self) -> &[u8] {
+        &self.data[..self.len]
     }
+}
 
-    pub fn new(_: Mode, curve: Curve, path: BIP32Path<B>) -> Self {
-        let bytes = match curve {
-            Curve::Secp256K1 => {
-                let secret = k256::ecdsa::SigningKey::random(&mut Self::rng8(path));
+impl Drop for SharedSecret {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
 
-                *secret.to_bytes().as_ref()
-            }
-            Curve::Secp256R1 => {
-                let secret = p256::ecdsa::SigningKey::random(&mut Self::rng8(path));
+pub struct SecretKey<const B: usize> {
+    curve: Curve,
+    bytes: [u8; 32],
+    chain_code: [u8; CHAIN_CODE_LEN],
+}
 
-                *secret.to_bytes().as_ref()
-            }
-            Curve::Ed25519 => {
-                use rand_chacha7::rand_core::RngCore;
-                let mut rng = Self::rng7(path);
-                let mut bytes = [0u8; 32];
-                rng.fill_bytes(&mut bytes);
-                let secret = ed25519_dalek::SigningKey::from_bytes(&bytes);
+impl<const B: usize> SecretKey<B> {
+    pub fn new(mode: Mode, curve: Curve, path: BIP32Path<B>) -> Self {
+        if let Curve::Stark256 = curve {
+            panic!("invalid curve passed to ecfp256 new")
+        }
 
-                secret.to_bytes()
-            }
-            Curve::Stark256 => {
-                panic!("invalid curve passed to ecfp256 new")
-            }
-        };
+        let node = super::hdw::derive(mode, curve, path.components());
 
-        Self { curve, bytes }
+        Self {
+            curve,
+            bytes: node.key,
+            chain_code: node.chain_code,
+        }
     }
 
     pub const fn curve(&self) -> Curve {
@@ -182,6 +267,14 @@ impl<const B: usize> SecretKey<B> {
                 bytes[..32].copy_from_slice(public.as_bytes());
                 (bytes, 32)
             }
+            Curve::Curve25519 => {
+                let public = curve25519_dalek::MontgomeryPoint::mul_base_clamped(self.bytes);
+                //the device encodes montgomery points as 02 || u
+                let mut bytes = [0; 65];
+                bytes[0] = 0x02;
+                bytes[1..33].copy_from_slice(public.as_bytes());
+                (bytes, 33)
+            }
             _ => unreachable!(),
         };
 
@@ -194,45 +287,122 @@ impl<const B: usize> SecretKey<B> {
 
     pub fn public_into(
         &self,
-        _: Option<&mut [u8; CHAIN_CODE_LEN]>,
+        chaincode: Option<&mut [u8; CHAIN_CODE_LEN]>,
         out: &mut MaybeUninit<PublicKey>,
     ) -> Result<(), Error> {
         let pk = self.public()?;
 
+        if let Some(chaincode) = chaincode {
+            chaincode.copy_from_slice(&self.chain_code);
+        }
+
         *out = MaybeUninit::new(pk);
 
         Ok(())
     }
 
-    pub fn sign<H>(&self, data: &[u8], out: &mut [u8]) -> Result<(BitFlags<ECCInfo>, usize), Error>
-    where
-        H: HasherId,
-        H::Id: Into<u8>,
-    {
-        match self.curve {
-            Curve::Secp256K1 => {
-                use k256::ecdsa::{signature::Signer, Signature};
+    pub fn ecdh(&self, peer: &PublicKey, mode: ECDHMode) -> Result<SharedSecret, Error> {
+        let mut out = MaybeUninit::uninit();
 
-                let secret = k256::ecdsa::SigningKey::from_bytes((&self.bytes[..]).into()).unwrap();
+        self.ecdh_into(peer, mode, &mut out)?;
 
-                let sig: Signature = secret.sign(data);
-                let sig = sig.to_der();
-                let sig = sig.as_ref();
+        //this is safe as the call above initialized it
+        Ok(unsafe { out.assume_init() })
+    }
 
-                out[..sig.len()].copy_from_slice(sig);
-                Ok((Default::default(), sig.len()))
-            }
-            Curve::Secp256R1 => {
-                use p256::ecdsa::{signature::Signer, Signature};
+    /// Compute the shared secret with the `peer` public key, of the same curve
+    ///
+    /// On secp256k1 and secp256r1 the peer key must be uncompressed,
+    /// while Curve25519 keys are `02 || u`, as returned by [`SecretKey::public`],
+    /// and only support [`ECDHMode::X`], as X25519 does
+    pub fn ecdh_into(
+        &self,
+        peer: &PublicKey,
+        mode: ECDHMode,
+        out: &mut MaybeUninit<SharedSecret>,
+    ) -> Result<(), Error> {
+        use core::convert::TryInto;
+        use k256::elliptic_curve::sec1::ToEncodedPoint;
 
-                let secret = p256::ecdsa::SigningKey::from_bytes((&self.bytes[..]).into()).unwrap();
-                let sig: Signature = secret.sign(data);
-                let sig = sig.to_der();
-                let sig = sig.as_ref();
+        //the device only accepts uncompressed points
+        if self.curve.is_weirstrass() && peer.len() != 65 {
+            return Err(INVALID_PARAMETER);
+        }
+
+        let mut shared = [0; 65];
+        match (self.curve, peer.curve) {
+            (Curve::Secp256K1, Curve::Secp256K1) => {
+                let point = k256::PublicKey::from_sec1_bytes(peer.as_ref())
+                    .map_err(|_| INVALID_PARAMETER)?;
+                let secret =
+                    k256::SecretKey::from_slice(&self.bytes).map_err(|_| INVALID_PARAMETER)?;
+
+                let point = point.to_projective() * *secret.to_nonzero_scalar();
+                shared.copy_from_slice(point.to_affine().to_encoded_point(false).as_bytes());
+            }
+            (Curve::Secp256R1, Curve::Secp256R1) => {
+                let point = p256::PublicKey::from_sec1_bytes(peer.as_ref())
+                    .map_err(|_| INVALID_PARAMETER)?;
+                let secret =
+                    p256::SecretKey::from_slice(&self.bytes).map_err(|_| INVALID_PARAMETER)?;
+
+                let point = point.to_projective() * *secret.to_nonzero_scalar();
+                shared.copy_from_slice(point.to_affine().to_encoded_point(false).as_bytes());
+            }
+            (Curve::Curve25519, Curve::Curve25519) => {
+                if let ECDHMode::Point = mode {
+                    return Err(INVALID_PARAMETER);
+                }
+
+                let peer: [u8; 32] = match peer.as_ref() {
+                    [0x02, u @ ..] => u.try_into().map_err(|_| INVALID_PARAMETER)?,
+                    _ => return Err(INVALID_PARAMETER),
+                };
+                let point = curve25519_dalek::MontgomeryPoint(peer).mul_clamped(self.bytes);
+
+                //reject low order points
+                if point.as_bytes().iter().all(|b| *b == 0) {
+                    return Err(INVALID_PARAMETER);
+                }
+
+                let mut data = [0; 65];
+                data[..32].copy_from_slice(point.as_bytes());
+                *out = MaybeUninit::new(SharedSecret { len: 32, data });
+                return Ok(());
+            }
+            _ => return Err(INVALID_PARAMETER),
+        }
 
-                out[..sig.len()].copy_from_slice(sig);
-                Ok((Default::default(), sig.len()))
+        let mut data = [0; 65];
+        let len = match mode {
+            ECDHMode::Point => {
+                data.copy_from_slice(&shared);
+                65
+            }
+            ECDHMode::X => {
+                data[..32].copy_from_slice(&shared[1..33]);
+                32
             }
+        };
+
+        *out = MaybeUninit::new(SharedSecret { len, data });
+        Ok(())
+    }
+
+    /// Sign `data` like the device does
+    ///
+    /// For ECDSA `data` is the digest and `H` selects the HMAC of the RFC6979 nonce,
+    /// while Ed25519 signs the message itself
+    pub fn sign<H>(&self, data: &[u8], out: &mut [u8]) -> Result<(BitFlags<ECCInfo>, usize), Error>
+    where
+        H: HasherId,
+        H::Id: Into<u8>,
+    {
+        let id: u8 = H::id().into();
+
+        match self.curve {
+            Curve::Secp256K1 => super::ecdsa::sign::<k256::Secp256k1>(&self.bytes, id, data, out),
+            Curve::Secp256R1 => super::ecdsa::sign::<p256::NistP256>(&self.bytes, id, data, out),
             Curve::Ed25519 => {
                 use ed25519_dalek::Signer;
                 let secret = ed25519_dalek::SigningKey::from_bytes(&self.bytes);
@@ -240,7 +410,100 @@ impl<const B: usize> SecretKey<B> {
                 out[..64].copy_from_slice(&sig.to_bytes()[..]);
                 Ok((Default::default(), 64))
             }
-            _ => unreachable!(),
+            _ => Err(INVALID_PARAMETER),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::FromHex;
+
+    const H: u32 = 0x8000_0000;
+
+    fn derive(curve: Curve, account: u32) -> SecretKey<3> {
+        let path = BIP32Path::new([44 | H, 60 | H, account | H]).unwrap();
+        SecretKey::new(Mode::BIP32, curve, path)
+    }
+
+    #[test]
+    fn x25519() {
+        //RFC7748 section 6.1
+        let curve = Curve::Curve25519;
+        let alice = SecretKey::<1> {
+            curve,
+            bytes: <[u8; 32]>::from_hex(
+                "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a",
+            )
+            .unwrap(),
+            chain_code: [0; CHAIN_CODE_LEN],
+        };
+        let bob = SecretKey::<1> {
+            curve,
+            bytes: <[u8; 32]>::from_hex(
+                "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb",
+            )
+            .unwrap(),
+            chain_code: [0; CHAIN_CODE_LEN],
+        };
+
+        let alice_pk = alice.public().unwrap();
+        let bob_pk = bob.public().unwrap();
+        //encoded like the device does, as 02 || u
+        assert_eq!(
+            hex::encode(alice_pk.as_ref()),
+            "028520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"
+        );
+
+        let shared = "4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742";
+        assert_eq!(
+            hex::encode(alice.ecdh(&bob_pk, ECDHMode::X).unwrap().as_ref()),
+            shared
+        );
+        assert_eq!(
+            hex::encode(bob.ecdh(&alice_pk, ECDHMode::X).unwrap().as_ref()),
+            shared
+        );
+
+        assert!(alice.ecdh(&bob_pk, ECDHMode::Point).is_err());
+
+        //the bare u coordinate is not the device encoding
+        let mut bare = bob_pk;
+        bare.data.copy_within(1..33, 0);
+        bare.len = 32;
+        assert!(alice.ecdh(&bare, ECDHMode::X).is_err());
+    }
+
+    #[test]
+    fn weierstrass_agreement() {
+        for curve in [Curve::Secp256K1, Curve::Secp256R1] {
+            let (a, b) = (derive(curve, 0), derive(curve, 1));
+            let (a_pk, b_pk) = (a.public().unwrap(), b.public().unwrap());
+
+            let x = a.ecdh(&b_pk, ECDHMode::X).unwrap();
+            assert_eq!(x.len(), 32);
+            assert_eq!(x.as_ref(), b.ecdh(&a_pk, ECDHMode::X).unwrap().as_ref());
+
+            let point = a.ecdh(&b_pk, ECDHMode::Point).unwrap();
+            assert_eq!(point.len(), 65);
+            assert_eq!(point.as_ref()[0], 0x04);
+            assert_eq!(&point.as_ref()[1..33], x.as_ref());
+        }
+    }
+
+    #[test]
+    fn ecdh_rejects_invalid_peer() {
+        let k1 = derive(Curve::Secp256K1, 0);
+        let r1 = derive(Curve::Secp256R1, 0);
+
+        //curve mismatch
+        let r1_pk = r1.public().unwrap();
+        assert!(k1.ecdh(&r1_pk, ECDHMode::X).is_err());
+
+        //compressed peer
+        let mut compressed = k1.public().unwrap();
+        compressed.compress().unwrap();
+        assert!(k1.ecdh(&compressed, ECDHMode::X).is_err());
+    }
+}
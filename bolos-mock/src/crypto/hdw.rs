@@ -0,0 +1,408 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Hierarchical deterministic derivation of the mock keys
+//!
+//! Keys are derived from a BIP39 seed like `os_perso_derive_node_with_seed_key` does:
+//! * BIP32 for secp256k1, and its SLIP-10 variant for secp256r1
+//! * SLIP-10 for Ed25519 with [`Mode::Ed25519Slip10`], and for Curve25519
+//! * BIP32-Ed25519 for Ed25519 with [`Mode::BIP32`]
+//!
+//! The seed defaults to the one of [`DEFAULT_MNEMONIC`], the mnemonic used by Speculos,
+//! and is kept per thread so each test can set its own with [`set_mnemonic`] or [`set_seed`]
+
+use std::{cell::RefCell, thread_local, vec::Vec};
+
+use hmac::{Hmac, Mac, NewMac};
+use k256::elliptic_curve::{
+    group::Group,
+    sec1::{ModulusSize, ToEncodedPoint},
+    CurveArithmetic, Field, FieldBytes, PrimeField,
+};
+use sha2::{Sha256, Sha512};
+
+use super::{Curve, Mode, CHAIN_CODE_LEN};
+
+/// Mnemonic used by default, the same as Speculos
+pub const DEFAULT_MNEMONIC: &str = "glory promote mansion idle axis finger extra february uncover one trip resource lawn turtle enact monster seven myth punch hobby comfort wild raise skin";
+
+const HARDENED: u32 = 0x8000_0000;
+
+const BIP32_SECP256K1_KEY: &[u8] = b"Bitcoin seed";
+const SLIP10_SECP256R1_KEY: &[u8] = b"Nist256p1 seed";
+const SLIP10_ED25519_KEY: &[u8] = b"ed25519 seed";
+const SLIP10_CURVE25519_KEY: &[u8] = b"curve25519 seed";
+
+thread_local! {
+    static SEED: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+/// Compute the BIP39 seed of the given mnemonic and passphrase
+///
+/// The mnemonic is not validated against the wordlist, and is expected to be already normalized
+pub fn mnemonic_to_seed(mnemonic: &str, passphrase: &str) -> [u8; 64] {
+    //PBKDF2-HMAC-SHA512 with 2048 iterations, and a single output block
+    let prf = Hmac::<Sha512>::new_from_slice(mnemonic.as_bytes()).unwrap();
+
+    let mut mac = prf.clone();
+    mac.update(b"mnemonic");
+    mac.update(passphrase.as_bytes());
+    mac.update(&1u32.to_be_bytes());
+    let mut u = mac.finalize().into_bytes();
+
+    let mut seed = [0; 64];
+    seed.copy_from_slice(&u);
+    for _ in 1..2048 {
+        let mut mac = prf.clone();
+        mac.update(&u);
+        u = mac.finalize().into_bytes();
+
+        seed.iter_mut().zip(u.iter()).for_each(|(s, u)| *s ^= u);
+    }
+
+    seed
+}
+
+/// Derive the keys of the current thread from the given mnemonic, without passphrase
+pub fn set_mnemonic(mnemonic: &str) {
+    set_seed(&mnemonic_to_seed(mnemonic, ""));
+}
+
+/// Derive the keys of the current thread from the given seed
+pub fn set_seed(seed: &[u8]) {
+    SEED.with(|s| *s.borrow_mut() = Some(seed.to_vec()));
+}
+
+/// Go back to deriving the keys of the current thread from [`DEFAULT_MNEMONIC`]
+pub fn reset_seed() {
+    SEED.with(|s| *s.borrow_mut() = None);
+}
+
+fn with_seed<R>(f: impl FnOnce(&[u8]) -> R) -> R {
+    SEED.with(|s| {
+        let mut s = s.borrow_mut();
+        let seed = s.get_or_insert_with(|| mnemonic_to_seed(DEFAULT_MNEMONIC, "").to_vec());
+
+        f(seed)
+    })
+}
+
+/// A derived private key with its chain code
+pub(crate) struct Node {
+    pub key: [u8; 32],
+    pub chain_code: [u8; CHAIN_CODE_LEN],
+}
+
+/// Derive the node at `path` for the given mode and curve
+///
+/// Panics if the curve doesn't support derivation
+pub(crate) fn derive(mode: Mode, curve: Curve, path: &[u32]) -> Node {
+    with_seed(|seed| match (mode, curve) {
+        (_, Curve::Secp256K1) => bip32::<k256::Secp256k1>(BIP32_SECP256K1_KEY, seed, path),
+        (_, Curve::Secp256R1) => bip32::<p256::NistP256>(SLIP10_SECP256R1_KEY, seed, path),
+        (Mode::Ed25519Slip10, Curve::Ed25519) => slip10_hardened(SLIP10_ED25519_KEY, seed, path),
+        (_, Curve::Curve25519) => slip10_hardened(SLIP10_CURVE25519_KEY, seed, path),
+        (Mode::BIP32, Curve::Ed25519) => bip32_ed25519(seed, path),
+        (_, Curve::Stark256) => panic!("stark keys are derived by crypto::stark"),
+    })
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).unwrap();
+    data.iter().for_each(|d| mac.update(d));
+
+    let mut out = [0; 64];
+    out.copy_from_slice(&mac.finalize().into_bytes());
+    out
+}
+
+fn split(i: &[u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let mut left = [0; 32];
+    let mut right = [0; 32];
+    left.copy_from_slice(&i[..32]);
+    right.copy_from_slice(&i[32..]);
+
+    (left, right)
+}
+
+/// Parse a big endian scalar, if below the curve order
+fn scalar<C: CurveArithmetic>(bytes: &[u8]) -> Option<C::Scalar> {
+    let mut repr = FieldBytes::<C>::default();
+    repr.copy_from_slice(bytes);
+
+    C::Scalar::from_repr(repr).into()
+}
+
+/// BIP32 derivation for weierstrass curves, with the SLIP-10 retry
+/// when a derived key is invalid (which is never the case on secp256k1)
+fn bip32<C>(curve_key: &[u8], seed: &[u8], path: &[u32]) -> Node
+where
+    C: CurveArithmetic,
+    C::AffinePoint: ToEncodedPoint<C>,
+    C::FieldBytesSize: ModulusSize,
+{
+    let mut i = hmac_sha512(curve_key, &[seed]);
+    let mut key = loop {
+        match scalar::<C>(&i[..32]) {
+            Some(key) if !bool::from(key.is_zero()) => break key,
+            _ => i = hmac_sha512(curve_key, &[&i]),
+        }
+    };
+    let mut chain_code = split(&i).1;
+
+    for &index in path {
+        let mut data = [0; 33];
+        if index & HARDENED != 0 {
+            data[1..].copy_from_slice(&key.to_repr());
+        } else {
+            let public: C::AffinePoint = (C::ProjectivePoint::generator() * key).into();
+            data.copy_from_slice(public.to_encoded_point(true).as_bytes());
+        }
+
+        loop {
+            let i = hmac_sha512(&chain_code, &[&data, &index.to_be_bytes()]);
+            let child = scalar::<C>(&i[..32])
+                .map(|tweak| tweak + key)
+                .filter(|child| !bool::from(child.is_zero()));
+
+            match child {
+                Some(child) => {
+                    key = child;
+                    chain_code = split(&i).1;
+                    break;
+                }
+                None => {
+                    data[0] = 1;
+                    data[1..].copy_from_slice(&i[32..]);
+                }
+            }
+        }
+    }
+
+    let mut out = [0; 32];
+    out.copy_from_slice(&key.to_repr());
+    Node {
+        key: out,
+        chain_code,
+    }
+}
+
+/// SLIP-10 derivation for Ed25519 and Curve25519, where every index is hardened
+fn slip10_hardened(curve_key: &[u8], seed: &[u8], path: &[u32]) -> Node {
+    let (mut key, mut chain_code) = split(&hmac_sha512(curve_key, &[seed]));
+
+    for &index in path {
+        let index = index | HARDENED;
+        (key, chain_code) = split(&hmac_sha512(
+            &chain_code,
+            &[&[0], &key, &index.to_be_bytes()],
+        ));
+    }
+
+    Node { key, chain_code }
+}
+
+/// BIP32-Ed25519 derivation, as specified by Khovratovich and Law
+/// with the root key generation used by the device
+///
+/// The resulting key is the left half of the extended key
+fn bip32_ed25519(seed: &[u8], path: &[u32]) -> Node {
+    let mut chain_code = [0; CHAIN_CODE_LEN];
+    let mut mac = Hmac::<Sha256>::new_from_slice(SLIP10_ED25519_KEY).unwrap();
+    mac.update(&[1]);
+    mac.update(seed);
+    chain_code.copy_from_slice(&mac.finalize().into_bytes());
+
+    //retry until the third highest bit is clear
+    let mut i = hmac_sha512(SLIP10_ED25519_KEY, &[seed]);
+    while i[31] & 0x20 != 0 {
+        i = hmac_sha512(SLIP10_ED25519_KEY, &[&i]);
+    }
+    let (mut kl, mut kr) = split(&i);
+    kl[0] &= 0xF8;
+    kl[31] &= 0x7F;
+    kl[31] |= 0x40;
+
+    for &index in path {
+        let index = index.to_le_bytes();
+        let (z, c) = if u32::from_le_bytes(index) & HARDENED != 0 {
+            (
+                hmac_sha512(&chain_code, &[&[0], &kl, &kr, &index]),
+                hmac_sha512(&chain_code, &[&[1], &kl, &kr, &index]),
+            )
+        } else {
+            let scalar = curve25519_dalek::Scalar::from_bytes_mod_order(kl);
+            let public = curve25519_dalek::EdwardsPoint::mul_base(&scalar).compress();
+            (
+                hmac_sha512(&chain_code, &[&[2], public.as_bytes(), &index]),
+                hmac_sha512(&chain_code, &[&[3], public.as_bytes(), &index]),
+            )
+        };
+
+        //kl += 8 * z[..28] and kr += z[32..], little endian modulo 2^256
+        let mut carry = 0u16;
+        for (n, k) in kl.iter_mut().enumerate() {
+            let z = z.get(n).filter(|_| n < 28).map_or(0, |z| (*z as u16) << 3);
+            let sum = *k as u16 + z + carry;
+            *k = sum as u8;
+            carry = sum >> 8;
+        }
+
+        let mut carry = 0u16;
+        for (k, z) in kr.iter_mut().zip(&z[32..]) {
+            let sum = *k as u16 + *z as u16 + carry;
+            *k = sum as u8;
+            carry = sum >> 8;
+        }
+
+        chain_code.copy_from_slice(&c[32..]);
+    }
+
+    Node {
+        key: kl,
+        chain_code,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{bip32::BIP32Path, ecfp256::SecretKey};
+    use core::mem::MaybeUninit;
+
+    const H: u32 = HARDENED;
+
+    /// Check the private key of `derive` and the chain code returned by `public_into`
+    fn check(mode: Mode, curve: Curve, path: &[u32], key: &str, chain_code: &str) {
+        assert_eq!(hex::encode(derive(mode, curve, path).key), key);
+
+        let secret =
+            SecretKey::<6>::new(mode, curve, BIP32Path::new(path.iter().copied()).unwrap());
+        let mut cc = [0; CHAIN_CODE_LEN];
+        let mut public = MaybeUninit::uninit();
+        secret.public_into(Some(&mut cc), &mut public).unwrap();
+        assert_eq!(hex::encode(cc), chain_code);
+    }
+
+    #[test]
+    fn bip32_secp256k1() {
+        //BIP32 test vector 1
+        set_seed(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+
+        check(
+            Mode::BIP32,
+            Curve::Secp256K1,
+            &[H],
+            "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+            "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141",
+        );
+        check(
+            Mode::BIP32,
+            Curve::Secp256K1,
+            &[H, 1],
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+            "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19",
+        );
+        check(
+            Mode::BIP32,
+            Curve::Secp256K1,
+            &[H, 1, 2 | H, 2, 1000000000],
+            "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8",
+            "c783e67b921d2beb8f6b389cc646d7263b4145701dadd2161548a8b078e65e9e",
+        );
+    }
+
+    #[test]
+    fn slip10_secp256r1() {
+        //SLIP-10 test vector 1 for nist256p1
+        set_seed(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+
+        check(
+            Mode::BIP32,
+            Curve::Secp256R1,
+            &[H],
+            "6939694369114c67917a182c59ddb8cafc3004e63ca5d3b84403ba8613debc0c",
+            "3460cea53e6a6bb5fb391eeef3237ffd8724bf0a40e94943c98b83825342ee11",
+        );
+        check(
+            Mode::BIP32,
+            Curve::Secp256R1,
+            &[H, 1],
+            "284e9d38d07d21e4e281b645089a94f4cf5a5a81369acf151a1c3a57f18b2129",
+            "4187afff1aafa8445010097fb99d23aee9f599450c7bd140b6826ac22ba21d0c",
+        );
+        check(
+            Mode::BIP32,
+            Curve::Secp256R1,
+            &[H, 1, 2 | H, 2, 1000000000],
+            "21c4f269ef0a5fd1badf47eeacebeeaa3de22eb8e5b0adcd0f27dd99d34d0119",
+            "b9b7b82d326bb9cb5b5b121066feea4eb93d5241103c9e7a18aad40f1dde8059",
+        );
+
+        //derivation retry
+        check(
+            Mode::BIP32,
+            Curve::Secp256R1,
+            &[28578 | H, 33941],
+            "092154eed4af83e078ff9b84322015aefe5769e31270f62c3f66c33888335f3a",
+            "9e87fe95031f14736774cd82f25fd885065cb7c358c1edf813c72af535e83071",
+        );
+    }
+
+    #[test]
+    fn slip10_ed25519() {
+        //SLIP-10 test vector 1 for ed25519
+        set_seed(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+
+        check(
+            Mode::Ed25519Slip10,
+            Curve::Ed25519,
+            &[H],
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+            "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69",
+        );
+        check(
+            Mode::Ed25519Slip10,
+            Curve::Ed25519,
+            &[H, 1 | H, 2 | H, 2 | H, 1000000000 | H],
+            "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793",
+            "68789923a0cac2cd5a29172a475fe9e0fb14cd6adb5ad98a3fa70333e7afa230",
+        );
+    }
+
+    #[test]
+    fn default_mnemonic() {
+        //m/44'/354'/0'/0/0 with BIP32-Ed25519
+        check(
+            Mode::BIP32,
+            Curve::Ed25519,
+            &[44 | H, 354 | H, H, 0, 0],
+            "38835d1f05e4e6715c79f3c22521bedab6a4bb13a71605bfe6f067981ad40055",
+            "b0ebceb7a3cf31cdbd98ecb4e0be3285c63cedf6eff4f4606b05d7d8543e107f",
+        );
+
+        set_mnemonic("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about");
+        //back to the default one
+        reset_seed();
+        //m/44'/60'/0'/0/0
+        check(
+            Mode::BIP32,
+            Curve::Secp256K1,
+            &[44 | H, 60 | H, H, 0, 0],
+            "ea2861b1058084974c509a4d2e21e73896059c1c69f7a5c2650661cac3493725",
+            "428489ee70680fa137392bc8399c4da9e39e92f058eb9e790f736142bba7e9d6",
+        );
+    }
+}
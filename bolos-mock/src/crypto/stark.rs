@@ -13,12 +13,104 @@
 *  See the License for the specific language governing permissions and
 *  limitations under the License.
 ********************************************************************************/
-//use bolos_common::hash::HasherId;
 use core::mem::MaybeUninit;
 
-use crate::Error;
+use crypto_bigint::{
+    modular::runtime_mod::{DynResidue, DynResidueParams},
+    Encoding, U256,
+};
+use sha2::{Digest, Sha256};
 
-use super::{bip32::BIP32Path, Curve};
+use crate::{
+    errors::SyscallError,
+    hash::{self, HasherId},
+    math, Error,
+};
+
+use super::{
+    bip32::BIP32Path,
+    ecdsa::{bits2int, encode_der, Rfc6979, SIGNATURE_MAX_LEN},
+    Curve, Mode,
+};
+
+//CX_INVALID_PARAMETER_VALUE
+const INVALID_PARAMETER: Error = SyscallError::Code(0xFF87);
+
+// C_cx_secp256k1_n - (C_cx_secp256k1_n % C_cx_Stark256_n)
+const STARK_DERIVE_BIAS: &[u8] = &[
+    0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x0e, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xf7,
+    0x38, 0xa1, 0x3b, 0x4b, 0x92, 0x0e, 0x94, 0x11, 0xae, 0x6d, 0xa5, 0xf4, 0x0b, 0x03, 0x58, 0xb1,
+];
+
+// n: 0x0800000000000010ffffffffffffffffb781126dcae7b2321e66a241adc64d2f
+const C_CX_STARK256_N: &[u8; 32] = &[
+    0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xb7, 0x81, 0x12, 0x6d, 0xca, 0xe7, 0xb2, 0x32, 0x1e, 0x66, 0xa2, 0x41, 0xad, 0xc6, 0x4d, 0x2f,
+];
+
+/// Curve order
+const N: U256 =
+    U256::from_be_hex("0800000000000010ffffffffffffffffb781126dcae7b2321e66a241adc64d2f");
+/// Field prime
+const P: U256 =
+    U256::from_be_hex("0800000000000011000000000000000000000000000000000000000000000001");
+const GX: U256 =
+    U256::from_be_hex("01ef15c18599971b7beced415a40f0c7deacfd9b0d1819e03d723d8bc943cfca");
+const GY: U256 =
+    U256::from_be_hex("005668060aa49730b7be4801df46ec62de53ecd11abe43a32873000c36e8dc1f");
+
+/// Bit length of the curve order
+const QLEN: usize = 252;
+
+type Residue = DynResidue<{ U256::LIMBS }>;
+
+/// Affine point of the curve `y^2 = x^3 + x + beta`, `None` being the identity
+type Point = Option<(Residue, Residue)>;
+
+fn generator() -> Point {
+    let params = DynResidueParams::new(&P);
+
+    Some((Residue::new(&GX, params), Residue::new(&GY, params)))
+}
+
+fn add(a: Point, b: Point) -> Point {
+    let ((x1, y1), (x2, y2)) = match (a, b) {
+        (None, p) | (p, None) => return p,
+        (Some(a), Some(b)) => (a, b),
+    };
+
+    let lambda = if x1.retrieve() == x2.retrieve() {
+        if (y1 + y2).retrieve() == U256::ZERO {
+            return None;
+        }
+
+        //tangent (3x^2 + alpha) / 2y, with alpha = 1
+        let xx = x1 * x1;
+        (xx + xx + xx + Residue::one(*x1.params())) * (y1 + y1).invert().0
+    } else {
+        (y2 - y1) * (x2 - x1).invert().0
+    };
+
+    let x3 = lambda * lambda - x1 - x2;
+    let y3 = lambda * (x1 - x3) - y1;
+    Some((x3, y3))
+}
+
+fn mul(k: &U256, p: Point) -> Point {
+    (0..U256::BITS).rev().fold(None, |acc, i| {
+        let acc = add(acc, acc);
+        if k.bit_vartime(i) {
+            add(acc, p)
+        } else {
+            acc
+        }
+    })
+}
+
+/// Reduce `v` modulo the curve order
+fn reduce(v: &U256) -> U256 {
+    Residue::new(v, DynResidueParams::new(&N)).retrieve()
+}
 
 #[derive(Clone, Copy)]
 pub struct PublicKey {
@@ -47,15 +139,32 @@ impl AsRef<[u8]> for PublicKey {
 }
 
 pub struct SecretKey<const B: usize> {
-    _bytes: [u8; 32],
+    bytes: [u8; 32],
 }
 
 impl<const B: usize> SecretKey<B> {
-    pub fn new(_: BIP32Path<B>) -> Self {
-        let _bytes = [0; 32];
+    pub const SIGNATURE_MAX_LEN: usize = SIGNATURE_MAX_LEN;
+
+    /// Derive the key of `path` like the device does, grinding the
+    /// secp256k1 key of the path into a stark key
+    pub fn new(path: BIP32Path<B>) -> Self {
+        let node = super::hdw::derive(Mode::BIP32, Curve::Secp256K1, path.components());
 
-        todo!("starkware new secret");
-        //Self { bytes: _bytes }
+        let mut tmp_secret = [0; 33];
+        tmp_secret[..32].copy_from_slice(&node.key);
+
+        let mut bytes = [0; 32];
+        for index in 0.. {
+            tmp_secret[32] = index;
+            bytes.copy_from_slice(&Sha256::digest(&tmp_secret));
+
+            if math::cmp(&bytes, STARK_DERIVE_BIAS).unwrap().is_lt() {
+                math::modm(&mut bytes, C_CX_STARK256_N).unwrap();
+                break;
+            }
+        }
+
+        Self { bytes }
     }
 
     pub const fn curve(&self) -> Curve {
@@ -63,11 +172,15 @@ impl<const B: usize> SecretKey<B> {
     }
 
     pub fn public(&self) -> Result<PublicKey, Error> {
-        let (_data, _len) = ([0; 65], 0);
+        let (x, y) =
+            mul(&U256::from_be_slice(&self.bytes), generator()).ok_or(INVALID_PARAMETER)?;
 
-        todo!("starkware to public");
+        let mut data = [0; 65];
+        data[0] = 0x04;
+        data[1..33].copy_from_slice(&x.retrieve().to_be_bytes());
+        data[33..].copy_from_slice(&y.retrieve().to_be_bytes());
 
-        //Ok(PublicKey { data: _data, len: _len })
+        Ok(PublicKey { data, len: 65 })
     }
 
     pub fn public_into(&self, out: &mut MaybeUninit<PublicKey>) -> Result<(), Error> {
@@ -78,7 +191,103 @@ impl<const B: usize> SecretKey<B> {
         Ok(())
     }
 
-    pub fn sign(&self, _data: &[u8], _out: &mut [u8]) -> Result<(bool, usize), Error> {
-        todo!("starkware sign")
+    /// Signs the given hash with the stark curve, using RFC6979 nonces with SHA256
+    ///
+    /// Returns (ECC_PARITY_ODD, sig_size)
+    pub fn sign(&self, data: &[u8], out: &mut [u8]) -> Result<(bool, usize), Error> {
+        let secret = U256::from_be_slice(&self.bytes);
+        let z = reduce(&U256::from_be_slice(&bits2int(data, QLEN)));
+
+        let params = DynResidueParams::new(&N);
+        let mut nonces =
+            Rfc6979::new(hash::Sha256::id(), &self.bytes, data, C_CX_STARK256_N, QLEN)?;
+        let (r, s, parity) = loop {
+            let k = U256::from_be_slice(&nonces.next());
+            if k == U256::ZERO || k >= N {
+                continue;
+            }
+
+            let (x, y) = match mul(&k, generator()) {
+                Some(point) => point,
+                None => continue,
+            };
+
+            //s = (z + r * secret) / k
+            let r = Residue::new(&x.retrieve(), params);
+            let s = (Residue::new(&z, params) + r * Residue::new(&secret, params))
+                * Residue::new(&k, params).invert().0;
+
+            let (r, s) = (r.retrieve(), s.retrieve());
+            if r != U256::ZERO && s != U256::ZERO {
+                break (r, s, y.retrieve().bit_vartime(0));
+            }
+        };
+
+        let len = encode_der(&r.to_be_bytes(), &s.to_be_bytes(), out)?;
+
+        Ok((parity, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const H: u32 = 0x8000_0000;
+
+    #[test]
+    fn public_key() {
+        //key pair of the StarkEx crypto test vectors
+        let secret =
+            U256::from_be_hex("03c1e9550e66958296d11b60f8e8e7a7ad990d07fa65d5f7652c4a6c87d4e3cc");
+        let secret = SecretKey::<1> {
+            bytes: secret.to_be_bytes(),
+        };
+
+        let public = secret.public().unwrap();
+        assert_eq!(
+            hex::encode(&public.as_ref()[1..33]),
+            "077a3b314db07c45076d11f62b6f9e748a39790441823307743cf00d6597ea43"
+        );
+    }
+
+    #[test]
+    fn derive_and_sign() {
+        let path = BIP32Path::<6>::new([
+            2645 | H,
+            579218131 | H,
+            211006541 | H,
+            1534045311 | H,
+            1431804530 | H,
+            1,
+        ])
+        .unwrap();
+        let secret = SecretKey::new(path);
+        assert_eq!(
+            hex::encode(secret.bytes),
+            "070a5d43972aa997d290fdaeada02599ca1e073c2ec56de2bcd88b5ab1017739"
+        );
+        assert_eq!(
+            hex::encode(secret.public().unwrap().as_ref()),
+            "0400d4c2547581a9da667c268d88d5a2c5e663e5ad2a825ba90933135d56fb6c7907e9dd2e0bb96e403d6fc8448e3f006d5bc81e6cd579012be821913e28a7494a"
+        );
+
+        //message hash left shifted by 4 bits, like StarkEx apps do
+        let hash =
+            U256::from_be_hex("397e76d1667c4454bfb83514e120583af836f8e32a516765497823eb85e02370");
+        let mut sig = [0; SIGNATURE_MAX_LEN];
+        let (parity, len) = secret.sign(&hash.to_be_bytes(), &mut sig).unwrap();
+        assert!(!parity);
+        assert_eq!(
+            hex::encode(&sig[..len]),
+            "30440220071a7c0e537678f642bd5be021d3c99b27cc8c2d5e32a2db3df299024ebf118002200246db672f7bac130fd1a52d05767efd374afe11030f6017afbd0c390583eca4"
+        );
+
+        //deterministic
+        let mut again = [0; SIGNATURE_MAX_LEN];
+        let (_, again_len) = secret.sign(&hash.to_be_bytes(), &mut again).unwrap();
+        assert_eq!(&sig[..len], &again[..again_len]);
+
+        assert!(secret.sign(&hash.to_be_bytes(), &mut [0; 32]).is_err());
     }
 }
@@ -42,6 +42,7 @@ pub use errors::Error;
 pub mod crypto;
 pub mod hash;
 pub mod hmac;
+pub mod math;
 
 mod panic {
     #[macro_export]
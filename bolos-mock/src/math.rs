@@ -0,0 +1,114 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Big endian arithmetic, mirroring the `cx_math_*` syscalls
+use crate::{errors::SyscallError, Error};
+
+use core::cmp::Ordering;
+
+//CX_INVALID_PARAMETER_VALUE
+const INVALID_PARAMETER: Error = SyscallError::Code(0xFF87);
+
+/// Compare two operands
+pub fn cmp(a: &[u8], b: &[u8]) -> Result<Ordering, Error> {
+    let len = core::cmp::min(a.len(), b.len());
+
+    Ok(a[..len].cmp(&b[..len]))
+}
+
+/// Modulo operation
+///
+/// Applies v % m storing the result in v
+pub fn modm(v: &mut [u8], m: &[u8]) -> Result<(), Error> {
+    if v.len() < m.len() || m.iter().all(|b| *b == 0) {
+        return Err(INVALID_PARAMETER);
+    }
+
+    //bitwise long division, with an extra byte for the shifted remainder
+    let mut rem = std::vec![0u8; m.len() + 1];
+    let mut modulus = std::vec![0u8; m.len() + 1];
+    modulus[1..].copy_from_slice(m);
+
+    for byte in v.iter() {
+        for bit in (0..8).rev() {
+            let mut carry = (byte >> bit) & 1;
+            for r in rem.iter_mut().rev() {
+                let next = *r >> 7;
+                *r = (*r << 1) | carry;
+                carry = next;
+            }
+
+            if rem >= modulus {
+                sub_in_place(&mut rem, &modulus);
+            }
+        }
+    }
+
+    let split = v.len() - m.len();
+    v[..split].fill(0);
+    v[split..].copy_from_slice(&rem[1..]);
+
+    Ok(())
+}
+
+/// Subtract `b` from `a`, with both of the same length
+fn sub_in_place(a: &mut [u8], b: &[u8]) {
+    let mut borrow = 0u16;
+    for (a, b) in a.iter_mut().zip(b).rev() {
+        let diff = (*a as u16).wrapping_sub(*b as u16).wrapping_sub(borrow);
+        *a = diff as u8;
+        borrow = (diff >> 8) & 1;
+    }
+}
+
+/// Subtraction
+///
+/// Computes a - b, modulo 2^(8 * len), storing the result in r
+pub fn sub(r: &mut [u8], a: &[u8], b: &[u8]) -> Result<(), Error> {
+    let len = r.len().min(a.len()).min(b.len());
+
+    let r = &mut r[..len];
+    r.copy_from_slice(&a[..len]);
+    sub_in_place(r, &b[..len]);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::FromHex;
+
+    #[test]
+    fn modulo() {
+        let mut v = [0x01, 0x00];
+        modm(&mut v, &[0x07]).unwrap();
+        assert_eq!(v, [0, 4]);
+
+        //secp256k1 n + 5
+        let n = <[u8; 32]>::from_hex(
+            "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141",
+        )
+        .unwrap();
+        let mut v = [0; 33];
+        v[1..].copy_from_slice(&n);
+        v[32] += 5;
+        modm(&mut v, &n).unwrap();
+        assert_eq!(v[..32], [0; 32]);
+        assert_eq!(v[32], 5);
+
+        assert!(modm(&mut [0; 4], &[0; 2]).is_err());
+    }
+}
@@ -86,6 +86,113 @@ pub trait DisplayableItem {
     ) -> Result<u8, ViewError>;
 }
 
+/// Options for [`handle_message_with`]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub struct PagingOptions {
+    /// Width in bytes of the lines the device splits each page into, if any
+    ///
+    /// Lines ending early are padded with spaces so the next one starts
+    /// where the device splits the page
+    pub line_width: Option<usize>,
+    /// Break pages (or lines) after a space instead of in the middle of a word, when possible
+    pub word_wrap: bool,
+}
+
+impl PagingOptions {
+    /// Pages as big as the output, only respecting UTF-8 boundaries
+    pub const DEFAULT: Self = Self {
+        line_width: None,
+        word_wrap: false,
+    };
+
+    /// Pages as big as the output, wrapping words
+    pub const WORD_WRAP: Self = Self {
+        line_width: None,
+        word_wrap: true,
+    };
+
+    /// Lines of the Nano S, which shows each page in 2 lines of 17 characters
+    pub const NANOS: Self = Self {
+        line_width: Some(17),
+        word_wrap: true,
+    };
+}
+
+/// Length of the longest prefix of `input` of at most `max` bytes
+/// which doesn't split a UTF-8 character, and optionally ends after a space
+fn line_len(input: &[u8], max: usize, word_wrap: bool) -> usize {
+    if input.len() <= max {
+        return input.len();
+    }
+
+    //back off at most 3 continuation bytes, so binary data is still split
+    let mut len = max;
+    for _ in 0..3 {
+        match input.get(len) {
+            Some(c) if c & 0xC0 == 0x80 && len > 1 => len -= 1,
+            _ => break,
+        }
+    }
+    if input.get(len).map(|c| c & 0xC0 == 0x80) == Some(true) {
+        len = max;
+    }
+
+    if word_wrap && input.get(len) != Some(&b' ') {
+        if let Some(space) = input[..len].iter().rposition(|c| *c == b' ') {
+            len = space + 1;
+        }
+    }
+
+    len
+}
+
+/// Compute the page of `input` starting at `start`,
+/// writing it in `out` if given without the null terminator
+///
+/// Returns the start of the next page and the number of bytes of the page
+fn page_of(
+    input: &[u8],
+    start: usize,
+    page_len: usize,
+    options: PagingOptions,
+    mut out: Option<&mut [u8]>,
+) -> (usize, usize) {
+    let line_width = match options.line_width {
+        Some(width) if width > 0 && width < page_len => width,
+        _ => page_len,
+    };
+
+    let mut pos = start;
+    let mut written = 0usize;
+    for _ in 0..page_len / line_width {
+        let rest = &input[pos..];
+        if rest.is_empty() {
+            break;
+        }
+
+        //pad the previous line to the device line width
+        let pad = written.next_multiple_of(line_width) - written;
+        if let Some(out) = out.as_deref_mut() {
+            out.get_mut(written..written + pad)
+                .ledger_unwrap()
+                .fill(b' ');
+        }
+        written += pad;
+
+        let len = line_len(rest, line_width, options.word_wrap);
+        if let Some(out) = out.as_deref_mut() {
+            out.get_mut(written..written + len)
+                .ledger_unwrap()
+                .copy_from_slice(&rest[..len]);
+        }
+        written += len;
+        pos += len;
+    }
+
+    (pos, written)
+}
+
 #[inline(never)]
 /// Perform paging of `input` into `out`
 ///
@@ -93,30 +200,58 @@ pub trait DisplayableItem {
 /// the length of the output
 /// and the given page number.
 ///
+/// Pages never split UTF-8 characters, see [`handle_message_with`] for more options.
+///
 /// If `None` is returned an error occured, otherwise the total number of pages is returned
 pub fn handle_message(input: &[u8], out: &mut [u8], page: u8) -> Option<u8> {
-    let m_len = out.len() - 1; //null byte terminator
-    if m_len <= input.len() {
-        let chunk = input
-            .chunks(m_len) //divide in non-overlapping chunks
-            .nth(page as usize)?; //get the nth chunk
-
-        //guaranteed to be less than out size
-        out.get_mut(..chunk.len())
-            .ledger_unwrap()
-            .copy_from_slice(chunk);
-        *out.get_mut(chunk.len()).ledger_unwrap() = 0; //null terminate
-
-        let n_pages = input.len() / m_len;
-        Some(n_pages as u8)
-    } else {
-        //guaranteed to be smaller than out
-        out.get_mut(..input.len())
-            .ledger_unwrap()
-            .copy_from_slice(input);
-        *out.get_mut(input.len()).ledger_unwrap() = 0; //null terminate
-        Some(1)
+    handle_message_with(input, out, page, PagingOptions::DEFAULT)
+}
+
+#[inline(never)]
+/// Perform paging of `input` into `out` with the given options
+///
+/// The output is always null terminated, and the returned page count is exact.
+/// When `input` fits in a single page it's written whatever `page` is.
+///
+/// If `None` is returned an error occured, otherwise the total number of pages is returned
+pub fn handle_message_with(
+    input: &[u8],
+    out: &mut [u8],
+    page: u8,
+    options: PagingOptions,
+) -> Option<u8> {
+    let page_len = out.len().checked_sub(1)?; //null byte terminator
+    if page_len == 0 {
+        return None;
+    }
+
+    //count pages, remembering where the requested one starts
+    let mut pages = 0usize;
+    let mut requested = None;
+    let mut pos = 0;
+    loop {
+        if pages == page as usize {
+            requested = Some(pos);
+        }
+
+        pos = page_of(input, pos, page_len, options, None).0;
+        pages += 1;
+        if pos >= input.len() {
+            break;
+        }
     }
+
+    let pages = u8::try_from(pages).ok()?;
+    let start = match requested {
+        Some(start) => start,
+        None if pages == 1 => 0,
+        None => return None,
+    };
+
+    let (_, written) = page_of(input, start, page_len, options, Some(out));
+    *out.get_mut(written).ledger_unwrap() = 0; //null terminate
+
+    Some(pages)
 }
 
 #[cfg(test)]
@@ -163,4 +298,93 @@ mod tests {
         // (since it fits in one page)
         assert!(handle_message(&MSG, &mut out, 1).is_some());
     }
+
+    fn all_pages(
+        input: &[u8],
+        out_len: usize,
+        options: PagingOptions,
+    ) -> std::vec::Vec<std::vec::Vec<u8>> {
+        let mut out = std::vec![0xffu8; out_len];
+        let n = handle_message_with(input, &mut out, 0, options).unwrap();
+
+        (0..n)
+            .map(|page| {
+                assert_eq!(handle_message_with(input, &mut out, page, options), Some(n));
+                let len = out.iter().position(|c| *c == 0).unwrap();
+                out[..len].to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exact_page_count() {
+        let mut out = [0u8; 5];
+
+        //9 bytes in pages of 4
+        assert_eq!(handle_message(b"deadbeef0", &mut out, 0), Some(3));
+        assert_eq!(handle_message(b"deadbeef0", &mut out, 2), Some(3));
+        assert_eq!(&out[..2], b"0\0");
+        assert!(handle_message(b"deadbeef0", &mut out, 3).is_none());
+
+        assert_eq!(handle_message(b"", &mut out, 0), Some(1));
+        assert_eq!(out[0], 0);
+        assert!(handle_message(b"dead", &mut [0; 1], 0).is_none());
+    }
+
+    #[test]
+    fn utf8_boundaries() {
+        //each 'ñ' is 2 bytes
+        let input = "aññb".as_bytes();
+        let pages = all_pages(input, 5, PagingOptions::DEFAULT);
+        assert_eq!(pages, ["añ".as_bytes(), "ñb".as_bytes()]);
+
+        //binary data is still split at the page size
+        let binary = [0x80u8; 10];
+        assert_eq!(all_pages(&binary, 5, PagingOptions::DEFAULT).len(), 3);
+    }
+
+    #[test]
+    fn word_wrap() {
+        let input = b"the quick brown fox";
+        let pages = all_pages(input, 11, PagingOptions::WORD_WRAP);
+        assert_eq!(pages, [&b"the quick "[..], b"brown fox"]);
+
+        //words longer than a page are split
+        let pages = all_pages(b"abcdefghij kl", 5, PagingOptions::WORD_WRAP);
+        assert_eq!(pages, [&b"abcd"[..], b"efgh", b"ij ", b"kl"]);
+    }
+
+    #[test]
+    fn nanos_lines() {
+        //message buffer of the Nano S
+        const OUT_LEN: usize = 2 * 17 + 1;
+
+        let input = b"the quick brown fox jumps over the lazy dog";
+        let pages = all_pages(input, OUT_LEN, PagingOptions::NANOS);
+        assert_eq!(
+            pages,
+            [&b"the quick brown  fox jumps over "[..], b"the lazy dog"]
+        );
+        //lines start where the device splits them
+        assert_eq!(&pages[0][17..], b"fox jumps over ");
+    }
+
+    #[test]
+    fn nanos_lines_non_ascii() {
+        const OUT_LEN: usize = 2 * 17 + 1;
+
+        let input = "café crème brûlée au chocolat".as_bytes();
+        let mut out = [0; OUT_LEN];
+        assert_eq!(
+            handle_message_with(input, &mut out, 0, PagingOptions::NANOS),
+            Some(2)
+        );
+
+        //the device replaces each non ASCII byte before splitting at 17
+        zemu_sys::asciify(&mut out);
+        let len = out.iter().position(|c| *c == 0).unwrap();
+        let (line1, line2) = out[..len].split_at(17);
+        assert_eq!(line1, b"caf.. cr..me     ");
+        assert_eq!(line2, b"br..l..e au ");
+    }
 }
@@ -20,5 +20,9 @@ pub use wrapper::*;
 mod encode;
 pub use encode::*;
 
-mod parser;
-pub use parser::{FromBytes, ObjectList};
+pub mod eip712;
+pub mod format;
+pub mod parser;
+pub mod rlp;
+pub mod signature;
+pub use parser::{FromBytes, ObjectList, ToBytes};
@@ -0,0 +1,1205 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! EIP-712 typed structured data hashing
+//!
+//! Struct definitions are registered in [`Types`], after which an [`Encoder`]
+//! computes `hashStruct` of a value provided field by field, in definition order,
+//! so the message never needs to be held in memory at once.
+//!
+//! The `domainSeparator` is the `hashStruct` of the [`DOMAIN`] struct,
+//! and the final digest to sign is obtained with [`signing_hash`].
+//!
+//! # Example
+//! ```rust
+//! # use bolos::eip712::{Eip712Error, Encoder, Types};
+//! # fn hash() -> Result<[u8; 32], Eip712Error> {
+//! let mut types = Types::<128>::new();
+//! types.add_struct("Person")?;
+//! types.add_field("string", "name")?;
+//! types.add_field("address[]", "wallets")?;
+//!
+//! let mut encoder = Encoder::<128, 4>::new(&types, "Person")?;
+//! encoder.value(b"Cow")?;
+//! encoder.array_len(1)?;
+//! let field = encoder.value(&[0xCD; 20])?;
+//! assert_eq!(field.name, "wallets");
+//!
+//! encoder.finish()
+//! # }
+//! # assert!(hash().is_ok());
+//! ```
+
+use crate::{
+    ethereum::ADDRESS_LEN,
+    hash::{Hasher, Keccak},
+    ui::DisplayableItem,
+    ApduError,
+};
+use zemu_sys::ViewError;
+
+/// Name of the struct used to compute the `domainSeparator`
+pub const DOMAIN: &str = "EIP712Domain";
+
+/// Maximum number of structs that can be registered in [`Types`]
+pub const MAX_STRUCTS: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum Eip712Error {
+    /// Not enough space to register the definition
+    NoSpace,
+    /// More than [`MAX_STRUCTS`] structs were registered
+    TooManyStructs,
+    /// A struct with the same name was already registered
+    DuplicateStruct,
+    /// A field was added before any struct was registered
+    NoStruct,
+    /// Malformed type or struct name
+    InvalidType,
+    /// A referenced struct was not registered
+    UnknownType,
+    /// Values are nested deeper than the encoder supports
+    TooDeep,
+    /// The value doesn't fit the type of the field
+    InvalidValue,
+    /// The value was not expected at this point
+    UnexpectedValue,
+    /// The encoding is not complete
+    Incomplete,
+    /// The hasher failed
+    Hash,
+}
+
+impl From<crate::Error> for Eip712Error {
+    fn from(_: crate::Error) -> Self {
+        Self::Hash
+    }
+}
+
+impl From<Eip712Error> for ApduError {
+    fn from(_: Eip712Error) -> Self {
+        ApduError::DataInvalid
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Type of a field
+pub enum Type<'t> {
+    Bool,
+    Address,
+    /// Unsigned integer of the given bits
+    Uint(u16),
+    /// Signed integer of the given bits
+    Int(u16),
+    /// `bytesN`, of the given length
+    FixedBytes(u8),
+    Bytes,
+    String,
+    /// Reference to a struct, by name
+    Struct(&'t str),
+    /// Array of the inner type, with the fixed length if any
+    Array {
+        inner: &'t str,
+        len: Option<usize>,
+    },
+}
+
+impl<'t> Type<'t> {
+    /// Parse the given type name
+    pub fn parse(ty: &'t str) -> Result<Self, Eip712Error> {
+        if let Some(rest) = ty.strip_suffix(']') {
+            let open = rest.rfind('[').ok_or(Eip712Error::InvalidType)?;
+            let (inner, len) = (&rest[..open], &rest[open + 1..]);
+
+            let len = match len {
+                "" => None,
+                len => Some(parse_decimal(len).ok_or(Eip712Error::InvalidType)?),
+            };
+            //validate the element type
+            Self::parse(inner)?;
+
+            return Ok(Self::Array { inner, len });
+        }
+
+        let ty = match ty {
+            "bool" => Self::Bool,
+            "address" => Self::Address,
+            "bytes" => Self::Bytes,
+            "string" => Self::String,
+            _ => {
+                let sized = |prefix: &str| ty.strip_prefix(prefix).and_then(parse_decimal);
+
+                if let Some(bits) = sized("uint") {
+                    Self::Uint(int_bits(bits)?)
+                } else if let Some(bits) = sized("int") {
+                    Self::Int(int_bits(bits)?)
+                } else if let Some(len) = sized("bytes") {
+                    match len {
+                        1..=32 => Self::FixedBytes(len as u8),
+                        _ => return Err(Eip712Error::InvalidType),
+                    }
+                } else if is_identifier(ty) {
+                    Self::Struct(ty)
+                } else {
+                    return Err(Eip712Error::InvalidType);
+                }
+            }
+        };
+
+        Ok(ty)
+    }
+
+    /// Retrieve the struct referenced by the type, looking through arrays
+    fn struct_name(ty: &'t str) -> Result<Option<&'t str>, Eip712Error> {
+        match Self::parse(ty)? {
+            Self::Struct(name) => Ok(Some(name)),
+            Self::Array { inner, .. } => Self::struct_name(inner),
+            _ => Ok(None),
+        }
+    }
+}
+
+fn int_bits(bits: usize) -> Result<u16, Eip712Error> {
+    match bits {
+        8..=256 if bits.is_multiple_of(8) => Ok(bits as u16),
+        _ => Err(Eip712Error::InvalidType),
+    }
+}
+
+/// Parse a decimal number without leading zeros
+fn parse_decimal(s: &str) -> Option<usize> {
+    if s.is_empty() || (s.len() > 1 && s.starts_with('0')) {
+        return None;
+    }
+
+    s.bytes().try_fold(0usize, |n, c| {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+
+        n.checked_mul(10)?.checked_add((c - b'0') as usize)
+    })
+}
+
+fn is_identifier(s: &str) -> bool {
+    let valid = |c: u8| c.is_ascii_alphanumeric() || c == b'_' || c == b'$';
+
+    match s.as_bytes().split_first() {
+        Some((first, rest)) => {
+            valid(*first) && !first.is_ascii_digit() && rest.iter().all(|c| valid(*c))
+        }
+        None => false,
+    }
+}
+
+/// Read a length prefixed string from the arena
+fn read_str(data: &[u8]) -> (&str, &[u8]) {
+    let (len, rest) = (data[0] as usize, &data[1..]);
+    let (s, rest) = rest.split_at(len);
+
+    //only valid strings are stored
+    (unsafe { core::str::from_utf8_unchecked(s) }, rest)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Definition of a struct field
+pub struct FieldDef<'t> {
+    pub ty: &'t str,
+    pub name: &'t str,
+}
+
+#[derive(Clone, Copy)]
+/// Iterator over the fields of a [`Struct`]
+pub struct Fields<'t> {
+    data: &'t [u8],
+    left: u8,
+}
+
+impl<'t> Iterator for Fields<'t> {
+    type Item = FieldDef<'t>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == 0 {
+            return None;
+        }
+
+        let (ty, rest) = read_str(self.data);
+        let (name, rest) = read_str(rest);
+        self.data = rest;
+        self.left -= 1;
+
+        Some(FieldDef { ty, name })
+    }
+}
+
+#[derive(Clone, Copy)]
+/// A registered struct definition
+pub struct Struct<'t> {
+    index: usize,
+    name: &'t str,
+    fields: Fields<'t>,
+}
+
+impl<'t> Struct<'t> {
+    pub fn name(&self) -> &'t str {
+        self.name
+    }
+
+    /// Number of fields of the struct
+    pub fn len(&self) -> usize {
+        self.fields.left as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.left == 0
+    }
+
+    pub fn fields(&self) -> Fields<'t> {
+        self.fields
+    }
+}
+
+/// Registry of struct definitions, stored in `N` bytes
+///
+/// Structs are registered with [`Types::add_struct`], followed by their fields
+/// in order with [`Types::add_field`]
+pub struct Types<const N: usize> {
+    arena: [u8; N],
+    used: usize,
+    count: usize,
+    //position of the number of fields of the last struct
+    last: usize,
+}
+
+impl<const N: usize> Default for Types<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Types<N> {
+    pub const fn new() -> Self {
+        Self {
+            arena: [0; N],
+            used: 0,
+            count: 0,
+            last: 0,
+        }
+    }
+
+    /// Number of registered structs
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn push(&mut self, data: &[u8]) -> Result<(), Eip712Error> {
+        let out = self
+            .arena
+            .get_mut(self.used..self.used + data.len())
+            .ok_or(Eip712Error::NoSpace)?;
+        out.copy_from_slice(data);
+        self.used += data.len();
+
+        Ok(())
+    }
+
+    fn push_str(&mut self, s: &str) -> Result<(), Eip712Error> {
+        let len = u8::try_from(s.len()).map_err(|_| Eip712Error::NoSpace)?;
+        self.push(&[len])?;
+        self.push(s.as_bytes())
+    }
+
+    #[inline(never)]
+    /// Register a new struct, to which following fields will be added
+    pub fn add_struct(&mut self, name: &str) -> Result<(), Eip712Error> {
+        if !is_identifier(name) {
+            return Err(Eip712Error::InvalidType);
+        }
+        if self.get(name).is_some() {
+            return Err(Eip712Error::DuplicateStruct);
+        }
+        if self.count >= MAX_STRUCTS {
+            return Err(Eip712Error::TooManyStructs);
+        }
+
+        //restore the arena if the struct doesn't fit
+        let used = self.used;
+        let res = self.push_str(name).and_then(|_| self.push(&[0]));
+        if res.is_err() {
+            self.used = used;
+            return res;
+        }
+
+        self.last = self.used - 1;
+        self.count += 1;
+
+        Ok(())
+    }
+
+    #[inline(never)]
+    /// Add a field to the last registered struct
+    ///
+    /// Referenced structs are allowed to be registered later
+    pub fn add_field(&mut self, ty: &str, name: &str) -> Result<(), Eip712Error> {
+        if self.count == 0 {
+            return Err(Eip712Error::NoStruct);
+        }
+        Type::parse(ty)?;
+        if !is_identifier(name) {
+            return Err(Eip712Error::InvalidType);
+        }
+        if self.arena[self.last] == u8::MAX {
+            return Err(Eip712Error::NoSpace);
+        }
+
+        let used = self.used;
+        let res = self.push_str(ty).and_then(|_| self.push_str(name));
+        if res.is_err() {
+            self.used = used;
+            return res;
+        }
+
+        self.arena[self.last] += 1;
+
+        Ok(())
+    }
+
+    /// Iterate over all the registered structs
+    pub fn structs(&self) -> impl Iterator<Item = Struct<'_>> {
+        let mut data = &self.arena[..self.used];
+
+        (0..self.count).map(move |index| {
+            let (name, rest) = read_str(data);
+            let fields = Fields {
+                data: &rest[1..],
+                left: rest[0],
+            };
+            data = &rest[1..];
+            for _ in fields {
+                let (_, rest) = read_str(data);
+                let (_, rest) = read_str(rest);
+                data = rest;
+            }
+
+            Struct {
+                index,
+                name,
+                fields,
+            }
+        })
+    }
+
+    /// Retrieve the struct with the given name
+    pub fn get(&self, name: &str) -> Option<Struct<'_>> {
+        self.structs().find(|s| s.name == name)
+    }
+
+    /// Set of structs referenced by `primary`, including itself, as a bitmask of struct indices
+    fn dependencies(&self, primary: &Struct<'_>) -> Result<u64, Eip712Error> {
+        let mut mask = 1u64 << primary.index;
+
+        loop {
+            let before = mask;
+            for s in self.structs().filter(|s| before & (1 << s.index) != 0) {
+                for field in s.fields() {
+                    if let Some(name) = Type::struct_name(field.ty)? {
+                        let dep = self.get(name).ok_or(Eip712Error::UnknownType)?;
+                        mask |= 1 << dep.index;
+                    }
+                }
+            }
+
+            if mask == before {
+                return Ok(mask);
+            }
+        }
+    }
+
+    #[inline(never)]
+    /// Compute `encodeType` of the given struct, passing each piece to `out`
+    ///
+    /// The primary struct is followed by the referenced structs, sorted by name
+    pub fn encode_type<E>(
+        &self,
+        name: &str,
+        mut out: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E>
+    where
+        E: From<Eip712Error>,
+    {
+        let primary = self.get(name).ok_or(Eip712Error::UnknownType)?;
+        let deps = self.dependencies(&primary)? & !(1 << primary.index);
+
+        let mut encode = |s: &Struct<'_>| {
+            out(s.name.as_bytes())?;
+            out(b"(")?;
+            for (i, field) in s.fields().enumerate() {
+                if i > 0 {
+                    out(b",")?;
+                }
+                out(field.ty.as_bytes())?;
+                out(b" ")?;
+                out(field.name.as_bytes())?;
+            }
+            out(b")")
+        };
+
+        encode(&primary)?;
+
+        let mut previous = "";
+        while let Some(next) = self
+            .structs()
+            .filter(|s| deps & (1 << s.index) != 0 && s.name > previous)
+            .min_by(|a, b| a.name.cmp(b.name))
+        {
+            encode(&next)?;
+            previous = next.name;
+        }
+
+        Ok(())
+    }
+
+    #[inline(never)]
+    /// Compute `typeHash` of the given struct
+    pub fn type_hash(&self, name: &str) -> Result<[u8; 32], Eip712Error> {
+        let mut hasher = Keccak::<32>::new()?;
+        self.encode_type(name, |piece| {
+            hasher.update(piece).map_err(Eip712Error::from)
+        })?;
+
+        Ok(hasher.finalize()?)
+    }
+}
+
+/// Compute the digest to sign, `keccak256(0x19 || 0x01 || domainSeparator || hashStruct(message))`
+pub fn signing_hash(
+    domain_separator: &[u8; 32],
+    message: &[u8; 32],
+) -> Result<[u8; 32], Eip712Error> {
+    let mut hasher = Keccak::<32>::new()?;
+    hasher.update(&[0x19, 0x01])?;
+    hasher.update(domain_separator)?;
+    hasher.update(message)?;
+
+    Ok(hasher.finalize()?)
+}
+
+enum Position<'t> {
+    Struct(Fields<'t>),
+    Array {
+        name: &'t str,
+        ty: &'t str,
+        //none if waiting for the length
+        left: Option<usize>,
+    },
+}
+
+struct Frame<'t> {
+    hasher: Keccak<32>,
+    position: Position<'t>,
+}
+
+/// Streaming `hashStruct` computation
+///
+/// Values are provided in the order of the struct definitions,
+/// with nested structs entered automatically.
+/// Dynamic arrays require their length with [`Encoder::array_len`] before the elements.
+///
+/// `D` is the maximum nesting depth of structs and arrays
+pub struct Encoder<'t, const N: usize, const D: usize> {
+    types: &'t Types<N>,
+    frames: [Option<Frame<'t>>; D],
+    depth: usize,
+    //hasher of a `bytes` or `string` value being received in chunks
+    dynamic: Option<Keccak<32>>,
+    hash: Option<[u8; 32]>,
+}
+
+impl<'t, const N: usize, const D: usize> Encoder<'t, N, D> {
+    #[inline(never)]
+    /// Start computing `hashStruct` of a value of the given struct
+    pub fn new(types: &'t Types<N>, primary: &str) -> Result<Self, Eip712Error> {
+        let mut this = Self {
+            types,
+            frames: [(); D].map(|_| None),
+            depth: 0,
+            dynamic: None,
+            hash: None,
+        };
+
+        let primary = types.get(primary).ok_or(Eip712Error::UnknownType)?;
+        this.push_struct(primary)?;
+        this.settle()?;
+
+        Ok(this)
+    }
+
+    /// Start computing the `domainSeparator`
+    pub fn domain(types: &'t Types<N>) -> Result<Self, Eip712Error> {
+        Self::new(types, DOMAIN)
+    }
+
+    fn push(&mut self, frame: Frame<'t>) -> Result<(), Eip712Error> {
+        let slot = self
+            .frames
+            .get_mut(self.depth)
+            .ok_or(Eip712Error::TooDeep)?;
+        *slot = Some(frame);
+        self.depth += 1;
+
+        Ok(())
+    }
+
+    fn push_struct(&mut self, s: Struct<'t>) -> Result<(), Eip712Error> {
+        let mut hasher = Keccak::<32>::new()?;
+        hasher.update(&self.types.type_hash(s.name)?)?;
+
+        self.push(Frame {
+            hasher,
+            position: Position::Struct(s.fields()),
+        })
+    }
+
+    fn top(&mut self) -> Option<&mut Frame<'t>> {
+        let depth = self.depth.checked_sub(1)?;
+        self.frames[depth].as_mut()
+    }
+
+    /// Advance the position of the current frame, returning the consumed field
+    fn consume(&mut self) -> Option<FieldDef<'t>> {
+        match &mut self.top()?.position {
+            Position::Struct(fields) => fields.next(),
+            Position::Array { name, ty, left } => {
+                let left = left.as_mut()?;
+                *left = left.checked_sub(1)?;
+
+                Some(FieldDef { ty, name })
+            }
+        }
+    }
+
+    /// Enter nested structs and arrays, and close the completed ones
+    fn settle(&mut self) -> Result<(), Eip712Error> {
+        loop {
+            let next = match self.next() {
+                Some(next) => next,
+                None => match self.top() {
+                    //waiting for the array length
+                    Some(Frame {
+                        position: Position::Array { left: None, .. },
+                        ..
+                    }) => return Ok(()),
+                    Some(_) => {
+                        self.pop()?;
+                        continue;
+                    }
+                    None => return Ok(()),
+                },
+            };
+
+            match Type::parse(next.ty)? {
+                Type::Struct(name) => {
+                    let s = self.types.get(name).ok_or(Eip712Error::UnknownType)?;
+                    self.consume();
+                    self.push_struct(s)?;
+                }
+                Type::Array { inner, len } => {
+                    self.consume();
+                    self.push(Frame {
+                        hasher: Keccak::<32>::new()?,
+                        position: Position::Array {
+                            name: next.name,
+                            ty: inner,
+                            left: len,
+                        },
+                    })?;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Close the current frame, encoding its hash in the parent
+    fn pop(&mut self) -> Result<(), Eip712Error> {
+        self.depth -= 1;
+        let frame = self.frames[self.depth]
+            .take()
+            .ok_or(Eip712Error::Incomplete)?;
+        let hash = frame.hasher.finalize()?;
+
+        match self.top() {
+            Some(parent) => parent.hasher.update(&hash)?,
+            None => self.hash = Some(hash),
+        }
+
+        Ok(())
+    }
+
+    /// Retrieve the field expected next, if any
+    ///
+    /// Array elements are named after the array field
+    pub fn next(&self) -> Option<FieldDef<'t>> {
+        let frame = self.frames.get(self.depth.checked_sub(1)?)?.as_ref()?;
+
+        match &frame.position {
+            Position::Struct(fields) => fields.clone().next(),
+            Position::Array {
+                name,
+                ty,
+                left: Some(left),
+            } if *left > 0 => Some(FieldDef { ty, name }),
+            _ => None,
+        }
+    }
+
+    /// Whether the encoder is waiting for the length of a dynamic array
+    pub fn expects_array_len(&self) -> bool {
+        matches!(
+            self.depth
+                .checked_sub(1)
+                .and_then(|depth| self.frames[depth].as_ref()),
+            Some(Frame {
+                position: Position::Array { left: None, .. },
+                ..
+            })
+        )
+    }
+
+    #[inline(never)]
+    /// Provide the number of elements of the current dynamic array
+    pub fn array_len(&mut self, len: usize) -> Result<(), Eip712Error> {
+        if !self.expects_array_len() || self.dynamic.is_some() {
+            return Err(Eip712Error::UnexpectedValue);
+        }
+
+        if let Some(Frame {
+            position: Position::Array { left, .. },
+            ..
+        }) = self.top()
+        {
+            *left = Some(len);
+        }
+
+        self.settle()
+    }
+
+    #[inline(never)]
+    /// Provide a chunk of the current `bytes` or `string` value,
+    /// which will be completed by the next [`Encoder::value`]
+    pub fn partial_value(&mut self, chunk: &[u8]) -> Result<(), Eip712Error> {
+        let next = self.next().ok_or(Eip712Error::UnexpectedValue)?;
+        if !matches!(Type::parse(next.ty)?, Type::Bytes | Type::String) {
+            return Err(Eip712Error::UnexpectedValue);
+        }
+
+        let hasher = match &mut self.dynamic {
+            Some(hasher) => hasher,
+            dynamic => dynamic.insert(Keccak::<32>::new()?),
+        };
+
+        Ok(hasher.update(chunk)?)
+    }
+
+    #[inline(never)]
+    /// Provide the value of the next field, or the last chunk of it
+    /// if [`Encoder::partial_value`] was used
+    ///
+    /// Integers and booleans are big endian and can omit leading bytes:
+    /// `uintN` values are unsigned, so `[0x80]` is 128, while `intN` values are
+    /// minimal two's complement, so `[0x80]` is -128 and 128 is `[0x00, 0x80]`.
+    /// Addresses must be exactly 20 bytes and `bytesN` exactly `N` bytes,
+    /// so they are displayed in full.
+    ///
+    /// Returns the encoded field, so it can be displayed
+    pub fn value<'v>(&mut self, value: &'v [u8]) -> Result<Field<'t, 'v>, Eip712Error> {
+        let next = self.next().ok_or(Eip712Error::UnexpectedValue)?;
+        let ty = Type::parse(next.ty)?;
+
+        let mut word = [0; 32];
+        match (ty, self.dynamic.take()) {
+            (Type::Bytes | Type::String, dynamic) => {
+                let mut hasher = match dynamic {
+                    Some(hasher) => hasher,
+                    None => Keccak::<32>::new()?,
+                };
+                hasher.update(value)?;
+                hasher.finalize_into(&mut word)?;
+            }
+            (_, Some(_)) => return Err(Eip712Error::UnexpectedValue),
+            (Type::Bool, _) => match value {
+                [b @ (0 | 1)] => word[31] = *b,
+                _ => return Err(Eip712Error::InvalidValue),
+            },
+            (Type::Address, _) => {
+                if value.len() != ADDRESS_LEN {
+                    return Err(Eip712Error::InvalidValue);
+                }
+                word[32 - ADDRESS_LEN..].copy_from_slice(value);
+            }
+            (Type::Uint(bits), _) => encode_uint(value, bits as usize / 8, &mut word)?,
+            (Type::Int(bits), _) => {
+                let size = bits as usize / 8;
+                if value.len() > size {
+                    return Err(Eip712Error::InvalidValue);
+                }
+                //sign extend
+                if value.first().map(|b| b & 0x80 != 0) == Some(true) {
+                    word.fill(0xff);
+                }
+                word[32 - value.len()..].copy_from_slice(value);
+            }
+            (Type::FixedBytes(len), _) => {
+                if value.len() != len as usize {
+                    return Err(Eip712Error::InvalidValue);
+                }
+                word[..value.len()].copy_from_slice(value);
+            }
+            (Type::Struct(_) | Type::Array { .. }, _) => return Err(Eip712Error::UnexpectedValue),
+        }
+
+        self.top()
+            .ok_or(Eip712Error::UnexpectedValue)?
+            .hasher
+            .update(&word)?;
+        self.consume();
+        self.settle()?;
+
+        Ok(Field {
+            name: next.name,
+            ty,
+            value,
+        })
+    }
+
+    /// Whether all the values have been provided
+    pub fn is_done(&self) -> bool {
+        self.hash.is_some()
+    }
+
+    /// Retrieve the resulting `hashStruct`
+    pub fn finish(self) -> Result<[u8; 32], Eip712Error> {
+        self.hash.ok_or(Eip712Error::Incomplete)
+    }
+}
+
+/// Left pad a big endian unsigned integer of at most `size` significant bytes
+fn encode_uint(value: &[u8], size: usize, word: &mut [u8; 32]) -> Result<(), Eip712Error> {
+    let zeros = value.iter().take_while(|b| **b == 0).count();
+    let value = &value[zeros..];
+    if value.len() > size {
+        return Err(Eip712Error::InvalidValue);
+    }
+
+    word[32 - value.len()..].copy_from_slice(value);
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// A field encoded by the [`Encoder`], displayable for review
+///
+/// Integers are shown in decimal, strings as is and the remaining types in `0x` prefixed hex
+pub struct Field<'t, 'v> {
+    pub name: &'t str,
+    pub ty: Type<'t>,
+    pub value: &'v [u8],
+}
+
+impl<'t, 'v> Field<'t, 'v> {
+    /// Write the decimal representation of the value at the end of `out`,
+    /// returning the index of the first digit
+    fn decimal(&self, out: &mut [u8; 80]) -> usize {
+        let mut word = [0; 32];
+        let value = &self.value[self.value.len().saturating_sub(32)..];
+        let negative =
+            matches!(self.ty, Type::Int(_)) && value.first().map(|b| b & 0x80 != 0) == Some(true);
+        if negative {
+            word.fill(0xff);
+        }
+        word[32 - value.len()..].copy_from_slice(value);
+
+        if negative {
+            //two's complement
+            let mut carry = true;
+            for b in word.iter_mut().rev() {
+                let (n, c) = (!*b).overflowing_add(carry as u8);
+                *b = n;
+                carry = c;
+            }
+        }
+
+        let mut idx = out.len();
+        loop {
+            let mut rem = 0u16;
+            for b in word.iter_mut() {
+                let n = (rem << 8) | *b as u16;
+                *b = (n / 10) as u8;
+                rem = n % 10;
+            }
+
+            idx -= 1;
+            out[idx] = b'0' + rem as u8;
+
+            if word.iter().all(|b| *b == 0) {
+                break;
+            }
+        }
+
+        if negative {
+            idx -= 1;
+            out[idx] = b'-';
+        }
+
+        idx
+    }
+
+    /// Write the page of the `0x` prefixed hex representation of the value
+    fn hex_page(&self, message: &mut [u8], page: u8) -> Result<u8, ViewError> {
+        let (_, out) = message.split_last_mut().ok_or(ViewError::Unknown)?;
+        if out.is_empty() {
+            return Err(ViewError::Unknown);
+        }
+
+        //addresses and bytesN are always shown in full,
+        // padded like they are encoded
+        let len = self.value.len();
+        let (width, pad) = match self.ty {
+            Type::Address => (len.max(ADDRESS_LEN), ADDRESS_LEN.saturating_sub(len)),
+            Type::FixedBytes(n) => (len.max(n as usize), 0),
+            _ => (len, 0),
+        };
+
+        let total = 2 + 2 * width;
+        let pages = total.div_ceil(out.len());
+        let start = page as usize * out.len();
+        if start >= total {
+            return Err(ViewError::NoData);
+        }
+        let end = total.min(start + out.len());
+
+        for (o, i) in out.iter_mut().zip(start..end) {
+            *o = match i {
+                0 => b'0',
+                1 => b'x',
+                i => {
+                    let byte = ((i - 2) / 2)
+                        .checked_sub(pad)
+                        .and_then(|j| self.value.get(j))
+                        .copied()
+                        .unwrap_or(0);
+                    let mut hex = [0; 2];
+                    crate::hex_encode([byte], &mut hex).map_err(|_| ViewError::Unknown)?;
+                    hex[i % 2]
+                }
+            };
+        }
+        message[end - start] = 0;
+
+        u8::try_from(pages).map_err(|_| ViewError::Unknown)
+    }
+}
+
+impl<'t, 'v> DisplayableItem for Field<'t, 'v> {
+    fn num_items(&self) -> usize {
+        1
+    }
+
+    #[inline(never)]
+    fn render_item(
+        &self,
+        item_n: u8,
+        title: &mut [u8],
+        message: &mut [u8],
+        page: u8,
+    ) -> Result<u8, ViewError> {
+        if item_n != 0 {
+            return Err(ViewError::NoData);
+        }
+
+        if let Some((_, out)) = title.split_last_mut() {
+            let len = self.name.len().min(out.len());
+            out[..len].copy_from_slice(&self.name.as_bytes()[..len]);
+            title[len] = 0;
+        }
+
+        match self.ty {
+            Type::Bool => {
+                let value: &[u8] = if self.value == [1] { b"true" } else { b"false" };
+                crate::ui::handle_message(value, message, page).ok_or(ViewError::NoData)
+            }
+            Type::Uint(_) | Type::Int(_) => {
+                let mut digits = [0; 80];
+                let start = self.decimal(&mut digits);
+                crate::ui::handle_message(&digits[start..], message, page).ok_or(ViewError::NoData)
+            }
+            Type::String => {
+                crate::ui::handle_message(self.value, message, page).ok_or(ViewError::NoData)
+            }
+            _ => self.hex_page(message, page),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mail_types() -> Types<256> {
+        let mut types = Types::new();
+        types.add_struct("EIP712Domain").unwrap();
+        types.add_field("string", "name").unwrap();
+        types.add_field("string", "version").unwrap();
+        types.add_field("uint256", "chainId").unwrap();
+        types.add_field("address", "verifyingContract").unwrap();
+        //referenced before being registered
+        types.add_struct("Mail").unwrap();
+        types.add_field("Person", "from").unwrap();
+        types.add_field("Person", "to").unwrap();
+        types.add_field("string", "contents").unwrap();
+        types.add_struct("Person").unwrap();
+        types.add_field("string", "name").unwrap();
+        types.add_field("address", "wallet").unwrap();
+
+        types
+    }
+
+    fn hash32(s: &str) -> [u8; 32] {
+        let mut out = [0; 32];
+        hex::decode_to_slice(s, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(Type::parse("uint256"), Ok(Type::Uint(256)));
+        assert_eq!(Type::parse("int8"), Ok(Type::Int(8)));
+        assert_eq!(Type::parse("bytes32"), Ok(Type::FixedBytes(32)));
+        assert_eq!(
+            Type::parse("Person[2][]"),
+            Ok(Type::Array {
+                inner: "Person[2]",
+                len: None
+            })
+        );
+        assert_eq!(Type::parse("uint7"), Err(Eip712Error::InvalidType));
+        assert_eq!(Type::parse("bytes33"), Err(Eip712Error::InvalidType));
+        assert_eq!(Type::parse("uint256[02]"), Err(Eip712Error::InvalidType));
+        assert_eq!(Type::parse("1Person"), Err(Eip712Error::InvalidType));
+
+        let mut types = mail_types();
+        assert_eq!(
+            types.add_struct("Person"),
+            Err(Eip712Error::DuplicateStruct)
+        );
+        assert_eq!(types.get("Mail").unwrap().len(), 3);
+
+        let mut encoded = std::vec::Vec::new();
+        types
+            .encode_type("Mail", |piece| {
+                encoded.extend_from_slice(piece);
+                Ok::<_, Eip712Error>(())
+            })
+            .unwrap();
+        assert_eq!(
+            &encoded[..],
+            b"Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            types.type_hash("Mail"),
+            Ok(hash32(
+                "a0cedeb2dc280ba39b857546d74f5549c3a1d7bdc2dd96bf881f76108e23dac2"
+            ))
+        );
+
+        let mut small = Types::<8>::new();
+        assert_eq!(small.add_struct("Transaction"), Err(Eip712Error::NoSpace));
+        assert!(small.is_empty());
+    }
+
+    #[test]
+    fn mail() {
+        let types = mail_types();
+
+        let mut domain = Encoder::<256, 1>::domain(&types).unwrap();
+        domain.value(b"Ether Mail").unwrap();
+        domain.value(b"1").unwrap();
+        domain.value(&[1]).unwrap();
+        domain.value(&[0xCC; 20]).unwrap();
+        let domain = domain.finish().unwrap();
+        assert_eq!(
+            domain,
+            hash32("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f")
+        );
+
+        let mut mail = Encoder::<256, 2>::new(&types, "Mail").unwrap();
+        assert_eq!(
+            mail.next(),
+            Some(FieldDef {
+                ty: "string",
+                name: "name"
+            })
+        );
+        mail.value(b"Cow").unwrap();
+        mail.value(&hex::decode("CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826").unwrap())
+            .unwrap();
+        mail.value(b"Bob").unwrap();
+        mail.value(&[0xBB; 20]).unwrap();
+        //contents in chunks
+        mail.partial_value(b"Hello, ").unwrap();
+        mail.value(b"Bob!").unwrap();
+        assert!(mail.is_done());
+        assert_eq!(mail.value(b""), Err(Eip712Error::UnexpectedValue));
+        let mail = mail.finish().unwrap();
+        assert_eq!(
+            mail,
+            hash32("c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e")
+        );
+
+        assert_eq!(
+            signing_hash(&domain, &mail),
+            Ok(hash32(
+                "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+            ))
+        );
+
+        //not enough depth for nested structs
+        let mail = Encoder::<256, 1>::new(&types, "Mail");
+        assert!(matches!(mail.err(), Some(Eip712Error::TooDeep)));
+    }
+
+    #[test]
+    fn values() {
+        let mut types = Types::<128>::new();
+        types.add_struct("Payment").unwrap();
+        types.add_field("int16", "delta").unwrap();
+        types.add_field("address", "to").unwrap();
+
+        let keccak = |data: &[u8]| Keccak::<32>::digest(data).unwrap();
+        let type_hash = types.type_hash("Payment").unwrap();
+        let to = [0x0a; 20];
+        let mut to_word = [0; 32];
+        to_word[12..].copy_from_slice(&to);
+
+        //positive values with the top bit set need the leading zero
+        let mut message = [0; 8];
+        for (value, fill, shown) in [
+            (&[0x00, 0x80][..], 0x00, &b"128\0"[..]),
+            (&[0x80], 0xff, b"-128\0"),
+        ] {
+            let mut encoder = Encoder::<128, 1>::new(&types, "Payment").unwrap();
+            let field = encoder.value(value).unwrap();
+            assert!(field.render_item(0, &mut [0; 8], &mut message, 0) == Ok(1));
+            assert_eq!(&message[..shown.len()], shown);
+
+            //addresses must be complete
+            assert_eq!(encoder.value(&to[1..]), Err(Eip712Error::InvalidValue));
+            encoder.value(&to).unwrap();
+
+            let mut delta = [fill; 32];
+            delta[31] = 0x80;
+            assert_eq!(
+                encoder.finish(),
+                Ok(keccak(&[type_hash, delta, to_word].concat()))
+            );
+        }
+    }
+
+    #[test]
+    fn arrays() {
+        let mut types = Types::<128>::new();
+        types.add_struct("Group").unwrap();
+        types.add_field("uint8[]", "ids").unwrap();
+        types.add_field("int16[2]", "deltas").unwrap();
+        types.add_field("bool", "open").unwrap();
+
+        let keccak = |data: &[u8]| Keccak::<32>::digest(data).unwrap();
+        let word = |n: u8, fill: u8| {
+            let mut w = [fill; 32];
+            w[31] = n;
+            w
+        };
+
+        let mut encoder = Encoder::<128, 2>::new(&types, "Group").unwrap();
+        assert!(encoder.expects_array_len());
+        assert_eq!(encoder.value(&[1]), Err(Eip712Error::UnexpectedValue));
+        encoder.array_len(2).unwrap();
+        encoder.value(&[1]).unwrap();
+        assert_eq!(encoder.value(&[1, 0]), Err(Eip712Error::InvalidValue));
+        encoder.value(&[0, 2]).unwrap();
+        encoder.value(&[0x7f]).unwrap();
+        encoder.value(&[0xff, 0xfe]).unwrap();
+        encoder.value(&[1]).unwrap();
+        let hash = encoder.finish().unwrap();
+
+        let ids = keccak(&[word(1, 0), word(2, 0)].concat());
+        let deltas = keccak(&[word(0x7f, 0), word(0xfe, 0xff)].concat());
+        let expected =
+            keccak(&[types.type_hash("Group").unwrap(), ids, deltas, word(1, 0)].concat());
+        assert_eq!(hash, expected);
+    }
+
+    #[test]
+    fn display() {
+        let mut title = [0; 8];
+        let mut message = [0; 9];
+
+        let field = Field {
+            name: "amount",
+            ty: Type::Int(256),
+            value: &[0xfe, 0x0c],
+        };
+        assert!(field.render_item(0, &mut title, &mut message, 0) == Ok(1));
+        assert_eq!(&title[..7], b"amount\0");
+        assert_eq!(&message[..5], b"-500\0");
+
+        let field = Field {
+            name: "recipient",
+            ty: Type::Address,
+            value: &[0xab; 20],
+        };
+        assert!(field.render_item(0, &mut title, &mut message, 0) == Ok(6));
+        assert_eq!(&title, b"recipie\0");
+        assert_eq!(&message, b"0xababab\0");
+        assert!(field.render_item(0, &mut title, &mut message, 5) == Ok(6));
+        assert_eq!(&message[..3], b"ab\0");
+        assert!(field.render_item(0, &mut title, &mut message, 6) == Err(ViewError::NoData));
+
+        //short addresses are never shortened on screen
+        let field = Field {
+            name: "recipient",
+            ty: Type::Address,
+            value: &[0xab],
+        };
+        assert!(field.render_item(0, &mut title, &mut message, 0) == Ok(6));
+        assert_eq!(&message, b"0x000000\0");
+        assert!(field.render_item(0, &mut title, &mut message, 5) == Ok(6));
+        assert_eq!(&message[..3], b"ab\0");
+
+        let field = Field {
+            name: "salt",
+            ty: Type::FixedBytes(4),
+            value: &[0xab],
+        };
+        assert!(field.render_item(0, &mut title, &mut message, 0) == Ok(2));
+        assert_eq!(&message, b"0xab0000\0");
+    }
+}
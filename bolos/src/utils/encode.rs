@@ -13,12 +13,26 @@
 *  See the License for the specific language governing permissions and
 *  limitations under the License.
 ********************************************************************************/
-use crate::{LedgerUnwrap, PIC};
+use crate::{ApduError, LedgerUnwrap, PIC};
 
+pub mod base32;
+pub mod base64;
 pub mod bech32;
+pub mod bitcoin;
+
+pub mod der;
+pub mod ethereum;
+
+pub mod ss58;
 
 mod convert_der_to_rs;
-pub use convert_der_to_rs::{convert_der_to_rs, ConvertError as ConvertDERtoRSError};
+pub use convert_der_to_rs::{
+    convert_der_to_rs, convert_der_to_rs_unsigned, ConvertError as ConvertDERtoRSError,
+    ConvertUnsignedError as ConvertDERtoUnsignedRSError,
+};
+
+mod convert_rs_to_der;
+pub use convert_rs_to_der::{convert_rs_to_der, MAX_DER_SIGNATURE_LEN};
 
 pub enum ConvertBitsError {
     /// `FROM` or `TO` bit size are either 0 or greater than 8
@@ -95,24 +109,62 @@ pub fn convert_bits<const FROM: u8, const TO: u8>(
 }
 
 /// Simple error indicating that the output slice was too small for the given input
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
 pub struct OutputBufferTooSmall;
 
+impl From<OutputBufferTooSmall> for ApduError {
+    fn from(_: OutputBufferTooSmall) -> Self {
+        ApduError::OutputBufferTooSmall
+    }
+}
+
+/// Letter case of hex strings
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum HexCase {
+    Lower,
+    Upper,
+}
+
 /// Attempt to convert the input byte slice into a hex string
 ///
 /// The hex string will be written to `output`, with the number of bytes written returned
 pub fn hex_encode(
     input: impl AsRef<[u8]>,
     output: &mut [u8],
+) -> Result<usize, OutputBufferTooSmall> {
+    hex_encode_with(input, output, HexCase::Lower, false)
+}
+
+/// Attempt to convert the input byte slice into a hex string of the given `case`,
+/// optionally prefixed with `0x`
+///
+/// The hex string will be written to `output`, with the number of bytes written returned
+pub fn hex_encode_with(
+    input: impl AsRef<[u8]>,
+    output: &mut [u8],
+    case: HexCase,
+    prefix: bool,
 ) -> Result<usize, OutputBufferTooSmall> {
     let input = input.as_ref();
 
-    if input.len() * 2 > output.len() {
+    let prefix: &[u8] = if prefix { b"0x" } else { b"" };
+    let len = prefix.len() + input.len() * 2;
+    if len > output.len() {
         return Err(OutputBufferTooSmall);
     }
 
     const HEX_CHARS_LOWER: &[u8; 16] = b"0123456789abcdef";
+    const HEX_CHARS_UPPER: &[u8; 16] = b"0123456789ABCDEF";
 
-    let table = PIC::new(HEX_CHARS_LOWER).into_inner();
+    let table = match case {
+        HexCase::Lower => PIC::new(HEX_CHARS_LOWER).into_inner(),
+        HexCase::Upper => PIC::new(HEX_CHARS_UPPER).into_inner(),
+    };
+
+    let (head, output) = output.split_at_mut(prefix.len());
+    head.copy_from_slice(prefix);
     for (byte, out) in input.iter().zip(output.chunks_exact_mut(2)) {
         let high = *table.get(((byte & 0xf0) >> 4) as usize).ledger_unwrap();
         let low = *table.get((byte & 0xf) as usize).ledger_unwrap();
@@ -125,9 +177,73 @@ pub fn hex_encode(
         out[1] = low;
     }
 
-    Ok(input.len() * 2)
+    Ok(len)
+}
+
+/// Errors of [`hex_decode`]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum HexDecodeError {
+    /// Non hex character at the given position
+    InvalidChar { position: usize },
+    /// The number of hex digits is odd
+    OddLength,
+    /// `output` size was too small
+    OutputBufferTooSmall,
+}
+
+impl From<HexDecodeError> for ApduError {
+    fn from(e: HexDecodeError) -> Self {
+        match e {
+            HexDecodeError::OutputBufferTooSmall => ApduError::OutputBufferTooSmall,
+            _ => ApduError::DataInvalid,
+        }
+    }
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
 }
 
+/// Attempt to decode the input hex string, optionally prefixed with `0x` or `0X`.
+/// Both lower and upper case digits are accepted
+///
+/// The bytes will be written to `output`, with the number of bytes written returned
+pub fn hex_decode(input: impl AsRef<[u8]>, output: &mut [u8]) -> Result<usize, HexDecodeError> {
+    let input = input.as_ref();
+    let (offset, digits) = match input {
+        [b'0', b'x' | b'X', digits @ ..] => (2, digits),
+        digits => (0, digits),
+    };
+
+    if digits.len() % 2 != 0 {
+        return Err(HexDecodeError::OddLength);
+    }
+    let len = digits.len() / 2;
+    let output = output
+        .get_mut(..len)
+        .ok_or(HexDecodeError::OutputBufferTooSmall)?;
+
+    for (i, (pair, out)) in digits.chunks_exact(2).zip(output.iter_mut()).enumerate() {
+        let position = offset + i * 2;
+        let high = hex_value(pair[0]).ok_or(HexDecodeError::InvalidChar { position })?;
+        let low = hex_value(pair[1]).ok_or(HexDecodeError::InvalidChar {
+            position: position + 1,
+        })?;
+
+        *out = (high << 4) | low;
+    }
+
+    Ok(len)
+}
+
+const BS58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
 /// Attempt to convert the input byte slice into a base58 encoded string,
 /// written in `output`.
 ///
@@ -136,14 +252,19 @@ pub fn bs58_encode(
     input: impl AsRef<[u8]>,
     output: &mut [u8],
 ) -> Result<usize, OutputBufferTooSmall> {
-    const ALPHABET_ENCODE: &[u8; 58] =
-        b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
-    let table = PIC::new(ALPHABET_ENCODE).into_inner();
+    bs58_encode_iter(input.as_ref().iter().copied(), output)
+}
+
+/// Base58 encode the bytes of `input` in `output`, returning the number of bytes written
+fn bs58_encode_iter(
+    input: impl Iterator<Item = u8> + Clone,
+    output: &mut [u8],
+) -> Result<usize, OutputBufferTooSmall> {
+    let table = PIC::new(BS58_ALPHABET).into_inner();
 
-    let input = input.as_ref();
     let mut index = 0;
 
-    for &val in input.iter() {
+    for val in input.clone() {
         let mut carry = val as usize;
         for byte in output.get_mut(..index).ledger_unwrap() {
             carry += (*byte as usize) << 8;
@@ -158,7 +279,7 @@ pub fn bs58_encode(
         }
     }
 
-    for _ in input.iter().take_while(|v| **v == 0) {
+    for _ in input.take_while(|v| *v == 0) {
         let output = output.get_mut(index).ok_or(OutputBufferTooSmall)?;
 
         *output = 0;
@@ -172,3 +293,249 @@ pub fn bs58_encode(
     output.get_mut(..index).ledger_unwrap().reverse();
     Ok(index)
 }
+
+/// Errors of base58 and base58check encoding and decoding
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum Bs58Error {
+    /// Character outside of the base58 alphabet, at the given position
+    InvalidChar { position: usize },
+    /// `output` size was too small
+    OutputBufferTooSmall,
+    /// The decoded data is shorter than the checksum
+    MissingChecksum,
+    /// The checksum doesn't match the decoded data
+    InvalidChecksum,
+    /// The hasher failed computing the checksum
+    Hash,
+}
+
+impl From<OutputBufferTooSmall> for Bs58Error {
+    fn from(_: OutputBufferTooSmall) -> Self {
+        Self::OutputBufferTooSmall
+    }
+}
+
+impl From<Bs58Error> for ApduError {
+    fn from(e: Bs58Error) -> Self {
+        match e {
+            Bs58Error::OutputBufferTooSmall => ApduError::OutputBufferTooSmall,
+            _ => ApduError::DataInvalid,
+        }
+    }
+}
+
+/// Attempt to decode the input base58 string, written in `output`.
+///
+/// Will return the number of bytes written.
+pub fn bs58_decode(input: impl AsRef<[u8]>, output: &mut [u8]) -> Result<usize, Bs58Error> {
+    let table = PIC::new(BS58_ALPHABET).into_inner();
+
+    let input = input.as_ref();
+    let mut index = 0;
+
+    for (position, c) in input.iter().enumerate() {
+        let mut carry = table
+            .iter()
+            .position(|x| x == c)
+            .ok_or(Bs58Error::InvalidChar { position })?;
+
+        for byte in output.get_mut(..index).ledger_unwrap() {
+            carry += (*byte as usize) * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            let output = output
+                .get_mut(index)
+                .ok_or(Bs58Error::OutputBufferTooSmall)?;
+            *output = (carry & 0xff) as u8;
+            index += 1;
+            carry >>= 8;
+        }
+    }
+
+    for _ in input.iter().take_while(|c| **c == table[0]) {
+        let output = output
+            .get_mut(index)
+            .ok_or(Bs58Error::OutputBufferTooSmall)?;
+
+        *output = 0;
+        index += 1;
+    }
+
+    output.get_mut(..index).ledger_unwrap().reverse();
+    Ok(index)
+}
+
+/// Length of the base58check checksum
+pub const BS58CHECK_CHECKSUM_LEN: usize = 4;
+
+/// Compute the base58check checksum, the first 4 bytes of the double Sha256 of `data`
+fn bs58check_checksum(data: &[&[u8]]) -> Result<[u8; BS58CHECK_CHECKSUM_LEN], Bs58Error> {
+    use crate::hash::{Hasher, Sha256};
+
+    let mut hasher = Sha256::new().map_err(|_| Bs58Error::Hash)?;
+    for data in data {
+        hasher.update(data).map_err(|_| Bs58Error::Hash)?;
+    }
+    let hash = hasher.finalize().map_err(|_| Bs58Error::Hash)?;
+    let hash = Sha256::digest(&hash).map_err(|_| Bs58Error::Hash)?;
+
+    let mut checksum = [0; BS58CHECK_CHECKSUM_LEN];
+    checksum.copy_from_slice(&hash[..BS58CHECK_CHECKSUM_LEN]);
+    Ok(checksum)
+}
+
+#[inline(never)]
+/// Attempt to encode `version || payload || checksum` as base58, written in `output`.
+///
+/// The checksum is the first 4 bytes of the double Sha256 of `version || payload`.
+///
+/// Will return the number of bytes written.
+pub fn bs58check_encode(
+    version: impl AsRef<[u8]>,
+    payload: impl AsRef<[u8]>,
+    output: &mut [u8],
+) -> Result<usize, Bs58Error> {
+    let (version, payload) = (version.as_ref(), payload.as_ref());
+    let checksum = bs58check_checksum(&[version, payload])?;
+
+    let input = version.iter().chain(payload).chain(&checksum).copied();
+    Ok(bs58_encode_iter(input, output)?)
+}
+
+#[inline(never)]
+/// Attempt to decode the input base58check string, verifying its checksum.
+///
+/// `output` needs to be able to hold the checksum as well,
+/// but only the number of bytes of `version || payload` is returned.
+pub fn bs58check_decode(input: impl AsRef<[u8]>, output: &mut [u8]) -> Result<usize, Bs58Error> {
+    let written = bs58_decode(input, output)?;
+    let len = written
+        .checked_sub(BS58CHECK_CHECKSUM_LEN)
+        .ok_or(Bs58Error::MissingChecksum)?;
+
+    let (data, checksum) = output[..written].split_at(len);
+    if bs58check_checksum(&[data])? != checksum {
+        return Err(Bs58Error::InvalidChecksum);
+    }
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //from bitcoin core's base58_encode_decode.json
+    const BS58_VECTORS: &[(&str, &str)] = &[
+        ("", ""),
+        ("61", "2g"),
+        ("626262", "a3gV"),
+        ("636363", "aPEr"),
+        (
+            "73696d706c792061206c6f6e6720737472696e67",
+            "2cFupjhnEsSn59qHXstmK2ffpLv2",
+        ),
+        (
+            "00eb15231dfceb60925886b67d065299925915aeb172c06647",
+            "1NS17iag9jJgTHD1VXjvLCEnZuQ3rJDE9L",
+        ),
+        ("516b6fcd0f", "ABnLTmg"),
+        ("bf4f89001e670274dd", "3SEo3LWLoPntC"),
+        ("572e4794", "3EFU7m"),
+        ("ecac89cad93923c02321", "EJDM8drfXA6uyA"),
+        ("10c8511e", "Rt5zm"),
+        ("00000000000000000000", "1111111111"),
+    ];
+
+    #[test]
+    fn hex() {
+        let mut out = [0; 16];
+        let written = hex_encode([0xde, 0xad, 0x0b], &mut out).unwrap();
+        assert_eq!(&out[..written], b"dead0b");
+
+        let written = hex_encode_with([0xde, 0xad, 0x0b], &mut out, HexCase::Upper, true).unwrap();
+        assert_eq!(&out[..written], b"0xDEAD0B");
+        assert_eq!(
+            hex_encode_with([0xde, 0xad], &mut [0; 5], HexCase::Lower, true),
+            Err(OutputBufferTooSmall)
+        );
+
+        for input in ["dead0b", "DEAD0B", "0xDeAd0b", "0XDEAD0B"] {
+            let written = hex_decode(input, &mut out).unwrap();
+            assert_eq!(&out[..written], &[0xde, 0xad, 0x0b]);
+        }
+        assert_eq!(hex_decode("0x", &mut out), Ok(0));
+        assert_eq!(
+            hex_decode("0xabc", &mut out),
+            Err(HexDecodeError::OddLength)
+        );
+        assert_eq!(
+            hex_decode("0xabcg", &mut out),
+            Err(HexDecodeError::InvalidChar { position: 5 })
+        );
+        assert_eq!(
+            hex_decode("abcd", &mut [0; 1]),
+            Err(HexDecodeError::OutputBufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn bs58() {
+        for (data, encoded) in BS58_VECTORS {
+            let data = hex::decode(data).unwrap();
+            let mut out = [0; 64];
+
+            let written = bs58_encode(&data, &mut out).unwrap();
+            assert_eq!(&out[..written], encoded.as_bytes());
+
+            let written = bs58_decode(encoded, &mut out).unwrap();
+            assert_eq!(&out[..written], &data[..]);
+        }
+
+        assert_eq!(
+            bs58_decode("3SEo3LWLoPn0C", &mut [0; 16]),
+            Err(Bs58Error::InvalidChar { position: 11 })
+        );
+        assert_eq!(
+            bs58_decode("3SEo3LWLoPntC", &mut [0; 8]),
+            Err(Bs58Error::OutputBufferTooSmall)
+        );
+        assert_eq!(
+            bs58_decode("1111", &mut [0; 3]),
+            Err(Bs58Error::OutputBufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn bs58check() {
+        //genesis block coinbase address
+        const ADDRESS: &str = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+        let hash = hex::decode("62e907b15cbf27d5425399ebf6f0fb50ebb88f18").unwrap();
+
+        let mut out = [0; 64];
+        let written = bs58check_encode([0x00], &hash, &mut out).unwrap();
+        assert_eq!(&out[..written], ADDRESS.as_bytes());
+        assert_eq!(
+            bs58check_encode([0x00], &hash, &mut [0; 33]),
+            Err(Bs58Error::OutputBufferTooSmall)
+        );
+
+        let written = bs58check_decode(ADDRESS, &mut out).unwrap();
+        assert_eq!(written, 21);
+        assert_eq!(out[0], 0x00);
+        assert_eq!(&out[1..written], &hash[..]);
+
+        //last character changed
+        assert_eq!(
+            bs58check_decode("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb", &mut out),
+            Err(Bs58Error::InvalidChecksum)
+        );
+        assert_eq!(
+            bs58check_decode("3EFU", &mut out),
+            Err(Bs58Error::MissingChecksum)
+        );
+    }
+}
@@ -0,0 +1,239 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Base32 encoding and decoding, with the alphabet of [RFC 4648](https://www.rfc-editor.org/rfc/rfc4648)
+//!
+//! Used unpadded by Stellar and Algorand addresses
+
+use super::OutputBufferTooSmall;
+use crate::{ApduError, LedgerUnwrap, PIC};
+
+const CHARS: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+const PAD: u8 = b'=';
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum DecodeError {
+    /// Character outside of the alphabet, at the given position
+    InvalidChar { position: usize },
+    /// The length doesn't match a valid encoding
+    InvalidLength,
+    /// Missing or unexpected padding, or non-zero trailing bits
+    InvalidPadding,
+    /// `output` size was too small
+    OutputBufferTooSmall,
+}
+
+impl From<DecodeError> for ApduError {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::OutputBufferTooSmall => ApduError::OutputBufferTooSmall,
+            _ => ApduError::DataInvalid,
+        }
+    }
+}
+
+/// Length of the encoding of `len` bytes, optionally padded with `=` to a multiple of 8 characters
+pub const fn encoded_len(len: usize, padding: bool) -> usize {
+    if padding {
+        len.div_ceil(5) * 8
+    } else {
+        (len * 8).div_ceil(5)
+    }
+}
+
+#[inline(never)]
+/// Attempt to encode the input as base32, optionally padded, written in `output`.
+///
+/// Will return the number of bytes written.
+pub fn encode(
+    input: impl AsRef<[u8]>,
+    output: &mut [u8],
+    padding: bool,
+) -> Result<usize, OutputBufferTooSmall> {
+    let input = input.as_ref();
+    let len = encoded_len(input.len(), padding);
+    let output = output.get_mut(..len).ok_or(OutputBufferTooSmall)?;
+    let table = PIC::new(CHARS).into_inner();
+
+    for (chunk, out) in input.chunks(5).zip(output.chunks_mut(8)) {
+        let mut group = [0; 8];
+        group[3..3 + chunk.len()].copy_from_slice(chunk);
+        let n = u64::from_be_bytes(group);
+
+        //number of characters carrying data
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = if i < chars {
+                let v = (n >> (35 - 5 * i)) & 0x1f;
+                *table.get(v as usize).ledger_unwrap()
+            } else {
+                PAD
+            };
+        }
+    }
+
+    Ok(len)
+}
+
+#[inline(never)]
+/// Attempt to decode the input base32 string, written in `output`.
+///
+/// Padding is required if `padding` is set and rejected otherwise.
+///
+/// Will return the number of bytes written.
+pub fn decode(
+    input: impl AsRef<[u8]>,
+    output: &mut [u8],
+    padding: bool,
+) -> Result<usize, DecodeError> {
+    let input = input.as_ref();
+
+    let data = if padding {
+        if input.len() % 8 != 0 {
+            return Err(DecodeError::InvalidLength);
+        }
+
+        let pad = input.iter().rev().take_while(|c| **c == PAD).count();
+        let data = &input[..input.len() - pad];
+        //only the characters needed to complete the last group
+        if pad > 6 || (pad > 0 && data.len() % 8 + pad != 8) {
+            return Err(DecodeError::InvalidPadding);
+        }
+
+        data
+    } else {
+        input
+    };
+
+    //lengths that can't be produced by whole bytes
+    if matches!(data.len() % 8, 1 | 3 | 6) {
+        return Err(DecodeError::InvalidLength);
+    }
+
+    let len = data.len() * 5 / 8;
+    let output = output
+        .get_mut(..len)
+        .ok_or(DecodeError::OutputBufferTooSmall)?;
+    let table = PIC::new(CHARS).into_inner();
+
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut written = 0;
+    for (position, c) in data.iter().enumerate() {
+        let v = table
+            .iter()
+            .position(|x| x == c)
+            .ok_or(DecodeError::InvalidChar { position })?;
+
+        acc = (acc << 5) | v as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output[written] = (acc >> bits) as u8;
+            written += 1;
+        }
+        acc &= (1 << bits) - 1;
+    }
+
+    if acc != 0 {
+        return Err(DecodeError::InvalidPadding);
+    }
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //RFC 4648, section 10
+    const VECTORS: &[(&str, &str)] = &[
+        ("", ""),
+        ("f", "MY======"),
+        ("fo", "MZXQ===="),
+        ("foo", "MZXW6==="),
+        ("foob", "MZXW6YQ="),
+        ("fooba", "MZXW6YTB"),
+        ("foobar", "MZXW6YTBOI======"),
+    ];
+
+    #[test]
+    fn rfc4648() {
+        let mut out = [0; 16];
+        for (data, encoded) in VECTORS {
+            let written = encode(data, &mut out, true).unwrap();
+            assert_eq!(&out[..written], encoded.as_bytes());
+            let written = decode(encoded, &mut out, true).unwrap();
+            assert_eq!(&out[..written], data.as_bytes());
+
+            let unpadded = encoded.trim_end_matches('=');
+            let written = encode(data, &mut out, false).unwrap();
+            assert_eq!(&out[..written], unpadded.as_bytes());
+            let written = decode(unpadded, &mut out, false).unwrap();
+            assert_eq!(&out[..written], data.as_bytes());
+        }
+    }
+
+    #[test]
+    fn stellar() {
+        //account id of the all zeros ed25519 key
+        const ADDRESS: &str = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+
+        let mut out = [0; 35];
+        assert_eq!(decode(ADDRESS, &mut out, false), Ok(35));
+        assert_eq!(out[0], 6 << 3);
+        assert!(out[1..33].iter().all(|b| *b == 0));
+
+        let mut encoded = [0; 56];
+        assert_eq!(encode(out, &mut encoded, false), Ok(56));
+        assert_eq!(&encoded[..], ADDRESS.as_bytes());
+    }
+
+    #[test]
+    fn invalid() {
+        let mut out = [0; 8];
+        assert_eq!(
+            decode("MZXW6", &mut out, true),
+            Err(DecodeError::InvalidLength)
+        );
+        assert_eq!(
+            decode("MZX", &mut out, false),
+            Err(DecodeError::InvalidLength)
+        );
+        assert_eq!(
+            decode("MZXW6Y==", &mut out, true),
+            Err(DecodeError::InvalidLength)
+        );
+        assert_eq!(
+            decode("========", &mut out, true),
+            Err(DecodeError::InvalidPadding)
+        );
+        assert_eq!(
+            decode("mzxw6ytb", &mut out, false),
+            Err(DecodeError::InvalidChar { position: 0 })
+        );
+        //non-zero trailing bits
+        assert_eq!(
+            decode("MZ", &mut out, false),
+            Err(DecodeError::InvalidPadding)
+        );
+        assert_eq!(
+            decode("MZXW6YTBOI", &mut [0; 5], false),
+            Err(DecodeError::OutputBufferTooSmall)
+        );
+    }
+}
@@ -0,0 +1,292 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Base64 encoding and decoding, as defined in [RFC 4648](https://www.rfc-editor.org/rfc/rfc4648)
+
+use super::OutputBufferTooSmall;
+use crate::{ApduError, LedgerUnwrap, PIC};
+
+const STANDARD_CHARS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE_CHARS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+const PAD: u8 = b'=';
+
+/// Alphabet used to encode 6 bit values
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum Alphabet {
+    /// Standard alphabet, with `+` and `/`
+    Standard,
+    /// URL and filename safe alphabet, with `-` and `_`
+    UrlSafe,
+}
+
+impl Alphabet {
+    fn table(self) -> &'static [u8; 64] {
+        match self {
+            Alphabet::Standard => PIC::new(STANDARD_CHARS).into_inner(),
+            Alphabet::UrlSafe => PIC::new(URL_SAFE_CHARS).into_inner(),
+        }
+    }
+}
+
+/// Base64 flavour
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub struct Config {
+    pub alphabet: Alphabet,
+    /// Whether the output is padded with `=` to a multiple of 4 characters
+    pub padding: bool,
+}
+
+impl Config {
+    pub const STANDARD: Self = Self {
+        alphabet: Alphabet::Standard,
+        padding: true,
+    };
+
+    pub const STANDARD_NO_PAD: Self = Self {
+        alphabet: Alphabet::Standard,
+        padding: false,
+    };
+
+    pub const URL_SAFE: Self = Self {
+        alphabet: Alphabet::UrlSafe,
+        padding: true,
+    };
+
+    pub const URL_SAFE_NO_PAD: Self = Self {
+        alphabet: Alphabet::UrlSafe,
+        padding: false,
+    };
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum DecodeError {
+    /// Character outside of the alphabet, at the given position
+    InvalidChar { position: usize },
+    /// The length doesn't match a valid encoding
+    InvalidLength,
+    /// Missing or unexpected padding, or non-zero trailing bits
+    InvalidPadding,
+    /// `output` size was too small
+    OutputBufferTooSmall,
+}
+
+impl From<DecodeError> for ApduError {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::OutputBufferTooSmall => ApduError::OutputBufferTooSmall,
+            _ => ApduError::DataInvalid,
+        }
+    }
+}
+
+/// Length of the encoding of `len` bytes
+pub const fn encoded_len(len: usize, padding: bool) -> usize {
+    if padding {
+        len.div_ceil(3) * 4
+    } else {
+        (len * 8).div_ceil(6)
+    }
+}
+
+#[inline(never)]
+/// Attempt to encode the input as base64, written in `output`.
+///
+/// Will return the number of bytes written.
+pub fn encode(
+    input: impl AsRef<[u8]>,
+    output: &mut [u8],
+    config: Config,
+) -> Result<usize, OutputBufferTooSmall> {
+    let input = input.as_ref();
+    let len = encoded_len(input.len(), config.padding);
+    let output = output.get_mut(..len).ok_or(OutputBufferTooSmall)?;
+    let table = config.alphabet.table();
+
+    for (chunk, out) in input.chunks(3).zip(output.chunks_mut(4)) {
+        let mut group = [0; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let n = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+
+        //number of characters carrying data
+        let chars = chunk.len() + 1;
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = if i < chars {
+                let v = (n >> (18 - 6 * i)) & 0x3f;
+                *table.get(v as usize).ledger_unwrap()
+            } else {
+                PAD
+            };
+        }
+    }
+
+    Ok(len)
+}
+
+#[inline(never)]
+/// Attempt to decode the input base64 string, written in `output`.
+///
+/// Padding is required if `config` is padded and rejected otherwise.
+///
+/// Will return the number of bytes written.
+pub fn decode(
+    input: impl AsRef<[u8]>,
+    output: &mut [u8],
+    config: Config,
+) -> Result<usize, DecodeError> {
+    let input = input.as_ref();
+
+    let data = if config.padding {
+        if input.len() % 4 != 0 {
+            return Err(DecodeError::InvalidLength);
+        }
+
+        let pad = input.iter().rev().take_while(|c| **c == PAD).count();
+        let data = &input[..input.len() - pad];
+        //only the characters needed to complete the last group
+        if pad > 2 || (pad > 0 && data.len() % 4 + pad != 4) {
+            return Err(DecodeError::InvalidPadding);
+        }
+
+        data
+    } else {
+        input
+    };
+
+    if data.len() % 4 == 1 {
+        return Err(DecodeError::InvalidLength);
+    }
+
+    let len = data.len() * 6 / 8;
+    let output = output
+        .get_mut(..len)
+        .ok_or(DecodeError::OutputBufferTooSmall)?;
+    let table = config.alphabet.table();
+
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut written = 0;
+    for (position, c) in data.iter().enumerate() {
+        let v = table
+            .iter()
+            .position(|x| x == c)
+            .ok_or(DecodeError::InvalidChar { position })?;
+
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output[written] = (acc >> bits) as u8;
+            written += 1;
+        }
+    }
+
+    if acc & ((1 << bits) - 1) != 0 {
+        return Err(DecodeError::InvalidPadding);
+    }
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //RFC 4648, section 10
+    const VECTORS: &[(&str, &str)] = &[
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
+    #[test]
+    fn rfc4648() {
+        let mut out = [0; 16];
+        for (data, encoded) in VECTORS {
+            let written = encode(data, &mut out, Config::STANDARD).unwrap();
+            assert_eq!(&out[..written], encoded.as_bytes());
+            let written = decode(encoded, &mut out, Config::STANDARD).unwrap();
+            assert_eq!(&out[..written], data.as_bytes());
+
+            let unpadded = encoded.trim_end_matches('=');
+            let written = encode(data, &mut out, Config::STANDARD_NO_PAD).unwrap();
+            assert_eq!(&out[..written], unpadded.as_bytes());
+            let written = decode(unpadded, &mut out, Config::STANDARD_NO_PAD).unwrap();
+            assert_eq!(&out[..written], data.as_bytes());
+        }
+    }
+
+    #[test]
+    fn alphabets() {
+        let mut out = [0; 8];
+        let written = encode([0xfb, 0xff], &mut out, Config::STANDARD).unwrap();
+        assert_eq!(&out[..written], b"+/8=");
+        let written = encode([0xfb, 0xff], &mut out, Config::URL_SAFE_NO_PAD).unwrap();
+        assert_eq!(&out[..written], b"-_8");
+
+        assert_eq!(
+            decode("-_8=", &mut out, Config::STANDARD),
+            Err(DecodeError::InvalidChar { position: 0 })
+        );
+        assert_eq!(decode("-_8=", &mut out, Config::URL_SAFE), Ok(2));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut out = [0; 8];
+        assert_eq!(
+            decode("Zm9", &mut out, Config::STANDARD),
+            Err(DecodeError::InvalidLength)
+        );
+        assert_eq!(
+            decode("Zg", &mut out, Config::STANDARD),
+            Err(DecodeError::InvalidLength)
+        );
+        assert_eq!(
+            decode("Zm9vY", &mut out, Config::STANDARD_NO_PAD),
+            Err(DecodeError::InvalidLength)
+        );
+        assert_eq!(
+            decode("Z===", &mut out, Config::STANDARD),
+            Err(DecodeError::InvalidPadding)
+        );
+        assert_eq!(
+            decode("Zg==", &mut out, Config::STANDARD_NO_PAD),
+            Err(DecodeError::InvalidChar { position: 2 })
+        );
+        //non-zero trailing bits
+        assert_eq!(
+            decode("Zh==", &mut out, Config::STANDARD),
+            Err(DecodeError::InvalidPadding)
+        );
+        assert_eq!(
+            decode("Zm9vYmFy", &mut [0; 5], Config::STANDARD),
+            Err(DecodeError::OutputBufferTooSmall)
+        );
+        assert_eq!(
+            encode("foobar", &mut [0; 7], Config::STANDARD),
+            Err(OutputBufferTooSmall)
+        );
+    }
+}
@@ -13,6 +13,8 @@
 *  See the License for the specific language governing permissions and
 *  limitations under the License.
 ********************************************************************************/
+use super::convert_bits;
+use crate::ApduError;
 
 /// Integer in the range `0..32`
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Default, PartialOrd, Ord, Hash)]
@@ -29,7 +31,6 @@ impl u5 {
     ];
 
     /// Convert a `u8` to `u5` if in range, return `Error` otherwise
-    #[allow(dead_code)]
     pub fn try_from_u8(value: u8) -> Result<u5, ()> {
         if value > 31 {
             Err(())
@@ -52,6 +53,14 @@ impl u5 {
     pub fn to_char(self) -> char {
         Self::charset()[self.to_u8() as usize]
     }
+
+    /// Get the 5 bit value represented by the given lowercase char
+    pub fn from_char(c: u8) -> Option<u5> {
+        Self::charset()
+            .iter()
+            .position(|&x| x as u8 == c)
+            .map(|v| u5(v as u8))
+    }
 }
 
 impl From<u5> for u8 {
@@ -77,18 +86,39 @@ pub struct Bech32Writer<'b> {
 #[derive(Debug)]
 pub enum Bech32WriterError {
     OutputBufferTooSmall,
+    /// The value doesn't fit in 5 bits
+    InvalidValue,
 }
 
-impl<'b> Bech32Writer<'b> {
-    /// Generator coefficients
-    const GEN: &'static [u32; 5] = &[
-        0x3b6a_57b2,
-        0x2650_8e6d,
-        0x1ea1_19fa,
-        0x3d42_33dd,
-        0x2a14_62b3,
-    ];
+/// Generator coefficients
+const GEN: &[u32; 5] = &[
+    0x3b6a_57b2,
+    0x2650_8e6d,
+    0x1ea1_19fa,
+    0x3d42_33dd,
+    0x2a14_62b3,
+];
+
+///PIC'ed generator coefficients
+fn gen() -> &'static [u32; 5] {
+    crate::PIC::new(GEN).into_inner()
+}
+
+/// Feed a 5 bit value to the checksum
+fn polymod_step(mut chk: u32, v: u5) -> u32 {
+    let b = (chk >> 25) as u8;
+    chk = (chk & 0x01ff_ffff) << 5 ^ (u32::from(*v.as_ref()));
+
+    for (i, item) in gen().iter().enumerate() {
+        if (b >> i) & 1 == 1 {
+            chk ^= item;
+        }
+    }
+
+    chk
+}
 
+impl<'b> Bech32Writer<'b> {
     /// Creatw a new bech32 writer
     pub fn new(hrp: &str, out: &'b mut [u8], variant: Variant) -> Result<Self, Bech32WriterError> {
         let mut this = Self {
@@ -117,14 +147,7 @@ impl<'b> Bech32Writer<'b> {
     }
 
     fn polymod_step(&mut self, v: u5) {
-        let b = (self.chk >> 25) as u8;
-        self.chk = (self.chk & 0x01ff_ffff) << 5 ^ (u32::from(*v.as_ref()));
-
-        for (i, item) in Self::gen().iter().enumerate() {
-            if (b >> i) & 1 == 1 {
-                self.chk ^= item;
-            }
-        }
+        self.chk = polymod_step(self.chk, v);
     }
 
     //verify that out has enough space for the operation
@@ -137,11 +160,6 @@ impl<'b> Bech32Writer<'b> {
         }
     }
 
-    ///PIC'ed generator coefficients
-    fn gen() -> &'static [u32; 5] {
-        crate::PIC::new(Self::GEN).into_inner()
-    }
-
     /// Writes a single 5 bit value of the data part
     fn write_u5(&mut self, data: u5) -> Result<(), Bech32WriterError> {
         self.check_rem_out(1)?;
@@ -152,6 +170,12 @@ impl<'b> Bech32Writer<'b> {
         Ok(())
     }
 
+    /// Write a single 5 bit value as is, like the witness version of a segwit address
+    pub fn write_value(&mut self, value: u8) -> Result<(), Bech32WriterError> {
+        let value = u5::try_from_u8(value).map_err(|_| Bech32WriterError::InvalidValue)?;
+        self.write_u5(value)
+    }
+
     /// Write a chunck of data
     pub fn write(&mut self, data: impl AsRef<[u8]>) -> Result<(), Bech32WriterError> {
         // Amount of bits left over from last round, stored in buffer.
@@ -267,6 +291,260 @@ impl Variant {
             Variant::Bech32m => BECH32M_CONST,
         }
     }
+
+    fn from_constant(constant: u32) -> Option<Self> {
+        match constant {
+            BECH32_CONST => Some(Variant::Bech32),
+            BECH32M_CONST => Some(Variant::Bech32m),
+            _ => None,
+        }
+    }
+}
+
+/// Maximum length of a bech32 string, as defined in BIP173
+pub const MAX_LENGTH: usize = 90;
+
+/// Length of the checksum, in characters
+const CHECKSUM_LEN: usize = 6;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum DecodeError {
+    /// The string is longer than allowed
+    TooLong,
+    /// The `1` separator between HRP and data is missing
+    MissingSeparator,
+    /// The HRP is empty, too long or contains characters outside of `33..=126`
+    InvalidHrp,
+    /// The data part is shorter than the checksum
+    TooShort,
+    /// Lowercase and uppercase characters are mixed
+    MixedCase,
+    /// Character outside of the bech32 charset, at the given position
+    InvalidChar { position: usize },
+    /// Checksum doesn't match any [`Variant`]
+    InvalidChecksum,
+    /// The data doesn't convert to a whole number of bytes
+    InvalidPadding,
+    /// `out` is too small for the decoded data
+    OutputBufferTooSmall { expected: usize },
+}
+
+impl From<DecodeError> for ApduError {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::OutputBufferTooSmall { .. } => ApduError::OutputBufferTooSmall,
+            _ => ApduError::DataInvalid,
+        }
+    }
+}
+
+/// A validated bech32 string
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub struct Decoded<'s> {
+    hrp: &'s str,
+    //data characters, without the checksum
+    data: &'s [u8],
+    variant: Variant,
+}
+
+impl<'s> Decoded<'s> {
+    /// Retrieve the HRP, as found in the input
+    pub fn hrp(&self) -> &'s str {
+        self.hrp
+    }
+
+    /// Case insensitive comparison of the HRP
+    pub fn hrp_eq(&self, hrp: &str) -> bool {
+        self.hrp.eq_ignore_ascii_case(hrp)
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Number of 5 bit values in the data part
+    pub fn data_len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Number of bytes of the data part once converted to 8 bits
+    pub fn decoded_len(&self) -> usize {
+        self.data.len() * 5 / 8
+    }
+
+    fn u5_iter(&self) -> impl Iterator<Item = u5> + 's {
+        //the charset was checked when decoding
+        self.data
+            .iter()
+            .map(|c| u5::from_char(c.to_ascii_lowercase()).unwrap_or_default())
+    }
+
+    /// Write the 5 bit values of the data part into `out`, returning the number written
+    pub fn data_u5_into(&self, out: &mut [u8]) -> Result<usize, DecodeError> {
+        let out = out
+            .get_mut(..self.data.len())
+            .ok_or(DecodeError::OutputBufferTooSmall {
+                expected: self.data.len(),
+            })?;
+
+        for (o, v) in out.iter_mut().zip(self.u5_iter()) {
+            *o = v.to_u8();
+        }
+
+        Ok(self.data.len())
+    }
+
+    #[inline(never)]
+    /// Convert the data part to bytes with [`convert_bits::<5, 8>`](convert_bits), into `out`
+    ///
+    /// Returns the number of bytes written
+    pub fn data_into(&self, out: &mut [u8]) -> Result<usize, DecodeError> {
+        let expected = self.decoded_len();
+        let out = out
+            .get_mut(..expected)
+            .ok_or(DecodeError::OutputBufferTooSmall { expected })?;
+
+        //8 values of 5 bits are exactly 5 bytes,
+        // so we can convert in chunks, with only the last one being padded
+        let mut values = self.u5_iter();
+        for out in out.chunks_mut(5) {
+            let mut chunk = [0; 8];
+            let mut len = 0;
+            for (c, v) in chunk.iter_mut().zip(values.by_ref()) {
+                *c = v.to_u8();
+                len += 1;
+            }
+
+            let mut bytes = [0; 5];
+            convert_bits::<5, 8>(&chunk[..len], &mut bytes, false)
+                .map_err(|_| DecodeError::InvalidPadding)?;
+            out.copy_from_slice(&bytes[..out.len()]);
+        }
+
+        //a single value left over is not enough for a byte
+        // and can't be padding either
+        if values.next().is_some() {
+            return Err(DecodeError::InvalidPadding);
+        }
+
+        Ok(expected)
+    }
+}
+
+/// Split `s` into HRP and data parts, validating lengths, charset and case
+fn split(s: &str, max_len: usize) -> Result<(&str, &[u8]), DecodeError> {
+    if s.len() > max_len {
+        return Err(DecodeError::TooLong);
+    }
+
+    let sep = s.rfind('1').ok_or(DecodeError::MissingSeparator)?;
+    let (hrp, data) = (&s[..sep], &s.as_bytes()[sep + 1..]);
+
+    if hrp.is_empty() || hrp.len() > 83 || !hrp.bytes().all(|c| (33..=126).contains(&c)) {
+        return Err(DecodeError::InvalidHrp);
+    }
+    if data.len() < CHECKSUM_LEN {
+        return Err(DecodeError::TooShort);
+    }
+
+    let (mut lower, mut upper) = (false, false);
+    for c in s.bytes() {
+        lower |= c.is_ascii_lowercase();
+        upper |= c.is_ascii_uppercase();
+    }
+    if lower && upper {
+        return Err(DecodeError::MixedCase);
+    }
+
+    for (i, c) in data.iter().enumerate() {
+        if u5::from_char(c.to_ascii_lowercase()).is_none() {
+            return Err(DecodeError::InvalidChar {
+                position: sep + 1 + i,
+            });
+        }
+    }
+
+    Ok((hrp, data))
+}
+
+/// Compute the checksum residue of the given HRP and data characters
+fn polymod(hrp: &str, data: &[u8]) -> u32 {
+    let mut chk = 1;
+
+    for b in hrp.bytes() {
+        chk = polymod_step(chk, u5(b.to_ascii_lowercase() >> 5));
+    }
+    chk = polymod_step(chk, u5(0));
+    for b in hrp.bytes() {
+        chk = polymod_step(chk, u5(b.to_ascii_lowercase() & 0x1f));
+    }
+    for c in data {
+        chk = polymod_step(
+            chk,
+            u5::from_char(c.to_ascii_lowercase()).unwrap_or_default(),
+        );
+    }
+
+    chk
+}
+
+/// Decode and validate the bech32 or bech32m string `s`, of at most [`MAX_LENGTH`] characters
+///
+/// The [`Variant`] is detected from the checksum
+pub fn decode(s: &str) -> Result<Decoded<'_>, DecodeError> {
+    decode_with_limit(s, MAX_LENGTH)
+}
+
+#[inline(never)]
+/// Same as [`decode`], with a custom maximum length
+pub fn decode_with_limit(s: &str, max_len: usize) -> Result<Decoded<'_>, DecodeError> {
+    let (hrp, data) = split(s, max_len)?;
+
+    let variant = Variant::from_constant(polymod(hrp, data)).ok_or(DecodeError::InvalidChecksum)?;
+
+    Ok(Decoded {
+        hrp,
+        data: &data[..data.len() - CHECKSUM_LEN],
+        variant,
+    })
+}
+
+#[inline(never)]
+/// Attempt to locate the error in a string that failed with [`DecodeError::InvalidChecksum`]
+///
+/// Returns the position of the character which, if substituted,
+/// makes the checksum valid for either [`Variant`].
+///
+/// Only single character errors in the data part can be located;
+/// the substitution is not guaranteed to be what was intended
+pub fn locate_error(s: &str) -> Option<usize> {
+    let (hrp, data) = split(s, usize::MAX).ok()?;
+    let residue = polymod(hrp, data);
+
+    //the checksum is linear, so the effect of an error `e` at `k` positions from the end
+    // is the combination of the effect of each of its bits
+    let mut basis: [u32; 5] = core::array::from_fn(|j| polymod_step(0, u5(1 << j)));
+
+    for k in 0..data.len() {
+        for e in 1u8..32 {
+            let delta = (0..5)
+                .filter(|j| (e >> j) & 1 == 1)
+                .fold(0, |acc, j| acc ^ basis[j]);
+
+            let fixed = residue ^ delta;
+            if fixed == BECH32_CONST || fixed == BECH32M_CONST {
+                return Some(s.len() - 1 - k);
+            }
+        }
+
+        for b in basis.iter_mut() {
+            *b = polymod_step(*b, u5(0));
+        }
+    }
+
+    None
 }
 
 #[cfg(test)]
@@ -480,4 +758,115 @@ mod tests {
 
         assert_eq!(BECH32M_ENCODINGS[6].to_lowercase(), encoded.to_lowercase());
     }
+
+    const BECH32_INPUTS: [&[u8]; 5] = [
+        b"",
+        b"",
+        b"testing number 1",
+        b"I can count up to twenty two and tie my laces up",
+        b"shake my hand upstream to join the crew",
+    ];
+
+    const BECH32M_INPUTS: [&[u8]; 7] = [
+        b"",
+        b"",
+        b"",
+        b"I can count up to twenty two and tie my laces up",
+        b"shake my hand upstream to join the crew",
+        b"shake my hand upstream to join the crew",
+        b"Yo",
+    ];
+
+    fn check_decode(encoded: &str, hrp: &str, input: &[u8], variant: Variant) {
+        let decoded = decode(encoded).expect("unable to decode");
+        assert!(decoded.hrp_eq(hrp));
+        assert_eq!(decoded.variant(), variant);
+
+        let mut out = [0; MAX_LENGTH];
+        let written = decoded.data_into(&mut out).expect("unable to convert");
+        assert_eq!(&out[..written], input);
+    }
+
+    #[test]
+    fn decode_bech32() {
+        for ((encoded, hrp), input) in BECH32_ENCODINGS.iter().zip(HRPS).zip(BECH32_INPUTS) {
+            check_decode(encoded, hrp, input, Variant::Bech32);
+        }
+
+        check_decode(EXPECTED, HRP, &INPUT, Variant::Bech32);
+    }
+
+    #[test]
+    fn decode_bech32m() {
+        for ((encoded, hrp), input) in BECH32M_ENCODINGS
+            .iter()
+            .zip(BECH32M_HRPS)
+            .zip(BECH32M_INPUTS)
+        {
+            check_decode(encoded, hrp, input, Variant::Bech32m);
+        }
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert_eq!(decode("pzry9x0s0muk"), Err(DecodeError::MissingSeparator));
+        assert_eq!(decode("1pzry9x0s0muk"), Err(DecodeError::InvalidHrp));
+        assert_eq!(decode("\x7f1axkwrx"), Err(DecodeError::InvalidHrp));
+        assert_eq!(
+            decode("x1b4n0q5v"),
+            Err(DecodeError::InvalidChar { position: 2 })
+        );
+        assert_eq!(decode("li1dgmt3"), Err(DecodeError::TooShort));
+        assert_eq!(decode("A1G7SGD8"), Err(DecodeError::InvalidChecksum));
+        assert_eq!(decode("A12uEL5L"), Err(DecodeError::MixedCase));
+        let long = "an84characterslonghumanreadablepartthatcontainsthenumber1andtheexcludedcharactersbio1569pvx";
+        assert_eq!(decode(long), Err(DecodeError::TooLong));
+        assert_eq!(decode_with_limit(long, 100), Err(DecodeError::InvalidHrp));
+
+        let decoded = decode(EXPECTED).unwrap();
+        assert_eq!(
+            decoded.data_into(&mut [0; 4]),
+            Err(DecodeError::OutputBufferTooSmall { expected: 15 })
+        );
+    }
+
+    #[test]
+    fn decode_padding() {
+        for values in [&[1][..], &[0, 1], &[0; 9]] {
+            let mut out = [0; 32];
+            let mut writer = Bech32Writer::new("a", &mut out, Variant::Bech32m).unwrap();
+            for v in values {
+                writer.write_u5(u5(*v)).unwrap();
+            }
+            let written = writer.finalize().unwrap();
+
+            let encoded = std::str::from_utf8(&out[..written]).unwrap();
+            let decoded = decode(encoded).unwrap();
+            assert_eq!(decoded.data_len(), values.len());
+            assert_eq!(
+                decoded.data_into(&mut [0; 32]),
+                Err(DecodeError::InvalidPadding)
+            );
+        }
+    }
+
+    #[test]
+    fn error_location() {
+        let mut encoded = std::string::String::from(BECH32_ENCODINGS[2]);
+        for position in [7, 20, encoded.len() - 1] {
+            let original = encoded.clone();
+            let c = if &encoded[position..=position] == "q" {
+                "p"
+            } else {
+                "q"
+            };
+            encoded.replace_range(position..=position, c);
+
+            assert_eq!(decode(&encoded), Err(DecodeError::InvalidChecksum));
+            assert_eq!(locate_error(&encoded), Some(position));
+            encoded = original;
+        }
+
+        assert_eq!(locate_error(BECH32M_ENCODINGS[5]), None);
+    }
 }
@@ -0,0 +1,370 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Bitcoin addresses of secp256k1 public keys
+//!
+//! Supports legacy P2PKH, P2SH-wrapped P2WPKH, native P2WPKH
+//! and key-path only P2TR (BIP-341) addresses
+
+use super::{
+    bech32::{Bech32Writer, Bech32WriterError, Variant},
+    bs58check_encode, Bs58Error,
+};
+use crate::{
+    crypto::{ecfp256::PublicKey, Curve},
+    hash::{Hasher, Ripemd160, Sha256},
+    math,
+    signature::SECP256K1_N,
+    ApduError,
+};
+
+/// Tag of the BIP-341 tweak hash
+const TAP_TWEAK_TAG: &[u8] = b"TapTweak";
+
+/// Length of a HASH160 digest
+pub const HASH160_LEN: usize = 20;
+
+/// Version bytes and human readable part of a network
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub struct Network {
+    /// Version prefix of base58check P2PKH addresses
+    pub p2pkh_version: &'static [u8],
+    /// Version prefix of base58check P2SH addresses
+    pub p2sh_version: &'static [u8],
+    /// Human readable part of segwit addresses
+    pub hrp: &'static str,
+}
+
+impl Network {
+    pub const MAINNET: Self = Self {
+        p2pkh_version: &[0x00],
+        p2sh_version: &[0x05],
+        hrp: "bc",
+    };
+
+    pub const TESTNET: Self = Self {
+        p2pkh_version: &[0x6f],
+        p2sh_version: &[0xc4],
+        hrp: "tb",
+    };
+
+    pub const REGTEST: Self = Self {
+        p2pkh_version: &[0x6f],
+        p2sh_version: &[0xc4],
+        hrp: "bcrt",
+    };
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum AddressError {
+    /// The public key is not a secp256k1 key in a supported form
+    InvalidKey,
+    /// The taproot tweak is not a valid scalar or couldn't be applied
+    InvalidTweak,
+    /// `out` size was too small
+    OutputBufferTooSmall,
+    /// The hasher failed
+    Hash,
+    /// The base58check encoding failed
+    Bs58(Bs58Error),
+}
+
+impl From<Bs58Error> for AddressError {
+    fn from(e: Bs58Error) -> Self {
+        match e {
+            Bs58Error::OutputBufferTooSmall => Self::OutputBufferTooSmall,
+            Bs58Error::Hash => Self::Hash,
+            e => Self::Bs58(e),
+        }
+    }
+}
+
+impl From<Bech32WriterError> for AddressError {
+    fn from(_: Bech32WriterError) -> Self {
+        Self::OutputBufferTooSmall
+    }
+}
+
+impl From<AddressError> for ApduError {
+    fn from(e: AddressError) -> Self {
+        match e {
+            AddressError::OutputBufferTooSmall => ApduError::OutputBufferTooSmall,
+            _ => ApduError::DataInvalid,
+        }
+    }
+}
+
+/// Compute the RIPEMD160 of the Sha256 of `data`
+pub fn hash160(data: &[u8]) -> Result<[u8; HASH160_LEN], AddressError> {
+    let sha = Sha256::digest(data).map_err(|_| AddressError::Hash)?;
+    Ripemd160::digest(&sha).map_err(|_| AddressError::Hash)
+}
+
+/// Retrieve the compressed form of a secp256k1 public key
+fn compressed(key: &PublicKey) -> Result<PublicKey, AddressError> {
+    if !matches!(key.curve(), Curve::Secp256K1) {
+        return Err(AddressError::InvalidKey);
+    }
+
+    let mut key = *key;
+    key.compress().map_err(|_| AddressError::InvalidKey)?;
+    match key.as_ref() {
+        [0x02 | 0x03, ..] if key.len() == 33 => Ok(key),
+        _ => Err(AddressError::InvalidKey),
+    }
+}
+
+/// Encode a segwit address of the given witness version and program, written in `out`
+fn segwit_encode(
+    hrp: &str,
+    version: u8,
+    program: &[u8],
+    out: &mut [u8],
+) -> Result<usize, AddressError> {
+    let variant = if version == 0 {
+        Variant::Bech32
+    } else {
+        Variant::Bech32m
+    };
+
+    let mut writer = Bech32Writer::new(hrp, out, variant)?;
+    writer.write_value(version)?;
+    writer.write(program)?;
+    Ok(writer.finalize()?)
+}
+
+#[inline(never)]
+/// Encode the P2PKH address of the given key, written in `out`.
+///
+/// The address always commits to the compressed key.
+///
+/// Will return the number of bytes written.
+pub fn p2pkh(key: &PublicKey, network: &Network, out: &mut [u8]) -> Result<usize, AddressError> {
+    let key = compressed(key)?;
+    let hash = hash160(key.as_ref())?;
+
+    Ok(bs58check_encode(network.p2pkh_version, hash, out)?)
+}
+
+#[inline(never)]
+/// Encode the P2SH address wrapping the P2WPKH script of the given key, written in `out`.
+///
+/// Will return the number of bytes written.
+pub fn p2sh_p2wpkh(
+    key: &PublicKey,
+    network: &Network,
+    out: &mut [u8],
+) -> Result<usize, AddressError> {
+    let key = compressed(key)?;
+
+    //redeem script: OP_0 PUSH20 <hash160(key)>
+    let mut script = [0; 2 + HASH160_LEN];
+    script[0] = 0x00;
+    script[1] = HASH160_LEN as u8;
+    script[2..].copy_from_slice(&hash160(key.as_ref())?);
+    let hash = hash160(&script)?;
+
+    Ok(bs58check_encode(network.p2sh_version, hash, out)?)
+}
+
+#[inline(never)]
+/// Encode the native P2WPKH address of the given key, written in `out`.
+///
+/// Will return the number of bytes written.
+pub fn p2wpkh(key: &PublicKey, network: &Network, out: &mut [u8]) -> Result<usize, AddressError> {
+    let key = compressed(key)?;
+    let hash = hash160(key.as_ref())?;
+
+    segwit_encode(network.hrp, 0, &hash, out)
+}
+
+#[inline(never)]
+/// Compute the BIP-341 output key of the given internal key, without script tree.
+///
+/// The key must be the uncompressed secp256k1 point, as returned by `SecretKey::public`.
+///
+/// Will return the x coordinate of the output key.
+pub fn taproot_output_key(key: &PublicKey) -> Result<[u8; 32], AddressError> {
+    let bytes = key.as_ref();
+    if !matches!(key.curve(), Curve::Secp256K1) || bytes.len() != 65 || bytes[0] != 0x04 {
+        return Err(AddressError::InvalidKey);
+    }
+    let x = &bytes[1..33];
+    let odd = bytes[64] & 1 == 1;
+
+    //t = sha256(sha256(tag) || sha256(tag) || x)
+    let tag = Sha256::digest(TAP_TWEAK_TAG).map_err(|_| AddressError::Hash)?;
+    let mut hasher = Sha256::new().map_err(|_| AddressError::Hash)?;
+    hasher.update(&tag).map_err(|_| AddressError::Hash)?;
+    hasher.update(&tag).map_err(|_| AddressError::Hash)?;
+    hasher.update(x).map_err(|_| AddressError::Hash)?;
+    let tweak = hasher.finalize().map_err(|_| AddressError::Hash)?;
+
+    if tweak >= SECP256K1_N {
+        return Err(AddressError::InvalidTweak);
+    }
+
+    //the internal key is taken with even y, so for an odd key
+    // we compute -(-P + tG) = P - tG, which has the same x
+    let tweak = if odd {
+        let mut negated = [0; 32];
+        math::sub(&mut negated, &SECP256K1_N, &tweak).map_err(|_| AddressError::InvalidTweak)?;
+        negated
+    } else {
+        tweak
+    };
+
+    let mut output = *key;
+    output
+        .tweak_add(&tweak)
+        .map_err(|_| AddressError::InvalidTweak)?;
+
+    let mut x = [0; 32];
+    x.copy_from_slice(&output.as_ref()[1..33]);
+    Ok(x)
+}
+
+#[inline(never)]
+/// Encode the key-path only P2TR address of the given key, written in `out`.
+///
+/// The key must be the uncompressed secp256k1 point, as returned by `SecretKey::public`.
+///
+/// Will return the number of bytes written.
+pub fn p2tr(key: &PublicKey, network: &Network, out: &mut [u8]) -> Result<usize, AddressError> {
+    let output_key = taproot_output_key(key)?;
+
+    segwit_encode(network.hrp, 1, &output_key, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //m/84'/0'/0'/0/0 of the "abandon ... about" mnemonic, BIP-84
+    const BIP84_KEY: &str = "0430d54fd0dd420a6e5f8d3624f5f3482cae350f79d5f0753bf5beef9c2d91af3c04717159ce0828a7f686c2c7510b7aa7d4c685ebc2051642ccbebc7099e2f679";
+    //m/49'/1'/0'/0/0 of the "abandon ... about" mnemonic, BIP-49
+    const BIP49_KEY: &str = "04a1af804ac108a8a51782198c2d034b28bf90c8803f5a53f76276fa69a4eae77f3010ba699877871e188285d8c36e320eb08311d8aecf27ff8971bc7fde240bfd";
+    //m/86'/0'/0'/0/0 of the "abandon ... about" mnemonic, BIP-86
+    const BIP86_KEY: &str = "04cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc1157e6f540ae051df90f5e37da8e812aedc999df252737d4f67f8180d85791a3834";
+    //same x as above, with odd y
+    const BIP86_KEY_ODD: &str = "04cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc1158190abf51fae206f0a1c825717ed512366620dad8c82b09807e7f27986e5c3fb";
+
+    fn from_hex(hex: &str) -> PublicKey {
+        PublicKey::from_bytes(Curve::Secp256K1, &hex::decode(hex).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn p2pkh_and_p2wpkh() {
+        let key = from_hex(BIP84_KEY);
+        let mut out = [0; 64];
+
+        let written = p2wpkh(&key, &Network::MAINNET, &mut out).unwrap();
+        assert_eq!(
+            &out[..written],
+            b"bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+
+        let written = p2pkh(&key, &Network::MAINNET, &mut out).unwrap();
+        assert_eq!(&out[..written], b"1JaUQDVNRdhfNsVncGkXedaPSM5Gc54Hso");
+
+        //compressed keys are accepted as well
+        let mut compressed = key;
+        compressed.compress().unwrap();
+        let written = p2pkh(&compressed, &Network::MAINNET, &mut out).unwrap();
+        assert_eq!(&out[..written], b"1JaUQDVNRdhfNsVncGkXedaPSM5Gc54Hso");
+    }
+
+    #[test]
+    fn p2sh_wrapped() {
+        let key = from_hex(BIP49_KEY);
+        let mut out = [0; 64];
+
+        let written = p2sh_p2wpkh(&key, &Network::TESTNET, &mut out).unwrap();
+        assert_eq!(&out[..written], b"2Mww8dCYPUpKHofjgcXcBCEGmniw9CoaiD2");
+    }
+
+    #[test]
+    fn taproot() {
+        const ADDRESS: &[u8] = b"bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr";
+        let mut out = [0; 64];
+
+        let key = from_hex(BIP86_KEY);
+        assert_eq!(
+            &taproot_output_key(&key).unwrap()[..],
+            &hex::decode("a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c")
+                .unwrap()[..]
+        );
+        let written = p2tr(&key, &Network::MAINNET, &mut out).unwrap();
+        assert_eq!(&out[..written], ADDRESS);
+
+        //only the x coordinate of the internal key matters
+        let written = p2tr(&from_hex(BIP86_KEY_ODD), &Network::MAINNET, &mut out).unwrap();
+        assert_eq!(&out[..written], ADDRESS);
+
+        let mut compressed = key;
+        compressed.compress().unwrap();
+        assert_eq!(
+            p2tr(&compressed, &Network::MAINNET, &mut out),
+            Err(AddressError::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn mock_derivation() {
+        use crate::crypto::{bip32::BIP32Path, ecfp256::SecretKey, hdw, Mode};
+
+        hdw::set_mnemonic("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about");
+
+        let path = BIP32Path::<5>::new([0x8000_0054, 0x8000_0000, 0x8000_0000, 0, 0]).unwrap();
+        let secret = SecretKey::new(Mode::BIP32, Curve::Secp256K1, path);
+        assert_eq!(
+            secret.public().unwrap().as_ref(),
+            from_hex(BIP84_KEY).as_ref()
+        );
+
+        let path = BIP32Path::<5>::new([0x8000_0056, 0x8000_0000, 0x8000_0000, 0, 0]).unwrap();
+        let secret = SecretKey::new(Mode::BIP32, Curve::Secp256K1, path);
+        let mut out = [0; 64];
+        let written = p2tr(&secret.public().unwrap(), &Network::MAINNET, &mut out).unwrap();
+        assert_eq!(
+            &out[..written],
+            b"bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let key = from_hex(BIP84_KEY);
+
+        assert_eq!(
+            p2wpkh(&key, &Network::MAINNET, &mut [0; 20]),
+            Err(AddressError::OutputBufferTooSmall)
+        );
+        assert_eq!(
+            p2pkh(&key, &Network::MAINNET, &mut [0; 20]),
+            Err(AddressError::OutputBufferTooSmall)
+        );
+
+        use crate::crypto::{bip32::BIP32Path, ecfp256::SecretKey, Mode};
+        let path = BIP32Path::<5>::new([0x8000_002c, 0x8000_0000, 0x8000_0000, 0, 0]).unwrap();
+        let secret = SecretKey::new(Mode::BIP32, Curve::Ed25519, path);
+        assert_eq!(
+            p2wpkh(&secret.public().unwrap(), &Network::MAINNET, &mut [0; 64]),
+            Err(AddressError::InvalidKey)
+        );
+    }
+}
@@ -14,6 +14,8 @@
 *  limitations under the License.
 ********************************************************************************/
 
+use super::der::{self, DerError};
+
 #[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
 pub enum ConvertError<const R: usize, const S: usize> {
     /// The DER prefix (at index 0) found was different than the expected 0x30
@@ -36,39 +38,74 @@ pub enum ConvertError<const R: usize, const S: usize> {
     },
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum ConvertUnsignedError {
+    /// The signature was not a valid DER `SEQUENCE { r INTEGER, s INTEGER }`
+    InvalidDER(DerError),
+    /// R or S didn't fit in the given output
+    OutputBufferTooSmall { expected: usize },
+}
+
+impl From<DerError> for ConvertUnsignedError {
+    fn from(e: DerError) -> Self {
+        Self::InvalidDER(e)
+    }
+}
+
+const MINPAYLOADLEN: usize = 1;
+const MAXPAYLOADLEN: usize = 33;
+
+/// Read an INTEGER whose encoded length is within the expected range,
+/// returning its value as encoded
+fn read_integer<'b, const R: usize, const S: usize>(
+    payload: &mut der::Reader<'b>,
+    invalid_marker: fn(u8) -> ConvertError<R, S>,
+    invalid_len: fn(usize) -> ConvertError<R, S>,
+) -> Result<&'b [u8], ConvertError<R, S>> {
+    let payload_range = core::ops::RangeInclusive::new(MINPAYLOADLEN, MAXPAYLOADLEN);
+
+    let len = match payload.remaining() {
+        [der::INTEGER, len, ..] => *len as usize,
+        [tag, ..] if *tag != der::INTEGER => return Err(invalid_marker(*tag)),
+        _ => return Err(ConvertError::TooShort),
+    };
+    //checked before reading, so the only length form accepted is the short one
+    if !payload_range.contains(&len) {
+        return Err(invalid_len(len));
+    }
+
+    //with the header validated the only possible failure is missing data
+    payload
+        .expect(der::INTEGER)
+        .map_err(|_| ConvertError::TooShort)
+}
+
 #[inline(never)]
 /// Converts a DER encoded signature into a RSV encoded signture
+///
+/// R and S are written as encoded, including their sign byte if any,
+/// left padded with zeros, and their encoded lengths are returned.
+/// Any data following R and S is ignored
 pub fn convert_der_to_rs<const R: usize, const S: usize>(
     sig: &[u8],
     out_r: &mut [u8; R],
     out_s: &mut [u8; S],
 ) -> Result<(usize, usize), ConvertError<R, S>> {
-    const MINPAYLOADLEN: usize = 1;
-    const MAXPAYLOADLEN: usize = 33;
-
-    let payload_range = core::ops::RangeInclusive::new(MINPAYLOADLEN, MAXPAYLOADLEN);
     // https://github.com/libbitcoin/libbitcoin-system/wiki/ECDSA-and-DER-Signatures#serialised-der-signature-sequence
-    // 0                [1 byte]   - DER Prefix (0x30)
-    // 1                [1 byte]   - Payload len
-    // 2                [1 byte]   - R Marker. Always 02
-    // 3                [1 byte]   - R Len                      RLEN
-    // ROFFSET ...      [.?. byte] - R                          ROFFSET
-    // ROFFSET+RLEN     [1 byte]   - S Marker. Always 02
-    // ROFFSET+RLEN+1   [1 byte]   - S Length                   SLEN
-    // ROFFSET+RLEN+2   [.?. byte] - S                          SOFFSET
+    // SEQUENCE {
+    //   r INTEGER,
+    //   s INTEGER,
+    // }
 
     //check that we have at least the DER prefix and the payload len
-    if sig.len() < 2 {
-        return Err(ConvertError::TooShort);
-    }
-
-    //check DER prefix
-    if sig[0] != 0x30 {
-        return Err(ConvertError::InvalidDERPrefix(sig[0]));
-    }
+    let payload_len = match sig {
+        [der::SEQUENCE, len, ..] => *len as usize,
+        [prefix, _, ..] => return Err(ConvertError::InvalidDERPrefix(*prefix)),
+        _ => return Err(ConvertError::TooShort),
+    };
 
     //check payload len size
-    let payload_len = sig[1] as usize;
     let min_payload_len = 2 + MINPAYLOADLEN + 2 + MINPAYLOADLEN;
     let max_payload_len = 2 + MAXPAYLOADLEN + 2 + MAXPAYLOADLEN;
     if payload_len < min_payload_len || payload_len > max_payload_len {
@@ -79,52 +116,181 @@ pub fn convert_der_to_rs<const R: usize, const S: usize>(
         });
     }
 
-    //check that the input slice is at least as long as the encoded len
-    if sig.len() - 2 < payload_len {
-        return Err(ConvertError::TooShort);
-    }
+    let mut payload = der::Reader::new(sig)
+        .sequence()
+        .map_err(|_| ConvertError::TooShort)?;
 
     //retrieve R
-    if sig[2] != 0x02 {
-        return Err(ConvertError::InvalidRMarker(sig[2]));
-    }
+    let r = read_integer(
+        &mut payload,
+        ConvertError::InvalidRMarker,
+        ConvertError::InvalidRLen,
+    )?;
 
-    let r_len = sig[3] as usize;
-    if !payload_range.contains(&r_len) {
-        return Err(ConvertError::InvalidRLen(r_len));
-    }
+    //retrieve S
+    let s = read_integer(
+        &mut payload,
+        ConvertError::InvalidSMarker,
+        ConvertError::InvalidSLen,
+    )?;
 
-    if R < r_len {
+    if R < r.len() || S < s.len() {
         return Err(ConvertError::TooShort);
     }
 
-    //sig[4], after DER, after Payload, after marker after len
-    let r = &sig[4..][..r_len];
+    //fill everything with 0 first
+    out_r.fill(0);
+    out_s.fill(0);
 
-    //retrieve S
-    if sig[4 + r_len] != 0x02 {
-        return Err(ConvertError::InvalidSMarker(sig[4 + r_len]));
-    }
+    //populate from the back
+    out_r[R - r.len()..].copy_from_slice(r);
+    out_s[S - s.len()..].copy_from_slice(s);
 
-    let s_len = sig[4 + r_len + 1] as usize;
-    if !payload_range.contains(&s_len) {
-        return Err(ConvertError::InvalidSLen(s_len));
-    }
+    Ok((r.len(), s.len()))
+}
 
-    if S < s_len {
-        return Err(ConvertError::TooShort);
-    }
+#[inline(never)]
+/// Converts a strict DER encoded signature into R and S, without their sign byte
+///
+/// Unlike [`convert_der_to_rs`], R and S must be minimally encoded non-negative
+/// integers and nothing can follow them in the SEQUENCE, so R and S fit
+/// in scalar sized buffers, where they are written left padded with zeros.
+/// Their lengths are returned. Any data following the DER signature is ignored
+pub fn convert_der_to_rs_unsigned<const R: usize, const S: usize>(
+    sig: &[u8],
+    out_r: &mut [u8; R],
+    out_s: &mut [u8; S],
+) -> Result<(usize, usize), ConvertUnsignedError> {
+    let mut payload = der::Reader::new(sig).sequence()?;
+    let r = payload.uint()?;
+    let s = payload.uint()?;
+    payload.finish()?;
 
-    //after r (4 + r_len), after marker, after len
-    let s = &sig[4 + r_len + 2..][..s_len];
+    if R < r.len() {
+        return Err(ConvertUnsignedError::OutputBufferTooSmall { expected: r.len() });
+    }
+    if S < s.len() {
+        return Err(ConvertUnsignedError::OutputBufferTooSmall { expected: s.len() });
+    }
 
-    //fill everything with 0 first
     out_r.fill(0);
     out_s.fill(0);
 
-    //populate from the back
-    out_r[R - r_len..][..r_len].copy_from_slice(r);
-    out_s[S - s_len..][..s_len].copy_from_slice(s);
+    out_r[R - r.len()..].copy_from_slice(r);
+    out_s[S - s.len()..].copy_from_slice(s);
+
+    Ok((r.len(), s.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ParserError;
+
+    const SIG: &str = "3045022100e8d7b4dcfcd7e8a4a3d9f1d61b10c5b1c4b7e4f2a1b6d9a3c5e7f9b1d3e5a7c9022041c5f3e2d7b9a1c3e5f7092b4d6f8a1c3e5b7d9f1a3c5e7092b4d6f8a1c3e5b7";
+
+    #[test]
+    fn der_to_rs() {
+        let sig = hex::decode(SIG).unwrap();
 
-    Ok((r_len, s_len))
+        //the sign byte of R is kept
+        let mut r = [0; 33];
+        let mut s = [0; 33];
+        let (r_len, s_len) = convert_der_to_rs(&sig, &mut r, &mut s).unwrap();
+        assert_eq!((r_len, s_len), (33, 32));
+        assert_eq!(&r[..], &sig[4..37]);
+        assert_eq!(&s[1..], &sig[39..71]);
+        assert_eq!(s[0], 0);
+
+        //trailing data is ignored, even inside the sequence
+        let mut with_trailing = sig.clone();
+        with_trailing[1] += 1;
+        with_trailing.push(0x01);
+        assert!(convert_der_to_rs(&with_trailing, &mut r, &mut s).is_ok());
+
+        //redundant leading zeros are kept
+        let padded = [0x30, 0x07, 0x02, 0x02, 0x00, 0x01, 0x02, 0x01, 0x01];
+        assert_eq!(convert_der_to_rs(&padded, &mut r, &mut s).unwrap(), (2, 1));
+        assert_eq!(&r[31..], &[0x00, 0x01]);
+
+        let mut bad_prefix = sig.clone();
+        bad_prefix[0] = 0x31;
+        assert!(matches!(
+            convert_der_to_rs(&bad_prefix, &mut r, &mut s),
+            Err(ConvertError::InvalidDERPrefix(0x31))
+        ));
+
+        let mut bad_s = sig.clone();
+        bad_s[37] = 0x03;
+        assert!(matches!(
+            convert_der_to_rs(&bad_s, &mut r, &mut s),
+            Err(ConvertError::InvalidSMarker(0x03))
+        ));
+
+        let mut bad_r_len = sig.clone();
+        bad_r_len[3] = 0x81;
+        assert!(matches!(
+            convert_der_to_rs(&bad_r_len, &mut r, &mut s),
+            Err(ConvertError::InvalidRLen(0x81))
+        ));
+
+        assert!(matches!(
+            convert_der_to_rs(&sig[..40], &mut r, &mut s),
+            Err(ConvertError::TooShort)
+        ));
+
+        //R doesn't fit with its sign byte
+        let mut small_r = [0; 32];
+        assert!(matches!(
+            convert_der_to_rs(&sig, &mut small_r, &mut s),
+            Err(ConvertError::TooShort)
+        ));
+    }
+
+    #[test]
+    fn der_to_rs_unsigned() {
+        let sig = hex::decode(SIG).unwrap();
+
+        let mut r = [0; 32];
+        let mut s = [0; 32];
+        let (r_len, s_len) = convert_der_to_rs_unsigned(&sig, &mut r, &mut s).unwrap();
+        assert_eq!((r_len, s_len), (32, 32));
+        assert_eq!(&r[..], &sig[5..37]);
+        assert_eq!(&s[..], &sig[39..71]);
+
+        //data after the signature is ignored
+        let mut after = sig.clone();
+        after.push(0x01);
+        assert!(convert_der_to_rs_unsigned(&after, &mut r, &mut s).is_ok());
+
+        //but not inside of it
+        let mut with_trailing = sig.clone();
+        with_trailing[1] += 1;
+        with_trailing.push(0x01);
+        assert_eq!(
+            convert_der_to_rs_unsigned(&with_trailing, &mut r, &mut s),
+            Err(ConvertUnsignedError::InvalidDER(DerError::TrailingData))
+        );
+
+        let padded = [0x30, 0x07, 0x02, 0x02, 0x00, 0x01, 0x02, 0x01, 0x01];
+        assert_eq!(
+            convert_der_to_rs_unsigned(&padded, &mut r, &mut s),
+            Err(ConvertUnsignedError::InvalidDER(
+                DerError::NonMinimalInteger
+            ))
+        );
+
+        assert!(matches!(
+            convert_der_to_rs_unsigned(&sig[..40], &mut r, &mut s),
+            Err(ConvertUnsignedError::InvalidDER(DerError::Parser(
+                ParserError::UnexpectedEof { .. }
+            )))
+        ));
+
+        let mut small_r = [0; 16];
+        assert_eq!(
+            convert_der_to_rs_unsigned(&sig, &mut small_r, &mut s),
+            Err(ConvertUnsignedError::OutputBufferTooSmall { expected: 32 })
+        );
+    }
 }
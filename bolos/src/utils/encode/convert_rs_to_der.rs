@@ -0,0 +1,75 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+use super::{der, OutputBufferTooSmall};
+
+/// Maximum length of a DER signature with 32 bytes R and S
+pub const MAX_DER_SIGNATURE_LEN: usize = 2 + 2 * (2 + 33);
+
+#[inline(never)]
+/// Converts big endian R and S into a DER encoded signature, written in `out`
+///
+/// Leading zeros are stripped and the sign byte is added where needed.
+///
+/// Will return the number of bytes written
+pub fn convert_rs_to_der(
+    r: &[u8],
+    s: &[u8],
+    out: &mut [u8],
+) -> Result<usize, OutputBufferTooSmall> {
+    // SEQUENCE {
+    //   r INTEGER,
+    //   s INTEGER,
+    // }
+    let mut writer = der::Writer::new(out);
+    writer.sequence(|w| {
+        w.uint(r)?;
+        w.uint(s)
+    })?;
+
+    Ok(writer.written())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert_der_to_rs_unsigned;
+
+    const SIG: &str = "3045022100e8d7b4dcfcd7e8a4a3d9f1d61b10c5b1c4b7e4f2a1b6d9a3c5e7f9b1d3e5a7c9022041c5f3e2d7b9a1c3e5f7092b4d6f8a1c3e5b7d9f1a3c5e7092b4d6f8a1c3e5b7";
+
+    #[test]
+    fn rs_to_der() {
+        let sig = hex::decode(SIG).unwrap();
+
+        let mut r = [0; 32];
+        let mut s = [0; 32];
+        convert_der_to_rs_unsigned(&sig, &mut r, &mut s).unwrap();
+
+        let mut out = [0; MAX_DER_SIGNATURE_LEN];
+        let written = convert_rs_to_der(&r, &s, &mut out).unwrap();
+        assert_eq!(&out[..written], &sig[..]);
+
+        //leading zeros are stripped
+        let mut small = [0; 32];
+        small[31] = 1;
+        let written = convert_rs_to_der(&small, &small, &mut out).unwrap();
+        assert_eq!(&out[..written], &[0x30, 6, 2, 1, 1, 2, 1, 1]);
+
+        assert_eq!(
+            convert_rs_to_der(&r, &s, &mut [0; 70]),
+            Err(OutputBufferTooSmall)
+        );
+    }
+}
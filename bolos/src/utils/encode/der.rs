@@ -0,0 +1,648 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Minimal ASN.1 DER reader and writer
+//!
+//! Only single byte tags are supported, which covers all the universal types
+//! used in keys and certificates as well as context specific tags up to 30.
+//! Lengths must use the minimal encoding and integers must have no redundant leading bytes.
+
+use crate::{parser::ParserError, ApduError, OutputBufferTooSmall};
+
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OID: u8 = 0x06;
+pub const SEQUENCE: u8 = 0x30;
+pub const SET: u8 = 0x31;
+
+const CONSTRUCTED: u8 = 0x20;
+const CONTEXT: u8 = 0x80;
+const HIGH_TAG: u8 = 0x1f;
+
+/// Tag of the explicit (constructed) context specific element `[n]`
+///
+/// # Panics
+/// If `n` is above 30, as it would need a multi byte tag
+pub const fn context(n: u8) -> u8 {
+    assert!(n < HIGH_TAG, "context tag number above 30");
+    CONTEXT | CONSTRUCTED | n
+}
+
+/// Tag of the implicit (primitive) context specific element `[n]`
+///
+/// # Panics
+/// If `n` is above 30, as it would need a multi byte tag
+pub const fn context_primitive(n: u8) -> u8 {
+    assert!(n < HIGH_TAG, "context tag number above 30");
+    CONTEXT | n
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Errors that can occur when reading DER
+pub enum DerError {
+    /// Error reading the underlying data
+    Parser(ParserError),
+    /// A different element than the one requested was found
+    UnexpectedTag { expected: u8, found: u8 },
+    /// Multi byte tags are not supported
+    UnsupportedTag(u8),
+    /// The length was indefinite or not minimally encoded
+    InvalidLength,
+    /// The integer was empty or had redundant leading bytes
+    NonMinimalInteger,
+    /// A negative integer was found where an unsigned one was expected
+    NegativeInteger,
+    /// The number of unused bits was invalid or the unused bits were not zero
+    InvalidBitString,
+    /// The object identifier was empty or not minimally encoded
+    InvalidOid,
+    /// NULL had a non-empty value
+    InvalidNull,
+    /// There were more elements than expected
+    TrailingData,
+}
+
+impl From<ParserError> for DerError {
+    fn from(e: ParserError) -> Self {
+        Self::Parser(e)
+    }
+}
+
+impl From<DerError> for ApduError {
+    fn from(_: DerError) -> Self {
+        ApduError::DataInvalid
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// A single Tag-Length-Value element, borrowing its value from the input
+pub struct Tlv<'b> {
+    pub tag: u8,
+    pub value: &'b [u8],
+}
+
+#[inline(never)]
+/// Read the first element in `input`, returning the remaining input and the element
+pub fn tlv(input: &[u8]) -> Result<(&[u8], Tlv<'_>), DerError> {
+    let (&tag, rem) = input
+        .split_first()
+        .ok_or(ParserError::UnexpectedEof { needed: 2 })?;
+    if tag & HIGH_TAG == HIGH_TAG {
+        return Err(DerError::UnsupportedTag(tag));
+    }
+
+    let (&first, rem) = rem
+        .split_first()
+        .ok_or(ParserError::UnexpectedEof { needed: 1 })?;
+
+    let (rem, len) = match first {
+        0..=0x7f => (rem, first as usize),
+        //indefinite length
+        0x80 => return Err(DerError::InvalidLength),
+        _ => {
+            let len_of_len = (first & 0x7f) as usize;
+            let (rem, len_bytes) = crate::parser::take(rem, len_of_len)?;
+            if len_bytes[0] == 0 {
+                return Err(DerError::InvalidLength);
+            }
+            if len_of_len > core::mem::size_of::<usize>() {
+                return Err(ParserError::LengthOverflow.into());
+            }
+
+            let len = len_bytes
+                .iter()
+                .fold(0usize, |len, &byte| (len << 8) | byte as usize);
+            //short form should have been used
+            if len < 0x80 {
+                return Err(DerError::InvalidLength);
+            }
+
+            (rem, len)
+        }
+    };
+
+    let (rem, value) = crate::parser::take(rem, len)?;
+    Ok((rem, Tlv { tag, value }))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Content of a BIT STRING
+pub struct BitString<'b> {
+    /// Number of unused bits in the last byte
+    pub unused_bits: u8,
+    pub bytes: &'b [u8],
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Encoded OBJECT IDENTIFIER, to be compared against known identifiers
+pub struct Oid<'b>(pub &'b [u8]);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Reader over a series of DER elements, like the content of a SEQUENCE
+pub struct Reader<'b> {
+    data: &'b [u8],
+}
+
+impl<'b> Reader<'b> {
+    pub fn new(data: &'b [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Returns the encoded elements that are yet to be read
+    pub fn remaining(&self) -> &'b [u8] {
+        self.data
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Ensure all the elements have been read
+    pub fn finish(self) -> Result<(), DerError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(DerError::TrailingData)
+        }
+    }
+
+    /// Retrieve the tag of the next element, if any
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Read the next element, whatever its tag
+    pub fn any(&mut self) -> Result<Tlv<'b>, DerError> {
+        let (rem, tlv) = tlv(self.data)?;
+        self.data = rem;
+
+        Ok(tlv)
+    }
+
+    /// Read the next element, ensuring it has the given tag, and return its value
+    pub fn expect(&mut self, tag: u8) -> Result<&'b [u8], DerError> {
+        let (rem, tlv) = tlv(self.data)?;
+        if tlv.tag != tag {
+            return Err(DerError::UnexpectedTag {
+                expected: tag,
+                found: tlv.tag,
+            });
+        }
+        self.data = rem;
+
+        Ok(tlv.value)
+    }
+
+    /// Read the next element if it has the given tag
+    pub fn optional(&mut self, tag: u8) -> Result<Option<&'b [u8]>, DerError> {
+        if self.peek_tag() == Some(tag) {
+            self.expect(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Read a SEQUENCE, returning a reader over its elements
+    pub fn sequence(&mut self) -> Result<Reader<'b>, DerError> {
+        self.expect(SEQUENCE).map(Reader::new)
+    }
+
+    /// Read the explicit context specific element `[n]`,
+    /// returning a reader over its content
+    pub fn context(&mut self, n: u8) -> Result<Reader<'b>, DerError> {
+        self.expect(context(n)).map(Reader::new)
+    }
+
+    /// Read the explicit context specific element `[n]` if present
+    pub fn optional_context(&mut self, n: u8) -> Result<Option<Reader<'b>>, DerError> {
+        self.optional(context(n)).map(|v| v.map(Reader::new))
+    }
+
+    #[inline(never)]
+    /// Read an INTEGER, returning its two's complement big endian value
+    pub fn integer(&mut self) -> Result<&'b [u8], DerError> {
+        let value = self.expect(INTEGER)?;
+
+        match value {
+            [] => Err(DerError::NonMinimalInteger),
+            //the first byte only repeats the sign of the second
+            [0x00, next, ..] if next & 0x80 == 0 => Err(DerError::NonMinimalInteger),
+            [0xff, next, ..] if next & 0x80 != 0 => Err(DerError::NonMinimalInteger),
+            _ => Ok(value),
+        }
+    }
+
+    /// Read a non-negative INTEGER, returning its big endian value without the sign byte
+    pub fn uint(&mut self) -> Result<&'b [u8], DerError> {
+        match self.integer()? {
+            [first, ..] if first & 0x80 != 0 => Err(DerError::NegativeInteger),
+            [0x00, rest @ ..] if !rest.is_empty() => Ok(rest),
+            value => Ok(value),
+        }
+    }
+
+    /// Read an OCTET STRING
+    pub fn octet_string(&mut self) -> Result<&'b [u8], DerError> {
+        self.expect(OCTET_STRING)
+    }
+
+    #[inline(never)]
+    /// Read a BIT STRING
+    pub fn bit_string(&mut self) -> Result<BitString<'b>, DerError> {
+        let value = self.expect(BIT_STRING)?;
+
+        let (&unused_bits, bytes) = value.split_first().ok_or(DerError::InvalidBitString)?;
+        let valid = match bytes.last() {
+            None => unused_bits == 0,
+            //the unused bits must be zero
+            Some(&last) => unused_bits < 8 && last & ((1 << unused_bits) - 1) == 0,
+        };
+        if !valid {
+            return Err(DerError::InvalidBitString);
+        }
+
+        Ok(BitString { unused_bits, bytes })
+    }
+
+    #[inline(never)]
+    /// Read an OBJECT IDENTIFIER
+    pub fn oid(&mut self) -> Result<Oid<'b>, DerError> {
+        let value = self.expect(OID)?;
+
+        //must end with a complete subidentifier
+        match value.last() {
+            Some(&last) if last & 0x80 == 0 => {}
+            _ => return Err(DerError::InvalidOid),
+        }
+
+        //subidentifiers can't start with a 0 group
+        let mut first_of_subid = true;
+        for &byte in value {
+            if first_of_subid && byte == 0x80 {
+                return Err(DerError::InvalidOid);
+            }
+            first_of_subid = byte & 0x80 == 0;
+        }
+
+        Ok(Oid(value))
+    }
+
+    /// Read a NULL
+    pub fn null(&mut self) -> Result<(), DerError> {
+        match self.expect(NULL)? {
+            [] => Ok(()),
+            _ => Err(DerError::InvalidNull),
+        }
+    }
+}
+
+/// Number of bytes the tag and length of a value of `len` bytes take
+pub const fn header_len(len: usize) -> usize {
+    if len < 0x80 {
+        2
+    } else {
+        2 + (usize::BITS - len.leading_zeros()).div_ceil(8) as usize
+    }
+}
+
+/// Write the tag and length in `out`, which is `header_len(len)` bytes long
+fn write_header(out: &mut [u8], tag: u8, len: usize) {
+    out[0] = tag;
+
+    if len < 0x80 {
+        out[1] = len as u8;
+    } else {
+        let len_of_len = out.len() - 2;
+        out[1] = 0x80 | len_of_len as u8;
+        out[2..].copy_from_slice(&len.to_be_bytes()[core::mem::size_of::<usize>() - len_of_len..]);
+    }
+}
+
+/// Writes DER elements into a caller provided buffer
+pub struct Writer<'o> {
+    out: &'o mut [u8],
+    written: usize,
+}
+
+impl<'o> Writer<'o> {
+    pub fn new(out: &'o mut [u8]) -> Self {
+        Self { out, written: 0 }
+    }
+
+    /// Number of bytes written so far
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    fn reserve(&mut self, len: usize) -> Result<&mut [u8], OutputBufferTooSmall> {
+        let out = self
+            .out
+            .get_mut(self.written..)
+            .and_then(|out| out.get_mut(..len))
+            .ok_or(OutputBufferTooSmall)?;
+        self.written += len;
+
+        Ok(out)
+    }
+
+    /// Reserve the tag, length and value of an element all at once,
+    /// so nothing is written if it doesn't fit, and return the value to fill
+    fn element(&mut self, tag: u8, len: usize) -> Result<&mut [u8], OutputBufferTooSmall> {
+        let header_len = header_len(len);
+        let out = self.reserve(header_len + len)?;
+        let (header, value) = out.split_at_mut(header_len);
+        write_header(header, tag, len);
+
+        Ok(value)
+    }
+
+    /// Write an element with the given tag and value
+    pub fn tlv(&mut self, tag: u8, value: &[u8]) -> Result<(), OutputBufferTooSmall> {
+        self.element(tag, value.len())?.copy_from_slice(value);
+
+        Ok(())
+    }
+
+    #[inline(never)]
+    /// Write a non-negative INTEGER from its big endian value,
+    /// stripping redundant zeros and adding the sign byte if needed
+    pub fn uint(&mut self, be: &[u8]) -> Result<(), OutputBufferTooSmall> {
+        let zeros = be.iter().take_while(|&&b| b == 0).count();
+        let value = match &be[zeros..] {
+            [] => &[0][..],
+            value => value,
+        };
+
+        let sign = (value[0] & 0x80 != 0) as usize;
+        let out = self.element(INTEGER, sign + value.len())?;
+        out[0] = 0;
+        out[sign..].copy_from_slice(value);
+
+        Ok(())
+    }
+
+    /// Write an OCTET STRING
+    pub fn octet_string(&mut self, data: &[u8]) -> Result<(), OutputBufferTooSmall> {
+        self.tlv(OCTET_STRING, data)
+    }
+
+    /// Write a BIT STRING with no unused bits
+    pub fn bit_string(&mut self, data: &[u8]) -> Result<(), OutputBufferTooSmall> {
+        let out = self.element(BIT_STRING, 1 + data.len())?;
+        out[0] = 0;
+        out[1..].copy_from_slice(data);
+
+        Ok(())
+    }
+
+    /// Write an already encoded OBJECT IDENTIFIER
+    pub fn oid(&mut self, oid: Oid<'_>) -> Result<(), OutputBufferTooSmall> {
+        self.tlv(OID, oid.0)
+    }
+
+    /// Write a NULL
+    pub fn null(&mut self) -> Result<(), OutputBufferTooSmall> {
+        self.tlv(NULL, &[])
+    }
+
+    #[inline(never)]
+    /// Write a constructed element with the given tag, whose content is written by `f`
+    ///
+    /// The content is written first and then moved to make room for the header if needed
+    pub fn constructed<F>(&mut self, tag: u8, f: F) -> Result<(), OutputBufferTooSmall>
+    where
+        F: FnOnce(&mut Writer<'_>) -> Result<(), OutputBufferTooSmall>,
+    {
+        let start = self.written;
+        //space for the short header
+        let out = self.out.get_mut(start + 2..).ok_or(OutputBufferTooSmall)?;
+
+        let mut inner = Writer::new(out);
+        f(&mut inner)?;
+        let len = inner.written();
+
+        let header_len = header_len(len);
+        if start + header_len + len > self.out.len() {
+            return Err(OutputBufferTooSmall);
+        }
+        self.out
+            .copy_within(start + 2..start + 2 + len, start + header_len);
+
+        write_header(&mut self.out[start..start + header_len], tag, len);
+        self.written += header_len + len;
+
+        Ok(())
+    }
+
+    /// Write a SEQUENCE, whose elements are written by `f`
+    pub fn sequence<F>(&mut self, f: F) -> Result<(), OutputBufferTooSmall>
+    where
+        F: FnOnce(&mut Writer<'_>) -> Result<(), OutputBufferTooSmall>,
+    {
+        self.constructed(SEQUENCE, f)
+    }
+
+    /// Write the explicit context specific element `[n]`, whose content is written by `f`
+    pub fn context<F>(&mut self, n: u8, f: F) -> Result<(), OutputBufferTooSmall>
+    where
+        F: FnOnce(&mut Writer<'_>) -> Result<(), OutputBufferTooSmall>,
+    {
+        self.constructed(context(n), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //1.2.840.10045.2.1
+    const EC_PUBLIC_KEY: Oid = Oid(&[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01]);
+    //1.3.132.0.10
+    const SECP256K1: Oid = Oid(&[0x2b, 0x81, 0x04, 0x00, 0x0a]);
+
+    #[test]
+    fn lengths() {
+        assert_eq!(
+            tlv(&[0x04, 0x02, 0xaa, 0xbb, 0xcc]),
+            Ok((
+                &[0xcc][..],
+                Tlv {
+                    tag: OCTET_STRING,
+                    value: &[0xaa, 0xbb]
+                }
+            ))
+        );
+        assert_eq!(tlv(&[0x04, 0x80]), Err(DerError::InvalidLength));
+        assert_eq!(tlv(&[0x04, 0x81, 0x02, 0, 0]), Err(DerError::InvalidLength));
+        assert_eq!(tlv(&[0x04, 0x82, 0x00, 0x80]), Err(DerError::InvalidLength));
+        assert_eq!(
+            tlv(&[0x04, 0x03, 0x00]),
+            Err(DerError::Parser(ParserError::UnexpectedEof { needed: 2 }))
+        );
+        assert_eq!(
+            tlv(&[0x1f, 0x01, 0x00]),
+            Err(DerError::UnsupportedTag(0x1f))
+        );
+
+        let mut long = std::vec![0x04, 0x81, 0x80];
+        long.resize(3 + 0x80, 0xaa);
+        assert_eq!(tlv(&long).unwrap().1.value.len(), 0x80);
+    }
+
+    #[test]
+    fn integers() {
+        let int = |bytes: &[u8]| {
+            let mut input = std::vec![INTEGER, bytes.len() as u8];
+            input.extend_from_slice(bytes);
+            let mut reader = Reader::new(&input);
+            reader.uint().map(|v| v.to_vec())
+        };
+
+        assert_eq!(int(&[0x00]), Ok(std::vec![0x00]));
+        assert_eq!(int(&[0x7f]), Ok(std::vec![0x7f]));
+        assert_eq!(int(&[0x00, 0x80]), Ok(std::vec![0x80]));
+        assert_eq!(int(&[]), Err(DerError::NonMinimalInteger));
+        assert_eq!(int(&[0x00, 0x7f]), Err(DerError::NonMinimalInteger));
+        assert_eq!(int(&[0xff, 0x80]), Err(DerError::NonMinimalInteger));
+        assert_eq!(int(&[0x80]), Err(DerError::NegativeInteger));
+
+        let mut out = [0; 8];
+        let mut writer = Writer::new(&mut out);
+        writer.uint(&[0x00, 0x00, 0x80]).unwrap();
+        writer.uint(&[0x00]).unwrap();
+        let written = writer.written();
+        assert_eq!(&out[..written], &[0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn failed_writes_leave_writer_untouched() {
+        let mut out = [0; 6];
+        let mut writer = Writer::new(&mut out);
+        writer.null().unwrap();
+
+        //the headers would fit, but not the values
+        assert_eq!(writer.uint(&[0x80, 0x01]), Err(OutputBufferTooSmall));
+        assert_eq!(writer.bit_string(&[0x01, 0x02]), Err(OutputBufferTooSmall));
+        assert_eq!(writer.octet_string(&[0; 3]), Err(OutputBufferTooSmall));
+        assert_eq!(writer.written(), 2);
+
+        writer.uint(&[0x01, 0x02]).unwrap();
+        assert_eq!(writer.written(), 6);
+        assert_eq!(out, [0x05, 0x00, 0x02, 0x02, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn bit_strings_and_oids() {
+        let mut reader = Reader::new(&[0x03, 0x02, 0x04, 0xf0]);
+        assert_eq!(
+            reader.bit_string(),
+            Ok(BitString {
+                unused_bits: 4,
+                bytes: &[0xf0]
+            })
+        );
+        let mut reader = Reader::new(&[0x03, 0x02, 0x04, 0xf8]);
+        assert_eq!(reader.bit_string(), Err(DerError::InvalidBitString));
+        let mut reader = Reader::new(&[0x03, 0x01, 0x01]);
+        assert_eq!(reader.bit_string(), Err(DerError::InvalidBitString));
+
+        let mut reader = Reader::new(&[0x06, 0x02, 0x2b, 0x81]);
+        assert_eq!(reader.oid(), Err(DerError::InvalidOid));
+        let mut reader = Reader::new(&[0x06, 0x03, 0x2b, 0x80, 0x01]);
+        assert_eq!(reader.oid(), Err(DerError::InvalidOid));
+    }
+
+    #[test]
+    fn subject_public_key_info() {
+        let mut point = [0x04; 65];
+        point[1..]
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b = i as u8);
+
+        //SubjectPublicKeyInfo ::= SEQUENCE { algorithm AlgorithmIdentifier, subjectPublicKey BIT STRING }
+        let mut out = [0; 100];
+        let mut writer = Writer::new(&mut out);
+        writer
+            .sequence(|w| {
+                w.sequence(|w| {
+                    w.oid(EC_PUBLIC_KEY)?;
+                    w.oid(SECP256K1)
+                })?;
+                w.bit_string(&point)
+            })
+            .unwrap();
+        let written = writer.written();
+        assert_eq!(written, 88);
+        assert_eq!(&out[..4], &[0x30, 0x56, 0x30, 0x10]);
+
+        let mut reader = Reader::new(&out[..written]);
+        let mut spki = reader.sequence().unwrap();
+        reader.finish().unwrap();
+
+        let mut algorithm = spki.sequence().unwrap();
+        assert_eq!(algorithm.oid(), Ok(EC_PUBLIC_KEY));
+        assert_eq!(algorithm.oid(), Ok(SECP256K1));
+        algorithm.finish().unwrap();
+
+        let key = spki.bit_string().unwrap();
+        assert_eq!(key.unused_bits, 0);
+        assert_eq!(key.bytes, &point[..]);
+        assert_eq!(spki.optional_context(0), Ok(None));
+        spki.finish().unwrap();
+    }
+
+    #[test]
+    fn context_tags() {
+        assert_eq!(context(0), 0xa0);
+        assert_eq!(context(30), 0xbe);
+        assert_eq!(context_primitive(30), 0x9e);
+    }
+
+    #[test]
+    #[should_panic]
+    fn context_tag_above_30() {
+        context(31);
+    }
+
+    #[test]
+    fn long_sequence() {
+        let data = [0xaa; 200];
+
+        let mut out = [0; 206];
+        let mut writer = Writer::new(&mut out);
+        writer.context(1, |w| w.octet_string(&data)).unwrap();
+        assert_eq!(writer.written(), 206);
+        assert_eq!(&out[..6], &[0xa1, 0x81, 0xcb, 0x04, 0x81, 0xc8]);
+
+        let mut reader = Reader::new(&out);
+        let mut inner = reader.context(1).unwrap();
+        assert_eq!(inner.octet_string(), Ok(&data[..]));
+
+        let mut out = [0; 205];
+        let mut writer = Writer::new(&mut out);
+        assert_eq!(
+            writer.context(1, |w| w.octet_string(&data)),
+            Err(OutputBufferTooSmall)
+        );
+    }
+}
@@ -0,0 +1,227 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Ethereum addresses of secp256k1 public keys
+//!
+//! The address is the last 20 bytes of the Keccak-256 of the uncompressed key,
+//! shown with the mixed-case checksum of [EIP-55](https://eips.ethereum.org/EIPS/eip-55),
+//! or the chain-aware one of [EIP-1191](https://eips.ethereum.org/EIPS/eip-1191)
+
+use super::{hex_encode, OutputBufferTooSmall};
+use crate::{
+    crypto::{ecfp256::PublicKey, Curve},
+    hash::{Hasher, Keccak},
+    ApduError,
+};
+
+/// Length of an address
+pub const ADDRESS_LEN: usize = 20;
+
+/// Length of the `0x` prefixed checksummed hex address
+pub const CHECKSUM_ADDRESS_LEN: usize = 2 + 2 * ADDRESS_LEN;
+
+//digits of the largest u64
+const MAX_CHAIN_ID_DIGITS: usize = 20;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum AddressError {
+    /// The public key is not an uncompressed secp256k1 key
+    InvalidKey,
+    /// `out` size was too small
+    OutputBufferTooSmall,
+    /// The hasher failed
+    Hash,
+}
+
+impl From<OutputBufferTooSmall> for AddressError {
+    fn from(_: OutputBufferTooSmall) -> Self {
+        Self::OutputBufferTooSmall
+    }
+}
+
+impl From<crate::Error> for AddressError {
+    fn from(_: crate::Error) -> Self {
+        Self::Hash
+    }
+}
+
+impl From<AddressError> for ApduError {
+    fn from(e: AddressError) -> Self {
+        match e {
+            AddressError::OutputBufferTooSmall => ApduError::OutputBufferTooSmall,
+            _ => ApduError::DataInvalid,
+        }
+    }
+}
+
+#[inline(never)]
+/// Compute the address of the given uncompressed secp256k1 key
+pub fn address(key: &PublicKey) -> Result<[u8; ADDRESS_LEN], AddressError> {
+    let bytes = key.as_ref();
+    if !matches!(key.curve(), Curve::Secp256K1) || bytes.len() != 65 || bytes[0] != 0x04 {
+        return Err(AddressError::InvalidKey);
+    }
+
+    let hash = Keccak::<32>::digest(&bytes[1..])?;
+
+    let mut address = [0; ADDRESS_LEN];
+    address.copy_from_slice(&hash[32 - ADDRESS_LEN..]);
+    Ok(address)
+}
+
+/// Write the decimal representation of `n` at the end of `out`,
+/// returning the index of the first digit
+fn decimal(mut n: u64, out: &mut [u8; MAX_CHAIN_ID_DIGITS]) -> usize {
+    let mut start = out.len();
+    loop {
+        start -= 1;
+        out[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break start;
+        }
+    }
+}
+
+#[inline(never)]
+/// Encode the address as `0x` prefixed hex with the mixed-case checksum, written in `out`.
+///
+/// If `chain_id` is given the EIP-1191 checksum for the chain is used, otherwise the EIP-55 one.
+///
+/// Will return the number of bytes written.
+pub fn checksum_encode(
+    address: &[u8; ADDRESS_LEN],
+    chain_id: Option<u64>,
+    out: &mut [u8],
+) -> Result<usize, AddressError> {
+    let out = out
+        .get_mut(..CHECKSUM_ADDRESS_LEN)
+        .ok_or(AddressError::OutputBufferTooSmall)?;
+    out[..2].copy_from_slice(b"0x");
+    let lower = &mut out[2..];
+    hex_encode(address, lower)?;
+
+    let mut hasher = Keccak::<32>::new()?;
+    if let Some(chain_id) = chain_id {
+        let mut digits = [0; MAX_CHAIN_ID_DIGITS];
+        let start = decimal(chain_id, &mut digits);
+        hasher.update(&digits[start..])?;
+        hasher.update(b"0x")?;
+    }
+    hasher.update(lower)?;
+    let hash = hasher.finalize()?;
+
+    //uppercase each letter whose hash nibble is 8 or above
+    for (i, c) in lower.iter_mut().enumerate() {
+        let nibble = if i % 2 == 0 {
+            hash[i / 2] >> 4
+        } else {
+            hash[i / 2] & 0x0f
+        };
+
+        if nibble >= 8 {
+            c.make_ascii_uppercase();
+        }
+    }
+
+    Ok(CHECKSUM_ADDRESS_LEN)
+}
+
+/// Encode the address of the given uncompressed secp256k1 key
+/// as checksummed hex, written in `out`.
+///
+/// See [`checksum_encode`] for the meaning of `chain_id`.
+///
+/// Will return the number of bytes written.
+pub fn encode_public_key(
+    key: &PublicKey,
+    chain_id: Option<u64>,
+    out: &mut [u8],
+) -> Result<usize, AddressError> {
+    checksum_encode(&address(key)?, chain_id, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //EIP-55 test cases, with their EIP-1191 checksum on chains 30 and 31
+    const VECTORS: &[[&str; 3]] = &[
+        [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0x5aaEB6053f3e94c9b9a09f33669435E7ef1bEAeD",
+            "0x5aAeb6053F3e94c9b9A09F33669435E7EF1BEaEd",
+        ],
+        [
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xFb6916095cA1Df60bb79ce92cE3EA74c37c5d359",
+            "0xFb6916095CA1dF60bb79CE92ce3Ea74C37c5D359",
+        ],
+        [
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xDBF03B407c01E7CD3cBea99509D93F8Dddc8C6FB",
+            "0xdbF03B407C01E7cd3cbEa99509D93f8dDDc8C6fB",
+        ],
+    ];
+
+    #[test]
+    fn checksum() {
+        for [eip55, chain30, chain31] in VECTORS {
+            let mut address = [0; ADDRESS_LEN];
+            address.copy_from_slice(&hex::decode(&eip55[2..]).unwrap());
+
+            let mut out = [0; CHECKSUM_ADDRESS_LEN];
+            assert_eq!(checksum_encode(&address, None, &mut out), Ok(42));
+            assert_eq!(&out[..], eip55.as_bytes());
+            checksum_encode(&address, Some(30), &mut out).unwrap();
+            assert_eq!(&out[..], chain30.as_bytes());
+            checksum_encode(&address, Some(31), &mut out).unwrap();
+            assert_eq!(&out[..], chain31.as_bytes());
+        }
+
+        assert_eq!(
+            checksum_encode(&[0; ADDRESS_LEN], None, &mut [0; 41]),
+            Err(AddressError::OutputBufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn public_key() {
+        //secp256k1 generator, public key of the secret key 1
+        const KEY: &str = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+        let mut key = PublicKey::from_bytes(Curve::Secp256K1, &hex::decode(KEY).unwrap()).unwrap();
+        let mut out = [0; CHECKSUM_ADDRESS_LEN];
+        encode_public_key(&key, None, &mut out).unwrap();
+        assert_eq!(&out[..], b"0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf");
+
+        key.compress().unwrap();
+        assert_eq!(address(&key), Err(AddressError::InvalidKey));
+    }
+
+    #[test]
+    fn speculos_account() {
+        use crate::crypto::{bip32::BIP32Path, ecfp256::SecretKey, Mode};
+
+        //m/44'/60'/0'/0/0 of the default mnemonic
+        let path = BIP32Path::<5>::new([0x8000_002c, 0x8000_003c, 0x8000_0000, 0, 0]).unwrap();
+        let secret = SecretKey::new(Mode::BIP32, Curve::Secp256K1, path);
+
+        let mut out = [0; CHECKSUM_ADDRESS_LEN];
+        encode_public_key(&secret.public().unwrap(), None, &mut out).unwrap();
+        assert_eq!(&out[..], b"0xDad77910DbDFdE764fC21FCD4E74D71bBACA6D8D");
+    }
+}
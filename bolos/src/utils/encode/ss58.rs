@@ -0,0 +1,343 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! SS58 address format, as used by Substrate chains
+//!
+//! An address is the base58 encoding of `prefix || payload || checksum`,
+//! where the checksum is taken from the Blake2b-512 of `SS58PRE || prefix || payload`
+
+use super::{bs58_decode, bs58_encode_iter, Bs58Error, OutputBufferTooSmall};
+use crate::{
+    crypto::{ecfp256::PublicKey, Curve},
+    hash::{Blake2b, Hasher},
+    ApduError,
+};
+
+/// Preimage prefix of the checksum
+pub const SS58PRE: &[u8] = b"SS58PRE";
+
+/// Maximum network identifier
+pub const MAX_NETWORK: u16 = 0x3fff;
+
+/// Network identifiers reserved by the SS58 registry, which can't be used in addresses
+pub const RESERVED_NETWORKS: [u16; 2] = [46, 47];
+
+//prefix, largest payload and its checksum
+const MAX_DECODED_LEN: usize = 2 + 33 + 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum Ss58Error {
+    /// The network identifier is above [`MAX_NETWORK`] or is one of [`RESERVED_NETWORKS`]
+    InvalidNetwork,
+    /// The payload length doesn't have an associated checksum length
+    InvalidLength,
+    /// The public key is not in a supported form
+    InvalidKey,
+    /// The base58 encoding is invalid
+    Bs58(Bs58Error),
+    /// The checksum doesn't match the decoded data
+    InvalidChecksum,
+    /// `out` size was too small
+    OutputBufferTooSmall,
+    /// The hasher failed computing the checksum
+    Hash,
+}
+
+impl From<OutputBufferTooSmall> for Ss58Error {
+    fn from(_: OutputBufferTooSmall) -> Self {
+        Self::OutputBufferTooSmall
+    }
+}
+
+impl From<Bs58Error> for Ss58Error {
+    fn from(e: Bs58Error) -> Self {
+        match e {
+            Bs58Error::OutputBufferTooSmall => Self::OutputBufferTooSmall,
+            e => Self::Bs58(e),
+        }
+    }
+}
+
+impl From<crate::Error> for Ss58Error {
+    fn from(_: crate::Error) -> Self {
+        Self::Hash
+    }
+}
+
+impl From<Ss58Error> for ApduError {
+    fn from(e: Ss58Error) -> Self {
+        match e {
+            Ss58Error::OutputBufferTooSmall => ApduError::OutputBufferTooSmall,
+            _ => ApduError::DataInvalid,
+        }
+    }
+}
+
+/// Encode the network identifier in its 1 or 2 bytes form,
+/// returning the bytes and the number used
+pub fn encode_prefix(network: u16) -> Result<([u8; 2], usize), Ss58Error> {
+    if RESERVED_NETWORKS.contains(&network) {
+        return Err(Ss58Error::InvalidNetwork);
+    }
+
+    match network {
+        0..=63 => Ok(([network as u8, 0], 1)),
+        64..=MAX_NETWORK => {
+            let first = ((network & 0b1111_1100) >> 2) as u8 | 0b0100_0000;
+            let second = (network >> 8) as u8 | ((network & 0b11) << 6) as u8;
+            Ok(([first, second], 2))
+        }
+        _ => Err(Ss58Error::InvalidNetwork),
+    }
+}
+
+/// Decode the network identifier from the start of `input`,
+/// returning it and the number of bytes used
+pub fn decode_prefix(input: &[u8]) -> Result<(u16, usize), Ss58Error> {
+    let (network, len) = match input {
+        [first @ 0..=63, ..] => Ok((*first as u16, 1)),
+        [first @ 64..=127, second, ..] => {
+            let lower = ((first << 2) | (second >> 6)) as u16;
+            let upper = (second & 0b0011_1111) as u16;
+            Ok((lower | (upper << 8), 2))
+        }
+        [64..=127] | [] => Err(Ss58Error::InvalidLength),
+        _ => Err(Ss58Error::InvalidNetwork),
+    }?;
+
+    if RESERVED_NETWORKS.contains(&network) {
+        return Err(Ss58Error::InvalidNetwork);
+    }
+
+    Ok((network, len))
+}
+
+/// Number of checksum bytes for a payload of the given length
+fn checksum_len(payload_len: usize) -> Result<usize, Ss58Error> {
+    match payload_len {
+        1 | 2 | 4 | 8 => Ok(1),
+        32 | 33 => Ok(2),
+        _ => Err(Ss58Error::InvalidLength),
+    }
+}
+
+fn checksum(prefix: &[u8], payload: &[u8]) -> Result<[u8; 64], Ss58Error> {
+    let mut hasher = Blake2b::<64>::new()?;
+    hasher.update(SS58PRE)?;
+    hasher.update(prefix)?;
+    hasher.update(payload)?;
+
+    Ok(hasher.finalize()?)
+}
+
+#[inline(never)]
+/// Encode `payload` as an SS58 address of the given network, written in `out`.
+///
+/// Will return the number of bytes written.
+pub fn encode(network: u16, payload: &[u8], out: &mut [u8]) -> Result<usize, Ss58Error> {
+    let (prefix, prefix_len) = encode_prefix(network)?;
+    let prefix = &prefix[..prefix_len];
+    let checksum_len = checksum_len(payload.len())?;
+    let checksum = checksum(prefix, payload)?;
+
+    let input = prefix
+        .iter()
+        .chain(payload)
+        .chain(&checksum[..checksum_len])
+        .copied();
+    Ok(bs58_encode_iter(input, out)?)
+}
+
+/// Encode the given public key as an SS58 address of the given network, written in `out`.
+///
+/// Ed25519 keys are encoded as their 32 bytes, while secp256k1 and secp256r1 keys
+/// need to be compressed beforehand.
+///
+/// Will return the number of bytes written.
+pub fn encode_public_key(
+    network: u16,
+    key: &PublicKey,
+    out: &mut [u8],
+) -> Result<usize, Ss58Error> {
+    let bytes = key.as_ref();
+    let payload = match (key.curve(), bytes.len()) {
+        (Curve::Ed25519, 32) => bytes,
+        //compressed form with leading byte
+        (Curve::Ed25519, 33) => &bytes[1..],
+        (Curve::Secp256K1 | Curve::Secp256R1, 33) => bytes,
+        _ => return Err(Ss58Error::InvalidKey),
+    };
+
+    encode(network, payload, out)
+}
+
+#[inline(never)]
+/// Decode the input SS58 address, verifying its checksum, writing the payload in `out`.
+///
+/// Will return the network identifier and the length of the payload.
+pub fn decode(input: impl AsRef<[u8]>, out: &mut [u8]) -> Result<(u16, usize), Ss58Error> {
+    let mut decoded = [0; MAX_DECODED_LEN];
+    let len = bs58_decode(input, &mut decoded).map_err(|e| match e {
+        Bs58Error::OutputBufferTooSmall => Ss58Error::InvalidLength,
+        e => e.into(),
+    })?;
+    let decoded = &decoded[..len];
+
+    let (network, prefix_len) = decode_prefix(decoded)?;
+    let (prefix, rest) = decoded.split_at(prefix_len);
+
+    //the checksum length depends on the payload length, so try each
+    let (payload, expected) = [1, 2]
+        .into_iter()
+        .filter_map(|len| {
+            rest.len()
+                .checked_sub(len)
+                .map(|split| rest.split_at(split))
+        })
+        .find(|(payload, checksum)| checksum_len(payload.len()) == Ok(checksum.len()))
+        .ok_or(Ss58Error::InvalidLength)?;
+
+    let checksum = checksum(prefix, payload)?;
+    if checksum[..expected.len()] != *expected {
+        return Err(Ss58Error::InvalidChecksum);
+    }
+
+    out.get_mut(..payload.len())
+        .ok_or(Ss58Error::OutputBufferTooSmall)?
+        .copy_from_slice(payload);
+
+    Ok((network, payload.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //public key of `//Alice`
+    const ALICE: &str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+
+    const VECTORS: &[(u16, &str)] = &[
+        (0, "15oF4uVJwmo4TdGW7VfQxNLavjCXviqxT9S1MgbjMNHr6Sp5"),
+        (2, "HNZata7iMYWmk5RvZRTiAsSDhV8366zq2YGb3tLH5Upf74F"),
+        (42, "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"),
+    ];
+
+    #[test]
+    fn prefix() {
+        assert_eq!(encode_prefix(42), Ok(([42, 0], 1)));
+        assert_eq!(encode_prefix(1284), Ok(([0x41, 0x05], 2)));
+        assert_eq!(
+            encode_prefix(MAX_NETWORK + 1),
+            Err(Ss58Error::InvalidNetwork)
+        );
+
+        for network in [0, 63, 64, 255, 1284, MAX_NETWORK] {
+            let (prefix, len) = encode_prefix(network).unwrap();
+            assert_eq!(decode_prefix(&prefix[..len]), Ok((network, len)));
+        }
+        assert_eq!(decode_prefix(&[0x80]), Err(Ss58Error::InvalidNetwork));
+        assert_eq!(decode_prefix(&[0x41]), Err(Ss58Error::InvalidLength));
+
+        for network in RESERVED_NETWORKS {
+            assert_eq!(encode_prefix(network), Err(Ss58Error::InvalidNetwork));
+            assert_eq!(
+                decode_prefix(&[network as u8]),
+                Err(Ss58Error::InvalidNetwork)
+            );
+        }
+    }
+
+    #[test]
+    fn alice() {
+        let key = hex::decode(ALICE).unwrap();
+
+        for (network, address) in VECTORS {
+            let mut out = [0; 64];
+            let written = encode(*network, &key, &mut out).unwrap();
+            assert_eq!(&out[..written], address.as_bytes());
+
+            let mut payload = [0; 32];
+            assert_eq!(decode(address, &mut payload), Ok((*network, 32)));
+            assert_eq!(&payload[..], &key[..]);
+        }
+
+        //two bytes prefix
+        let mut out = [0; 64];
+        let written = encode(1284, &key, &mut out).unwrap();
+        let mut payload = [0; 32];
+        assert_eq!(decode(&out[..written], &mut payload), Ok((1284, 32)));
+        assert_eq!(&payload[..], &key[..]);
+    }
+
+    #[test]
+    fn invalid() {
+        let key = hex::decode(ALICE).unwrap();
+
+        assert_eq!(
+            encode(0, &key[..31], &mut [0; 64]),
+            Err(Ss58Error::InvalidLength)
+        );
+        assert_eq!(
+            encode(0, &key, &mut [0; 40]),
+            Err(Ss58Error::OutputBufferTooSmall)
+        );
+
+        //last character changed
+        assert_eq!(
+            decode(
+                "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQZ",
+                &mut [0; 32]
+            ),
+            Err(Ss58Error::InvalidChecksum)
+        );
+        assert_eq!(
+            decode(
+                "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQ0",
+                &mut [0; 32]
+            ),
+            Err(Ss58Error::Bs58(Bs58Error::InvalidChar { position: 47 }))
+        );
+        assert_eq!(
+            decode(VECTORS[2].1, &mut [0; 16]),
+            Err(Ss58Error::OutputBufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn public_key() {
+        use crate::crypto::{bip32::BIP32Path, ecfp256::SecretKey, Mode};
+
+        let path = BIP32Path::<5>::new([0x8000_002c, 0x8000_0162, 0x8000_0000, 0, 0]).unwrap();
+        let secret = SecretKey::new(Mode::BIP32, Curve::Ed25519, path);
+        let key = secret.public().unwrap();
+
+        let mut out = [0; 64];
+        let written = encode_public_key(42, &key, &mut out).unwrap();
+        let mut payload = [0; 32];
+        assert_eq!(decode(&out[..written], &mut payload), Ok((42, 32)));
+        assert_eq!(&payload[..], &key.as_ref()[key.len() - 32..]);
+
+        //uncompressed secp256k1 keys are not supported
+        let secret = SecretKey::new(Mode::BIP32, Curve::Secp256K1, path);
+        let mut key = secret.public().unwrap();
+        assert_eq!(
+            encode_public_key(42, &key, &mut out),
+            Err(Ss58Error::InvalidKey)
+        );
+        key.compress().unwrap();
+        assert!(encode_public_key(42, &key, &mut out).is_ok());
+    }
+}
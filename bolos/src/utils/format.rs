@@ -0,0 +1,372 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Allocation-free formatting of decimal integers and fixed-point amounts
+//!
+//! All functions write a null terminated string, suitable for the UI
+//!
+//! ```
+//! # use bolos::{format::{amount, AmountFormat, Ticker}, OutputBufferTooSmall};
+//! # fn run() -> Result<(), OutputBufferTooSmall> {
+//! let format = AmountFormat {
+//!     decimals: 6,
+//!     separator: Some(b','),
+//!     ticker: Ticker::Prefix("ATOM"),
+//!     ..AmountFormat::INTEGER
+//! };
+//!
+//! let mut out = [0; 32];
+//! let len = amount(1_234_567_500_000u64, &format, &mut out)?;
+//! assert_eq!(&out[..len], b"ATOM 1,234,567.5");
+//! assert_eq!(out[len], 0);
+//! # Ok(())
+//! # }
+//! # assert!(run().is_ok());
+//! ```
+
+use crate::{LedgerUnwrap, OutputBufferTooSmall};
+
+/// Digits of the largest u128
+const MAX_DIGITS: usize = 39;
+
+/// Size of the buffer used by [`page_amount`]
+pub const MAX_AMOUNT_LEN: usize = 128;
+
+/// Integers that can be formatted, up to 128 bits
+pub trait Integer: Copy {
+    /// Split the value in its sign (`true` if negative) and magnitude
+    fn sign_magnitude(self) -> (bool, u128);
+}
+
+macro_rules! impl_unsigned {
+    ($($ty:ty),*) => {
+        $(impl Integer for $ty {
+            fn sign_magnitude(self) -> (bool, u128) {
+                (false, self as u128)
+            }
+        })*
+    };
+}
+
+macro_rules! impl_signed {
+    ($($ty:ty),*) => {
+        $(impl Integer for $ty {
+            fn sign_magnitude(self) -> (bool, u128) {
+                (self < 0, self.unsigned_abs() as u128)
+            }
+        })*
+    };
+}
+
+impl_unsigned!(u8, u16, u32, u64, u128, usize);
+impl_signed!(i8, i16, i32, i64, i128, isize);
+
+/// Where the ticker is placed, separated from the number by a space
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum Ticker<'t> {
+    None,
+    /// Before the number, like `DOT 1.5`
+    Prefix(&'t str),
+    /// After the number, like `1.5 DOT`
+    Suffix(&'t str),
+}
+
+/// How to format a fixed-point amount
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub struct AmountFormat<'t> {
+    /// Number of decimals of the base unit
+    pub decimals: u8,
+    /// Whether trailing zeros of the fractional part are removed,
+    /// together with the decimal point if nothing is left
+    pub trim_zeros: bool,
+    /// Separator inserted between each group of 3 integer digits
+    pub separator: Option<u8>,
+    pub ticker: Ticker<'t>,
+}
+
+impl AmountFormat<'static> {
+    /// Plain decimal integer
+    pub const INTEGER: Self = Self {
+        decimals: 0,
+        trim_zeros: true,
+        separator: None,
+        ticker: Ticker::None,
+    };
+}
+
+/// Write the decimal digits of `n` at the end of `out`,
+/// returning the index of the first digit
+fn digits(mut n: u128, out: &mut [u8; MAX_DIGITS]) -> usize {
+    let mut start = out.len();
+    loop {
+        start -= 1;
+        out[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break start;
+        }
+    }
+}
+
+/// Sequential writer over a buffer known to be big enough
+struct Writer<'b> {
+    out: &'b mut [u8],
+    written: usize,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, c: u8) {
+        *self.out.get_mut(self.written).ledger_unwrap() = c;
+        self.written += 1;
+    }
+
+    fn extend(&mut self, s: &[u8]) {
+        let end = self.written + s.len();
+        self.out
+            .get_mut(self.written..end)
+            .ledger_unwrap()
+            .copy_from_slice(s);
+        self.written = end;
+    }
+}
+
+#[inline(never)]
+/// Format `value` as a fixed-point amount, written null terminated in `out`.
+///
+/// If the formatted amount doesn't fit `out` nothing is written
+/// besides an empty null terminated string.
+///
+/// Will return the number of bytes written, excluding the null terminator.
+pub fn amount(
+    value: impl Integer,
+    format: &AmountFormat,
+    out: &mut [u8],
+) -> Result<usize, OutputBufferTooSmall> {
+    let (negative, magnitude) = value.sign_magnitude();
+    let decimals = format.decimals as usize;
+
+    let mut buf = [0; MAX_DIGITS];
+    let start = digits(magnitude, &mut buf);
+    let all = &buf[start..];
+
+    //split the digits in the integer part and the fractional part,
+    // the latter being `zeros` leading zeros followed by `frac`
+    let (int, zeros, frac): (&[u8], usize, &[u8]) = if all.len() > decimals {
+        let (int, frac) = all.split_at(all.len() - decimals);
+        (int, 0, frac)
+    } else {
+        (b"0", decimals - all.len(), all)
+    };
+
+    let (zeros, frac) = if format.trim_zeros {
+        let trailing = frac.iter().rev().take_while(|d| **d == b'0').count();
+        match &frac[..frac.len() - trailing] {
+            [] => (0, &[][..]),
+            frac => (zeros, frac),
+        }
+    } else {
+        (zeros, frac)
+    };
+
+    let separators = match format.separator {
+        Some(_) => (int.len() - 1) / 3,
+        None => 0,
+    };
+    let frac_len = zeros + frac.len();
+    let ticker_len = match format.ticker {
+        Ticker::None => 0,
+        Ticker::Prefix(t) | Ticker::Suffix(t) => t.len() + 1,
+    };
+
+    let len = negative as usize
+        + ticker_len
+        + int.len()
+        + separators
+        + if frac_len > 0 { 1 + frac_len } else { 0 };
+
+    //room for the null terminator
+    if len >= out.len() {
+        if let Some(first) = out.first_mut() {
+            *first = 0;
+        }
+        return Err(OutputBufferTooSmall);
+    }
+
+    let mut w = Writer { out, written: 0 };
+    if let Ticker::Prefix(t) = format.ticker {
+        w.extend(t.as_bytes());
+        w.push(b' ');
+    }
+    if negative {
+        w.push(b'-');
+    }
+    for (i, d) in int.iter().enumerate() {
+        if let Some(separator) = format.separator {
+            if i > 0 && (int.len() - i).is_multiple_of(3) {
+                w.push(separator);
+            }
+        }
+        w.push(*d);
+    }
+    if frac_len > 0 {
+        w.push(b'.');
+        for _ in 0..zeros {
+            w.push(b'0');
+        }
+        w.extend(frac);
+    }
+    if let Ticker::Suffix(t) = format.ticker {
+        w.push(b' ');
+        w.extend(t.as_bytes());
+    }
+    w.push(0);
+
+    Ok(len)
+}
+
+/// Format `value` as a decimal integer, written null terminated in `out`.
+///
+/// Will return the number of bytes written, excluding the null terminator.
+pub fn integer(value: impl Integer, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall> {
+    amount(value, &AmountFormat::INTEGER, out)
+}
+
+#[inline(never)]
+/// Format `value` as a fixed-point amount and page it into `out` with [`crate::ui::handle_message`]
+///
+/// If `None` is returned an error occured, otherwise the total number of pages is returned
+pub fn page_amount(
+    value: impl Integer,
+    format: &AmountFormat,
+    out: &mut [u8],
+    page: u8,
+) -> Option<u8> {
+    let mut buf = [0; MAX_AMOUNT_LEN];
+    let len = amount(value, format, &mut buf).ok()?;
+
+    crate::ui::handle_message(&buf[..len], out, page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(value: impl Integer, format: &AmountFormat) -> std::string::String {
+        let mut out = [0xff; 80];
+        let len = amount(value, format, &mut out).unwrap();
+        assert_eq!(out[len], 0);
+        std::str::from_utf8(&out[..len]).unwrap().into()
+    }
+
+    #[test]
+    fn integers() {
+        let mut out = [0; 41];
+
+        let len = integer(0u8, &mut out).unwrap();
+        assert_eq!(&out[..=len], b"0\0");
+        let len = integer(u128::MAX, &mut out).unwrap();
+        assert_eq!(&out[..len], b"340282366920938463463374607431768211455");
+        let len = integer(i128::MIN, &mut out).unwrap();
+        assert_eq!(&out[..len], b"-170141183460469231731687303715884105728");
+        let len = integer(-42i8, &mut out).unwrap();
+        assert_eq!(&out[..len], b"-42");
+    }
+
+    #[test]
+    fn amounts() {
+        let six = AmountFormat {
+            decimals: 6,
+            ..AmountFormat::INTEGER
+        };
+
+        assert_eq!(format(1_500_000u64, &six), "1.5");
+        assert_eq!(format(5u64, &six), "0.000005");
+        assert_eq!(format(0u64, &six), "0");
+        assert_eq!(format(42_000_000u64, &six), "42");
+        assert_eq!(format(-12_500_000i64, &six), "-12.5");
+        assert_eq!(format(-5i64, &six), "-0.000005");
+
+        let untrimmed = AmountFormat {
+            trim_zeros: false,
+            ..six
+        };
+        assert_eq!(format(1_500_000u64, &untrimmed), "1.500000");
+        assert_eq!(format(0u64, &untrimmed), "0.000000");
+
+        let separated = AmountFormat {
+            separator: Some(b','),
+            ..six
+        };
+        assert_eq!(format(1_234_567_890_123u64, &separated), "1,234,567.890123");
+        assert_eq!(format(123_000_000u64, &separated), "123");
+        assert_eq!(format(-1_000_000_000i64, &separated), "-1,000");
+
+        let ticker = AmountFormat {
+            ticker: Ticker::Suffix("ETH"),
+            decimals: 18,
+            ..AmountFormat::INTEGER
+        };
+        assert_eq!(format(1u8, &ticker), "0.000000000000000001 ETH");
+        assert_eq!(
+            format(u128::MAX, &ticker),
+            "340282366920938463463.374607431768211455 ETH"
+        );
+
+        let prefix = AmountFormat {
+            ticker: Ticker::Prefix("DOT"),
+            ..separated
+        };
+        assert_eq!(format(-2_500_000i32, &prefix), "DOT -2.5");
+    }
+
+    #[test]
+    fn truncation() {
+        let format = AmountFormat {
+            decimals: 2,
+            ..AmountFormat::INTEGER
+        };
+
+        let mut out = [0xff; 5];
+        assert_eq!(
+            amount(12345u32, &format, &mut out),
+            Err(OutputBufferTooSmall)
+        );
+        assert_eq!(out[0], 0);
+        assert_eq!(amount(1230u32, &format, &mut out), Ok(4));
+        assert_eq!(&out, b"12.3\0");
+
+        assert_eq!(integer(1u8, &mut []), Err(OutputBufferTooSmall));
+    }
+
+    #[test]
+    fn paging() {
+        let format = AmountFormat {
+            decimals: 4,
+            separator: Some(b'\''),
+            ticker: Ticker::Suffix("XTZ"),
+            ..AmountFormat::INTEGER
+        };
+
+        let mut page = [0; 6];
+        //1'234'567.8 XTZ
+        let pages = page_amount(12_345_678_000u64, &format, &mut page, 0).unwrap();
+        assert_eq!(pages, 3);
+        assert_eq!(&page, b"1'234\0");
+        page_amount(12_345_678_000u64, &format, &mut page, 2).unwrap();
+        assert_eq!(&page, b"8 XTZ\0");
+    }
+}
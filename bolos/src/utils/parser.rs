@@ -15,6 +15,18 @@
 ********************************************************************************/
 mod from_bytes;
 mod object_list;
+mod to_bytes;
+
+mod primitives;
+pub use primitives::*;
+
+pub mod borsh;
+pub mod cbor;
+pub mod json;
+pub mod prefixed;
+pub mod protobuf;
+pub mod scale;
 
 pub use from_bytes::FromBytes;
 pub use object_list::ObjectList;
+pub use to_bytes::*;
@@ -0,0 +1,262 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Primitives to decode Borsh encoded data, as used by NEAR and Solana programs
+//!
+//! Integers are read with [`Le`](super::Le), booleans with [`Bool`]
+//! and options with [`Tagged`],
+//! fixed size arrays as `&[u8; N]`, while structs and enums can be declared with [`borsh!`](crate::borsh).
+
+use core::mem::MaybeUninit;
+
+use super::{
+    le_u32, prefixed, prefixed::LengthPrefix, take, FromBytes, ObjectList, ParserError,
+    ParserResult,
+};
+
+pub use super::prefixed::{Bool, Tagged};
+
+/// Read a `u32` little endian length
+pub fn len_u32(input: &[u8]) -> ParserResult<'_, usize> {
+    let (rem, len) = le_u32(input)?;
+    let len = usize::try_from(len).map_err(|_| ParserError::LengthOverflow)?;
+
+    Ok((rem, len))
+}
+
+#[inline(never)]
+/// Build an [`ObjectList`] of as many `T` as specified by the leading `u32` count
+///
+/// Returns the remaining input and the number of items
+pub fn object_list_into<'b, T>(
+    input: &'b [u8],
+    out: &mut MaybeUninit<ObjectList<'b, T>>,
+) -> Result<(&'b [u8], usize), T::Error>
+where
+    T: FromBytes<'b>,
+    T::Error: From<ParserError>,
+{
+    let (rem, len) = len_u32(input)?;
+    let rem = ObjectList::new_into(rem, len, out)?;
+
+    Ok((rem, len))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// [`LengthPrefix`] of Borsh vectors and strings, a `u32` little endian length
+pub struct U32Len;
+
+impl LengthPrefix for U32Len {
+    fn length(input: &[u8]) -> ParserResult<'_, usize> {
+        len_u32(input)
+    }
+}
+
+/// Byte vector, prefixed with its `u32` length
+pub type Bytes<'b> = prefixed::Bytes<'b, U32Len>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// UTF-8 string, prefixed with its `u32` length in bytes
+pub struct Str<'b>(pub &'b str);
+
+impl<'b> FromBytes<'b> for Str<'b> {
+    type Error = ParserError;
+
+    #[inline(never)]
+    fn from_bytes_into(
+        input: &'b [u8],
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], Self::Error> {
+        let (rem, len) = len_u32(input)?;
+        let (rem, bytes) = take(rem, len)?;
+        let s = core::str::from_utf8(bytes).map_err(|_| ParserError::InvalidUtf8)?;
+        out.write(Str(s));
+
+        Ok(rem)
+    }
+}
+
+/// Sequence of `T`, prefixed with its `u32` count,
+/// the Borsh equivalent of `Vec<T>`
+pub type Seq<'b, T> = prefixed::Seq<'b, T, U32Len>;
+
+#[macro_export]
+/// Declare a struct or an enum in Borsh format and implement [`FromBytes`](crate::FromBytes) for it
+///
+/// Struct fields are parsed in declaration order.
+/// Enum variants are selected by a leading `u8` tag and can have a single field,
+/// unknown tags fail with [`ParserError::InvalidTag`](crate::parser::ParserError::InvalidTag).
+///
+/// The error of every field must be convertible into [`ParserError`](crate::parser::ParserError).
+///
+/// # Example
+/// ```rust
+/// # use bolos::{borsh, parser::{Le, borsh::Str}};
+/// borsh! {
+///     pub struct Transfer<'b> {
+///         pub receiver: Str<'b>,
+///         pub deposit: Le<u128>,
+///     }
+/// }
+///
+/// borsh! {
+///     pub enum Action<'b> {
+///         0 => CreateAccount,
+///         3 => Transfer(Transfer<'b>),
+///     }
+/// }
+/// ```
+macro_rules! borsh {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident $(<$lt:lifetime>)? {
+            $( $(#[$fattr:meta])* $fvis:vis $field:ident : $ty:ty ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name $(<$lt>)? {
+            $( $(#[$fattr])* $fvis $field: $ty, )*
+        }
+
+        $crate::__from_bytes_impl!($name $(<$lt>)?, |input, out| {
+            let out = out.as_mut_ptr();
+            let rem = input;
+            $(
+                //good ptr and no uninit reads
+                let field = unsafe {
+                    &mut *::core::ptr::addr_of_mut!((*out).$field)
+                        .cast::<::core::mem::MaybeUninit<$ty>>()
+                };
+                let rem = <$ty as $crate::FromBytes>::from_bytes_into(rem, field)?;
+            )*
+
+            Ok(rem)
+        });
+    };
+    (
+        $(#[$attr:meta])*
+        $vis:vis enum $name:ident $(<$lt:lifetime>)? {
+            $( $idx:literal => $variant:ident $(($inner:ty))? ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis enum $name $(<$lt>)? {
+            $( $variant $(($inner))?, )*
+        }
+
+        $crate::__index_enum_from_bytes!($name $(<$lt>)? {
+            $( $idx => $variant $(($inner))? ),*
+        });
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Le;
+
+    borsh! {
+        #[derive(Debug, PartialEq)]
+        struct Transfer<'b> {
+            receiver: Str<'b>,
+            key: &'b [u8; 4],
+            deposit: Le<u128>,
+            memo: Tagged<Bytes<'b>>,
+            refund: Bool,
+        }
+    }
+
+    borsh! {
+        #[derive(Debug, PartialEq)]
+        enum Action<'b> {
+            0 => CreateAccount,
+            3 => Transfer(Transfer<'b>),
+        }
+    }
+
+    borsh! {
+        #[derive(Debug, PartialEq)]
+        struct Batch<'b> {
+            nonce: Le<u64>,
+            actions: Seq<'b, Action<'b>>,
+        }
+    }
+
+    #[test]
+    fn primitives() {
+        assert_eq!(
+            Str::from_bytes(b"\x05\x00\x00\x00hello!"),
+            Ok((&b"!"[..], Str("hello")))
+        );
+        assert_eq!(
+            Str::from_bytes(b"\x01\x00\x00\x00\xff"),
+            Err(ParserError::InvalidUtf8)
+        );
+        assert_eq!(
+            Bytes::from_bytes(&[0x02, 0, 0, 0, 0xaa]),
+            Err(ParserError::UnexpectedEof { needed: 1 })
+        );
+        assert_eq!(Bool::from_bytes(&[0x02]), Err(ParserError::InvalidTag(2)));
+
+        let input = [0x02, 0, 0, 0, 0x01, 0x00, 0x02, 0x00];
+        let mut list = MaybeUninit::uninit();
+        let (rem, len) = object_list_into::<Le<u16>>(&input, &mut list).unwrap();
+        let list = unsafe { list.assume_init() };
+        assert!(rem.is_empty());
+        assert_eq!(len, 2);
+        assert!(list.iter().map(|n| n.0).eq([1, 2]));
+    }
+
+    #[test]
+    fn declared() {
+        let mut input = std::vec![];
+        input.extend_from_slice(&7u64.to_le_bytes());
+        input.extend_from_slice(&2u32.to_le_bytes());
+        //CreateAccount
+        input.push(0);
+        //Transfer
+        input.push(3);
+        input.extend_from_slice(b"\x04\x00\x00\x00bob!");
+        input.extend_from_slice(&[1, 2, 3, 4]);
+        input.extend_from_slice(&1000u128.to_le_bytes());
+        input.extend_from_slice(&[1, 1, 0, 0, 0, 0xaa]);
+        input.push(1);
+
+        let (rem, batch) = Batch::from_bytes(&input).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(batch.nonce, Le(7));
+        assert_eq!(batch.actions.len(), 2);
+
+        let mut actions = batch.actions.list().iter();
+        assert_eq!(actions.next(), Some(Action::CreateAccount));
+        assert_eq!(
+            actions.next(),
+            Some(Action::Transfer(Transfer {
+                receiver: Str("bob!"),
+                key: &[1, 2, 3, 4],
+                deposit: Le(1000),
+                memo: Tagged(Some(Bytes::new(&[0xaa]))),
+                refund: Bool(true),
+            }))
+        );
+
+        //unknown action
+        let mut input = input.clone();
+        input[12] = 1;
+        assert_eq!(Batch::from_bytes(&input), Err(ParserError::InvalidTag(1)));
+    }
+}
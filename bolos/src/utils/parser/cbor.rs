@@ -0,0 +1,916 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Zero-copy, allocation-free CBOR ([RFC 8949](https://www.rfc-editor.org/rfc/rfc8949)) decoder
+//!
+//! Arrays and maps are iterated with closures, so nested structures
+//! never need to be stored anywhere; the nesting depth is bounded by [`Config::max_depth`].
+
+use core::mem::MaybeUninit;
+
+use super::{be_u16, be_u32, be_u64, be_u8, take, FromBytes, ParserError};
+use crate::ApduError;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Errors that can occur when decoding CBOR
+pub enum CborError {
+    /// Error reading the underlying data
+    Parser(ParserError),
+    /// The additional information of the initial byte is reserved or not valid for the major type
+    InvalidAdditionalInfo(u8),
+    /// A different item than the one requested was found
+    UnexpectedType {
+        expected: MajorType,
+        found: MajorType,
+    },
+    /// A simple value other than a boolean was found where one was expected
+    NotABool,
+    /// A "break" was found outside of an indefinite length item
+    UnexpectedBreak,
+    /// A chunk of an indefinite length string was not a definite string of the same type
+    InvalidChunk,
+    /// The text string was not valid UTF-8
+    InvalidUtf8,
+    /// The integer doesn't fit in the requested type
+    IntegerOverflow,
+    /// The nesting of arrays, maps and tags exceeded [`Config::max_depth`]
+    DepthExceeded,
+    /// The item was not encoded with the shortest possible argument or float width
+    NonCanonical,
+    /// Indefinite length items are not allowed in canonical mode
+    IndefiniteLength,
+    /// Map keys were not sorted in canonical mode
+    UnsortedMapKeys,
+    /// The same map key was found twice in canonical mode
+    DuplicateMapKey,
+}
+
+impl From<ParserError> for CborError {
+    fn from(e: ParserError) -> Self {
+        Self::Parser(e)
+    }
+}
+
+impl From<CborError> for ApduError {
+    fn from(_: CborError) -> Self {
+        ApduError::DataInvalid
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// The 8 CBOR major types
+pub enum MajorType {
+    Unsigned = 0,
+    Negative = 1,
+    Bytes = 2,
+    Text = 3,
+    Array = 4,
+    Map = 5,
+    Tag = 6,
+    /// Floats and simple values (including `false`, `true`, `null` and "break")
+    Simple = 7,
+}
+
+impl MajorType {
+    fn from_initial_byte(byte: u8) -> Self {
+        match byte >> 5 {
+            0 => Self::Unsigned,
+            1 => Self::Negative,
+            2 => Self::Bytes,
+            3 => Self::Text,
+            4 => Self::Array,
+            5 => Self::Map,
+            6 => Self::Tag,
+            _ => Self::Simple,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Length of an array or map
+pub enum Len {
+    Definite(u64),
+    Indefinite,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// A single data item header, as read by [`Decoder::item`]
+///
+/// Strings are returned in full when they have a definite length,
+/// while for arrays, maps and tags only the header is read
+pub enum Item<'b> {
+    Unsigned(u64),
+    /// Negative integer, the represented value is `-1 - n`
+    Negative(u64),
+    Bytes(&'b [u8]),
+    Text(&'b str),
+    /// Start of an indefinite length byte string, followed by definite chunks and a "break"
+    IndefiniteBytes,
+    /// Start of an indefinite length text string, followed by definite chunks and a "break"
+    IndefiniteText,
+    Array(Len),
+    Map(Len),
+    Tag(u64),
+    Bool(bool),
+    Null,
+    Undefined,
+    Simple(u8),
+    Float(f64),
+    Break,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Configuration of the [`Decoder`]
+pub struct Config {
+    /// Maximum nesting of arrays, maps and tags
+    pub max_depth: usize,
+    /// Enforce the core deterministic encoding requirements of RFC 8949 §4.2.1:
+    /// shortest arguments, definite lengths only and bytewise sorted, unique map keys,
+    /// with floats in the shortest width that preserves their value (§4.2.2)
+    pub canonical: bool,
+}
+
+impl Config {
+    pub const DEFAULT: Self = Self {
+        max_depth: 16,
+        canonical: false,
+    };
+
+    pub const CANONICAL: Self = Self {
+        max_depth: 16,
+        canonical: true,
+    };
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+const BREAK: u8 = 0xff;
+
+#[derive(Clone, Copy)]
+enum Arg {
+    Value(u64),
+    Indefinite,
+}
+
+#[derive(Clone, Copy)]
+/// CBOR pull decoder over a byte slice
+pub struct Decoder<'b> {
+    input: &'b [u8],
+    offset: usize,
+    depth: usize,
+    config: Config,
+}
+
+impl<'b> Decoder<'b> {
+    /// Create a new decoder with the default [`Config`]
+    pub fn new(input: &'b [u8]) -> Self {
+        Self::with_config(input, Config::DEFAULT)
+    }
+
+    /// Create a new decoder with the given [`Config`]
+    pub fn with_config(input: &'b [u8], config: Config) -> Self {
+        Self {
+            input,
+            offset: 0,
+            depth: 0,
+            config,
+        }
+    }
+
+    /// Returns the bytes that are yet to be decoded
+    pub fn remaining(&self) -> &'b [u8] {
+        &self.input[self.offset..]
+    }
+
+    /// Returns the number of bytes decoded so far
+    pub fn position(&self) -> usize {
+        self.offset
+    }
+
+    fn advance(&mut self, rem: &'b [u8]) {
+        self.offset = self.input.len() - rem.len();
+    }
+
+    #[inline(never)]
+    fn header(&mut self) -> Result<(MajorType, u8, Arg), CborError> {
+        let (rem, initial) = be_u8(self.remaining())?;
+        let major = MajorType::from_initial_byte(initial);
+        let info = initial & 0x1f;
+
+        let (rem, arg, min) = match info {
+            0..=23 => (rem, info as u64, 0),
+            24 => be_u8(rem).map(|(rem, v)| (rem, v as u64, 24))?,
+            25 => be_u16(rem).map(|(rem, v)| (rem, v as u64, 0x100))?,
+            26 => be_u32(rem).map(|(rem, v)| (rem, v as u64, 0x1_0000))?,
+            27 => be_u64(rem).map(|(rem, v)| (rem, v, 0x1_0000_0000))?,
+            31 => {
+                match major {
+                    MajorType::Unsigned | MajorType::Negative | MajorType::Tag => {
+                        return Err(CborError::InvalidAdditionalInfo(info))
+                    }
+                    //break
+                    MajorType::Simple => {}
+                    _ if self.config.canonical => return Err(CborError::IndefiniteLength),
+                    _ => {}
+                }
+
+                self.advance(rem);
+                return Ok((major, info, Arg::Indefinite));
+            }
+            _ => return Err(CborError::InvalidAdditionalInfo(info)),
+        };
+
+        match major {
+            //one byte simple values below 32 are not well-formed
+            MajorType::Simple if info == 24 && arg < 32 => {
+                return Err(CborError::InvalidAdditionalInfo(info))
+            }
+            //floats have a fixed size argument
+            MajorType::Simple => {}
+            _ if self.config.canonical && arg < min => return Err(CborError::NonCanonical),
+            _ => {}
+        }
+
+        self.advance(rem);
+        Ok((major, info, Arg::Value(arg)))
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'b [u8], CborError> {
+        let len = usize::try_from(len).map_err(|_| ParserError::LengthOverflow)?;
+        let (rem, data) = take(self.remaining(), len)?;
+        self.advance(rem);
+
+        Ok(data)
+    }
+
+    #[inline(never)]
+    /// Read the next data item
+    ///
+    /// See [`Item`] for what is read for each type
+    pub fn item(&mut self) -> Result<Item<'b>, CborError> {
+        let (major, info, arg) = self.header()?;
+
+        let arg = match arg {
+            Arg::Value(arg) => arg,
+            Arg::Indefinite => {
+                return Ok(match major {
+                    MajorType::Bytes => Item::IndefiniteBytes,
+                    MajorType::Text => Item::IndefiniteText,
+                    MajorType::Array => Item::Array(Len::Indefinite),
+                    MajorType::Map => Item::Map(Len::Indefinite),
+                    _ => Item::Break,
+                })
+            }
+        };
+
+        let item = match major {
+            MajorType::Unsigned => Item::Unsigned(arg),
+            MajorType::Negative => Item::Negative(arg),
+            MajorType::Bytes => Item::Bytes(self.take(arg)?),
+            MajorType::Text => Item::Text(
+                core::str::from_utf8(self.take(arg)?).map_err(|_| CborError::InvalidUtf8)?,
+            ),
+            MajorType::Array => Item::Array(Len::Definite(arg)),
+            MajorType::Map => Item::Map(Len::Definite(arg)),
+            MajorType::Tag => Item::Tag(arg),
+            MajorType::Simple => match info {
+                20 => Item::Bool(false),
+                21 => Item::Bool(true),
+                22 => Item::Null,
+                23 => Item::Undefined,
+                25 => Item::Float(f16_to_f64(arg as u16)),
+                26 | 27 => {
+                    let value = match info {
+                        26 => f32::from_bits(arg as u32) as f64,
+                        _ => f64::from_bits(arg),
+                    };
+
+                    //a narrower float would hold the same value
+                    let shorter = fits_f16(value) || (info == 27 && value as f32 as f64 == value);
+                    if self.config.canonical && shorter {
+                        return Err(CborError::NonCanonical);
+                    }
+
+                    Item::Float(value)
+                }
+                _ => Item::Simple(arg as u8),
+            },
+        };
+
+        Ok(item)
+    }
+
+    /// Read the next data item without moving forward
+    pub fn peek(&self) -> Result<Item<'b>, CborError> {
+        let mut this = *self;
+        this.item()
+    }
+
+    fn expect(&mut self, expected: MajorType) -> Result<Item<'b>, CborError> {
+        let found = self
+            .remaining()
+            .first()
+            .map(|b| MajorType::from_initial_byte(*b))
+            .ok_or(ParserError::UnexpectedEof { needed: 1 })?;
+
+        if found != expected {
+            return Err(CborError::UnexpectedType { expected, found });
+        }
+
+        self.item()
+    }
+
+    /// Read an unsigned integer
+    pub fn u64(&mut self) -> Result<u64, CborError> {
+        match self.expect(MajorType::Unsigned)? {
+            Item::Unsigned(n) => Ok(n),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Read an unsigned or negative integer
+    pub fn i64(&mut self) -> Result<i64, CborError> {
+        match self.peek()? {
+            Item::Negative(_) => match self.item()? {
+                Item::Negative(n) if n <= i64::MAX as u64 => Ok(-1 - n as i64),
+                _ => Err(CborError::IntegerOverflow),
+            },
+            _ => i64::try_from(self.u64()?).map_err(|_| CborError::IntegerOverflow),
+        }
+    }
+
+    /// Read a definite length byte string
+    pub fn bytes(&mut self) -> Result<&'b [u8], CborError> {
+        match self.expect(MajorType::Bytes)? {
+            Item::Bytes(b) => Ok(b),
+            _ => Err(CborError::IndefiniteLength),
+        }
+    }
+
+    /// Read a definite length text string
+    pub fn str(&mut self) -> Result<&'b str, CborError> {
+        match self.expect(MajorType::Text)? {
+            Item::Text(s) => Ok(s),
+            _ => Err(CborError::IndefiniteLength),
+        }
+    }
+
+    /// Read a tag number, the tagged item follows
+    pub fn tag(&mut self) -> Result<u64, CborError> {
+        match self.expect(MajorType::Tag)? {
+            Item::Tag(t) => Ok(t),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Read a boolean
+    pub fn bool(&mut self) -> Result<bool, CborError> {
+        match self.expect(MajorType::Simple)? {
+            Item::Bool(b) => Ok(b),
+            _ => Err(CborError::NotABool),
+        }
+    }
+
+    /// Returns true if the next item is a "break"
+    fn at_break(&self) -> Result<bool, CborError> {
+        match self.remaining().first() {
+            Some(&b) => Ok(b == BREAK),
+            None => Err(ParserError::UnexpectedEof { needed: 1 }.into()),
+        }
+    }
+
+    /// Call `f` one nesting level deeper, restoring the depth whatever the outcome
+    fn nested<T, E, F>(&mut self, f: F) -> Result<T, E>
+    where
+        E: From<CborError>,
+        F: FnOnce(&mut Self) -> Result<T, E>,
+    {
+        if self.depth >= self.config.max_depth {
+            return Err(CborError::DepthExceeded.into());
+        }
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+
+        result
+    }
+
+    /// Call `f` for each of the `len` items, consuming the final "break"
+    /// if the length is indefinite
+    fn items<E, F>(&mut self, len: Len, mut f: F) -> Result<usize, E>
+    where
+        E: From<CborError>,
+        F: FnMut(&mut Self, usize) -> Result<(), E>,
+    {
+        self.nested(|this| {
+            let mut i = 0;
+            match len {
+                Len::Definite(n) => {
+                    while (i as u64) < n {
+                        f(this, i)?;
+                        i += 1;
+                    }
+                }
+                Len::Indefinite => {
+                    while !this.at_break()? {
+                        f(this, i)?;
+                        i += 1;
+                    }
+                    this.offset += 1;
+                }
+            }
+
+            Ok(i)
+        })
+    }
+
+    #[inline(never)]
+    /// Read an array, calling `f` with the decoder and the index of each element
+    ///
+    /// `f` must consume exactly one data item.
+    /// Returns the number of elements
+    pub fn array<E, F>(&mut self, f: F) -> Result<usize, E>
+    where
+        E: From<CborError>,
+        F: FnMut(&mut Self, usize) -> Result<(), E>,
+    {
+        match self.expect(MajorType::Array)? {
+            Item::Array(len) => self.items(len, f),
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline(never)]
+    /// Read a map, calling `f` with the decoder and the index of each entry
+    ///
+    /// `f` must consume exactly two data items: the key and the value.
+    /// Returns the number of entries
+    pub fn map<E, F>(&mut self, f: F) -> Result<usize, E>
+    where
+        E: From<CborError>,
+        F: FnMut(&mut Self, usize) -> Result<(), E>,
+    {
+        match self.expect(MajorType::Map)? {
+            Item::Map(len) => self.entries(len, f),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Call `f` for each of the `len` map entries,
+    /// checking the ordering of the keys in canonical mode
+    fn entries<E, F>(&mut self, len: Len, mut f: F) -> Result<usize, E>
+    where
+        E: From<CborError>,
+        F: FnMut(&mut Self, usize) -> Result<(), E>,
+    {
+        if !self.config.canonical {
+            return self.items(len, f);
+        }
+
+        let mut prev_key: &[u8] = &[];
+        self.items(len, |this, i| {
+            //find the encoded key to check the ordering,
+            // then rewind so `f` can read it
+            let start = this.offset;
+            this.skip()?;
+            let key = &this.input[start..this.offset];
+            this.offset = start;
+
+            if i > 0 {
+                match prev_key.cmp(key) {
+                    core::cmp::Ordering::Less => {}
+                    core::cmp::Ordering::Equal => return Err(CborError::DuplicateMapKey.into()),
+                    core::cmp::Ordering::Greater => return Err(CborError::UnsortedMapKeys.into()),
+                }
+            }
+            prev_key = key;
+
+            f(this, i)
+        })
+    }
+
+    #[inline(never)]
+    /// Skip the next data item, including all of its nested items
+    pub fn skip(&mut self) -> Result<(), CborError> {
+        match self.item()? {
+            Item::Array(len) => self.items(len, |this, _| this.skip()).map(|_| ()),
+            Item::Map(len) => self
+                .entries(len, |this, _| {
+                    this.skip()?;
+                    this.skip()
+                })
+                .map(|_| ()),
+            Item::Tag(_) => self.nested(|this| this.skip()),
+            Item::IndefiniteBytes => self.skip_chunks(MajorType::Bytes),
+            Item::IndefiniteText => self.skip_chunks(MajorType::Text),
+            Item::Break => Err(CborError::UnexpectedBreak),
+            _ => Ok(()),
+        }
+    }
+
+    fn skip_chunks(&mut self, major: MajorType) -> Result<(), CborError> {
+        while !self.at_break()? {
+            match self.item()? {
+                Item::Bytes(_) if major == MajorType::Bytes => {}
+                Item::Text(_) if major == MajorType::Text => {}
+                _ => return Err(CborError::InvalidChunk),
+            }
+        }
+        self.offset += 1;
+
+        Ok(())
+    }
+
+    /// Read a byte string of either definite or indefinite length,
+    /// calling `f` with each chunk
+    pub fn bytes_chunks<F>(&mut self, mut f: F) -> Result<(), CborError>
+    where
+        F: FnMut(&'b [u8]),
+    {
+        match self.expect(MajorType::Bytes)? {
+            Item::Bytes(b) => {
+                f(b);
+                Ok(())
+            }
+            _ => {
+                while !self.at_break()? {
+                    match self.item()? {
+                        Item::Bytes(b) => f(b),
+                        _ => return Err(CborError::InvalidChunk),
+                    }
+                }
+                self.offset += 1;
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Check if `value` can be encoded as an IEEE 754 half precision float without loss,
+/// with NaN always encoded as half precision
+fn fits_f16(value: f64) -> bool {
+    if value.is_nan() || value.is_infinite() || value == 0.0 {
+        return true;
+    }
+
+    let bits = value.to_bits();
+    let exp = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mant = bits & ((1 << 52) - 1);
+
+    //significant bits after the leading one, fewer for subnormals
+    let precision = match exp {
+        -14..=15 => 10,
+        -24..=-15 => exp + 24,
+        _ => return false,
+    };
+
+    mant & ((1 << (52 - precision)) - 1) == 0
+}
+
+/// Convert an IEEE 754 half precision float to f64
+fn f16_to_f64(half: u16) -> f64 {
+    let exp = ((half >> 10) & 0x1f) as i64;
+    let mant = (half & 0x3ff) as f64;
+
+    let value = match exp {
+        //subnormal, mant * 2^-24
+        0 => mant / (1u64 << 24) as f64,
+        31 if mant == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        //(1024 + mant) * 2^(exp - 25)
+        _ => (1024.0 + mant) * f64::from_bits(((exp - 25 + 1023) as u64) << 52),
+    };
+
+    if half & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// This trait allows types to be decoded from a [`Decoder`],
+/// and from bytes via [`Cbor`] or [`from_bytes_into`]
+pub trait FromCbor<'b>: Sized {
+    /// The concrete error type to return in case of failure
+    type Error: From<CborError>;
+
+    /// The configuration to use when decoding from bytes
+    const CONFIG: Config = Config::DEFAULT;
+
+    /// Decode exactly one data item from `decoder` into `out`
+    ///
+    /// It's a good idea to always put `#[inline(never)]` on top of this
+    /// function's implementation
+    fn from_cbor_into(
+        decoder: &mut Decoder<'b>,
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<(), Self::Error>;
+}
+
+#[inline(never)]
+/// Decode a `T` from the first data item in `input`, returning the remaining bytes
+///
+/// Useful to implement [`FromBytes`] for types implementing [`FromCbor`]
+pub fn from_bytes_into<'b, T: FromCbor<'b>>(
+    input: &'b [u8],
+    out: &mut MaybeUninit<T>,
+) -> Result<&'b [u8], T::Error> {
+    let mut decoder = Decoder::with_config(input, T::CONFIG);
+    T::from_cbor_into(&mut decoder, out)?;
+
+    Ok(decoder.remaining())
+}
+
+#[repr(transparent)]
+/// Adapter to parse any [`FromCbor`] type as [`FromBytes`],
+/// for example to use with [`ObjectList`](super::ObjectList) or on [`Uploader`](crate::Uploader) output
+pub struct Cbor<T>(pub T);
+
+impl<T> Cbor<T> {
+    /// Retrieve the decoded item
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<'b, T: FromCbor<'b>> FromBytes<'b> for Cbor<T> {
+    type Error = T::Error;
+
+    fn from_bytes_into(
+        input: &'b [u8],
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], Self::Error> {
+        //SAFE: Cbor is transparent over T
+        let out = unsafe { &mut *out.as_mut_ptr().cast::<MaybeUninit<T>>() };
+
+        from_bytes_into(input, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::ptr::addr_of_mut;
+
+    fn decode(input: &[u8]) -> Item<'_> {
+        let mut d = Decoder::new(input);
+        let item = d.item().unwrap();
+        assert!(d.remaining().is_empty());
+        item
+    }
+
+    #[test]
+    fn items() {
+        //RFC 8949 Appendix A
+        assert_eq!(decode(&[0x00]), Item::Unsigned(0));
+        assert_eq!(decode(&[0x18, 0x19]), Item::Unsigned(25));
+        assert_eq!(decode(&[0x39, 0x03, 0xe7]), Item::Negative(999));
+        assert_eq!(decode(&[0x44, 1, 2, 3, 4]), Item::Bytes(&[1, 2, 3, 4]));
+        assert_eq!(decode(b"\x64IETF"), Item::Text("IETF"));
+        assert_eq!(decode(&[0xf4]), Item::Bool(false));
+        assert_eq!(decode(&[0xf6]), Item::Null);
+        assert_eq!(decode(&[0xf8, 0xff]), Item::Simple(255));
+        assert_eq!(decode(&[0xf9, 0x3c, 0x00]), Item::Float(1.0));
+        assert_eq!(
+            decode(&[0xf9, 0x00, 0x01]),
+            Item::Float(5.960464477539063e-8)
+        );
+        assert_eq!(decode(&[0xf9, 0xc4, 0x00]), Item::Float(-4.0));
+        assert_eq!(
+            decode(&[0xfa, 0x47, 0xc3, 0x50, 0x00]),
+            Item::Float(100000.0)
+        );
+        assert_eq!(decode(&[0xc1]), Item::Tag(1));
+
+        assert_eq!(Decoder::new(&[0xf5]).bool(), Ok(true));
+        assert_eq!(Decoder::new(&[0xf6]).bool(), Err(CborError::NotABool));
+
+        assert_eq!(
+            Decoder::new(&[0x3b, 0xff, 0, 0, 0, 0, 0, 0, 0]).i64(),
+            Err(CborError::IntegerOverflow)
+        );
+        assert_eq!(Decoder::new(&[0x39, 0x03, 0xe7]).i64(), Ok(-1000));
+        assert_eq!(
+            Decoder::new(&[0xf8, 0x10]).item(),
+            Err(CborError::InvalidAdditionalInfo(24))
+        );
+    }
+
+    #[test]
+    fn nested() {
+        //[_ 1, [2, 3], [_ 4, 5]]
+        let input = [0x9f, 0x01, 0x82, 0x02, 0x03, 0x9f, 0x04, 0x05, 0xff, 0xff];
+
+        let mut sum = 0;
+        let len = Decoder::new(&input)
+            .array::<CborError, _>(|d, i| {
+                if i == 0 {
+                    sum += d.u64()?;
+                } else {
+                    d.array::<CborError, _>(|d, _| {
+                        sum += d.u64()?;
+                        Ok(())
+                    })?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(sum, 15);
+
+        let mut d = Decoder::new(&input);
+        d.skip().unwrap();
+        assert!(d.remaining().is_empty());
+
+        //indefinite lengths are rejected in canonical mode
+        assert_eq!(
+            Decoder::with_config(&input, Config::CANONICAL).skip(),
+            Err(CborError::IndefiniteLength)
+        );
+
+        //(_ h'0102', h'03')
+        let input = [0x5f, 0x42, 0x01, 0x02, 0x41, 0x03, 0xff];
+        let mut out = [0; 3];
+        let mut len = 0;
+        Decoder::new(&input)
+            .bytes_chunks(|chunk| {
+                out[len..][..chunk.len()].copy_from_slice(chunk);
+                len += chunk.len();
+            })
+            .unwrap();
+        assert_eq!(out, [1, 2, 3]);
+    }
+
+    #[test]
+    fn depth() {
+        let mut input = [0x81; 20];
+        input[19] = 0x00;
+
+        assert_eq!(Decoder::new(&input).skip(), Err(CborError::DepthExceeded));
+
+        let config = Config {
+            max_depth: 19,
+            ..Config::DEFAULT
+        };
+        assert_eq!(Decoder::with_config(&input, config).skip(), Ok(()));
+
+        //the depth is restored when a nested read fails
+        let mut d = Decoder::new(&[0x82, 0x01, 0x61, 0xff]);
+        let result = d.array(|d, _| d.u64().map(|_| ()));
+        assert!(matches!(result, Err(CborError::UnexpectedType { .. })));
+        assert_eq!(d.depth, 0);
+
+        let mut d = Decoder::new(&[0xc1, 0x81, 0xff]);
+        assert_eq!(d.skip(), Err(CborError::UnexpectedBreak));
+        assert_eq!(d.depth, 0);
+    }
+
+    #[test]
+    fn canonical() {
+        assert_eq!(
+            Decoder::with_config(&[0x18, 0x01], Config::CANONICAL).u64(),
+            Err(CborError::NonCanonical)
+        );
+        assert_eq!(Decoder::new(&[0x18, 0x01]).u64(), Ok(1));
+
+        //{3: 4, 1: 2}
+        let unsorted = [0xa2, 0x03, 0x04, 0x01, 0x02];
+        assert_eq!(Decoder::new(&unsorted).skip(), Ok(()));
+        assert_eq!(
+            Decoder::with_config(&unsorted, Config::CANONICAL).skip(),
+            Err(CborError::UnsortedMapKeys)
+        );
+
+        let duplicate = [0xa2, 0x01, 0x04, 0x01, 0x02];
+        assert_eq!(
+            Decoder::with_config(&duplicate, Config::CANONICAL).skip(),
+            Err(CborError::DuplicateMapKey)
+        );
+
+        fn canonical(input: &[u8]) -> Result<Item<'_>, CborError> {
+            Decoder::with_config(input, Config::CANONICAL).item()
+        }
+        //1.0 and 2^-24 fit in half precision, as does NaN
+        let one = [0xfb, 0x3f, 0xf0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(Decoder::new(&one).item(), Ok(Item::Float(1.0)));
+        assert_eq!(canonical(&one), Err(CborError::NonCanonical));
+        assert_eq!(
+            canonical(&[0xfa, 0x3f, 0x80, 0x00, 0x00]),
+            Err(CborError::NonCanonical)
+        );
+        assert_eq!(
+            canonical(&[0xfa, 0x33, 0x80, 0x00, 0x00]),
+            Err(CborError::NonCanonical)
+        );
+        assert_eq!(
+            canonical(&[0xfb, 0x7f, 0xf8, 0, 0, 0, 0, 0, 0]),
+            Err(CborError::NonCanonical)
+        );
+        //100000.0 needs single precision, 1.1 double
+        assert_eq!(
+            canonical(&[0xfa, 0x47, 0xc3, 0x50, 0x00]),
+            Ok(Item::Float(100000.0))
+        );
+        assert_eq!(
+            canonical(&[0xfb, 0x3f, 0xf1, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]),
+            Ok(Item::Float(1.1))
+        );
+        assert_eq!(canonical(&[0xf9, 0x3c, 0x00]), Ok(Item::Float(1.0)));
+    }
+
+    struct Transfer<'b> {
+        to: &'b [u8],
+        amount: u64,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum TransferError {
+        Cbor(CborError),
+        MissingField,
+    }
+
+    impl From<CborError> for TransferError {
+        fn from(e: CborError) -> Self {
+            Self::Cbor(e)
+        }
+    }
+
+    impl<'b> FromCbor<'b> for Transfer<'b> {
+        type Error = TransferError;
+
+        const CONFIG: Config = Config::CANONICAL;
+
+        fn from_cbor_into(
+            decoder: &mut Decoder<'b>,
+            out: &mut MaybeUninit<Self>,
+        ) -> Result<(), Self::Error> {
+            let out = out.as_mut_ptr();
+            let (mut to, mut amount) = (false, false);
+
+            decoder.map::<CborError, _>(|d, _| {
+                match d.u64()? {
+                    0 => unsafe {
+                        addr_of_mut!((*out).to).write(d.bytes()?);
+                        to = true;
+                    },
+                    1 => unsafe {
+                        addr_of_mut!((*out).amount).write(d.u64()?);
+                        amount = true;
+                    },
+                    _ => d.skip()?,
+                }
+                Ok(())
+            })?;
+
+            //every field must be written before the caller can assume init
+            if !(to && amount) {
+                return Err(TransferError::MissingField);
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn from_bytes() {
+        //{0: h'aabb', 1: 1000}, 0
+        let input = [0xa2, 0x00, 0x42, 0xaa, 0xbb, 0x01, 0x19, 0x03, 0xe8, 0x00];
+
+        let (rem, transfer) = Cbor::<Transfer>::from_bytes(&input).unwrap();
+        let transfer = transfer.into_inner();
+        assert_eq!(rem, &[0x00]);
+        assert_eq!(transfer.to, &[0xaa, 0xbb]);
+        assert_eq!(transfer.amount, 1000);
+
+        //{0: h'aabb'}
+        let input = [0xa1, 0x00, 0x42, 0xaa, 0xbb];
+        assert!(matches!(
+            Cbor::<Transfer>::from_bytes(&input),
+            Err(TransferError::MissingField)
+        ));
+    }
+}
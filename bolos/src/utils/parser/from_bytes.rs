@@ -15,6 +15,12 @@
 ********************************************************************************/
 use core::mem::MaybeUninit;
 
+use super::ParserError;
+use crate::{
+    crypto::bip32::{BIP32Path, BIP32PathError},
+    LedgerUnwrap,
+};
+
 ///This trait defines an useful interface to parse
 ///objects from bytes.
 ///this gives different objects in a transaction
@@ -51,3 +57,48 @@ pub trait FromBytes<'b>: Sized {
         out: &mut MaybeUninit<Self>,
     ) -> Result<&'b [u8], Self::Error>;
 }
+
+/// Reads the same format accepted by [`BIP32Path::read`]:
+/// the number of components followed by each component,
+/// leaving any trailing bytes untouched
+impl<'b, const MAX_LEN: usize> FromBytes<'b> for BIP32Path<MAX_LEN> {
+    type Error = BIP32PathError;
+
+    #[inline(never)]
+    fn from_bytes_into(
+        input: &'b [u8],
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], Self::Error> {
+        let len = *input.first().ok_or(BIP32PathError::ZeroLength)? as usize;
+        if len > MAX_LEN {
+            return Err(BIP32PathError::TooMuchData);
+        }
+
+        let path_len = 1 + 4 * len;
+        if input.len() < path_len {
+            return Err(BIP32PathError::NotEnoughData);
+        }
+
+        let (path, rem) = input.split_at(path_len);
+        out.write(BIP32Path::read(path)?);
+
+        Ok(rem)
+    }
+}
+
+/// Borrows the next `N` bytes, for fixed size data like keys and hashes
+impl<'b, const N: usize> FromBytes<'b> for &'b [u8; N] {
+    type Error = ParserError;
+
+    #[inline]
+    fn from_bytes_into(
+        input: &'b [u8],
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], Self::Error> {
+        let (rem, bytes) = super::take(input, N)?;
+        //take guarantees the length
+        out.write(bytes.try_into().ledger_unwrap());
+
+        Ok(rem)
+    }
+}
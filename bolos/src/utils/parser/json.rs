@@ -0,0 +1,695 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Allocation-free JSON tokenizer
+//!
+//! The input is split into at most `N` [`Token`]s, stored in pre-order in a fixed size array.
+//! Tokens only reference the input, so strings are returned raw, with their escapes intact.
+//!
+//! Values can be looked up by path (`msgs[0].value.amount`), and [`Leaves`]
+//! allows displaying every leaf value, titled with its path.
+
+use zemu_sys::ViewError;
+
+use crate::{ui::DisplayableItem, ApduError};
+
+/// Maximum nesting of objects and arrays
+pub const MAX_DEPTH: usize = 16;
+
+const NONE: u16 = u16::MAX;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Errors that can occur when tokenizing or querying JSON
+pub enum JsonError {
+    /// The input is not valid JSON, `position` is the offset of the offending byte
+    Syntax { position: usize },
+    /// The input has more tokens than the capacity
+    TooManyTokens,
+    /// The nesting exceeded [`MAX_DEPTH`]
+    TooDeep,
+    /// The input exceeds the maximum supported length of 65534 bytes
+    TooLong,
+    /// Whitespace was found outside of strings in canonical mode
+    Whitespace,
+    /// The keys of an object were not sorted in canonical mode
+    UnsortedKeys,
+    /// The same key was found twice in an object in canonical mode
+    DuplicateKey,
+    /// The path was not valid
+    InvalidPath,
+    /// No value was found at the given path
+    NotFound,
+}
+
+impl From<JsonError> for ApduError {
+    fn from(_: JsonError) -> Self {
+        ApduError::DataInvalid
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum TokenKind {
+    Object,
+    Array,
+    /// Key of an object member, immediately followed by its value
+    Key,
+    String,
+    /// Number, `true`, `false` or `null`
+    Primitive,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// A single JSON value or key
+pub struct Token {
+    pub kind: TokenKind,
+    start: u16,
+    end: u16,
+    /// Number of members of an object or elements of an array
+    size: u16,
+    parent: u16,
+    /// Index of the first token after this one and its children
+    next: u16,
+}
+
+impl Token {
+    const EMPTY: Self = Self {
+        kind: TokenKind::Primitive,
+        start: 0,
+        end: 0,
+        size: 0,
+        parent: NONE,
+        next: 0,
+    };
+
+    /// Number of members of an object or elements of an array
+    pub fn size(&self) -> usize {
+        self.size as usize
+    }
+
+    /// Returns true if the token is a value without children
+    pub fn is_leaf(&self) -> bool {
+        match self.kind {
+            TokenKind::Key => false,
+            TokenKind::Object | TokenKind::Array => self.size == 0,
+            _ => true,
+        }
+    }
+}
+
+/// Tokenized JSON document, with room for `N` tokens
+pub struct Json<'b, const N: usize> {
+    input: &'b [u8],
+    tokens: [Token; N],
+    len: usize,
+    whitespace: bool,
+}
+
+impl<'b, const N: usize> Json<'b, N> {
+    #[inline(never)]
+    /// Tokenize `input`, which must contain exactly one JSON value
+    pub fn parse(input: &'b [u8]) -> Result<Self, JsonError> {
+        if input.len() >= NONE as usize || N >= NONE as usize {
+            return Err(JsonError::TooLong);
+        }
+
+        let mut this = Self {
+            input,
+            tokens: [Token::EMPTY; N],
+            len: 0,
+            whitespace: false,
+        };
+
+        let mut pos = this.skip_whitespace(0);
+        pos = this.value(pos, NONE, 0)?;
+        pos = this.skip_whitespace(pos);
+        if pos != input.len() {
+            return Err(JsonError::Syntax { position: pos });
+        }
+
+        Ok(this)
+    }
+
+    /// Number of tokens
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Retrieve the token at `idx`
+    pub fn token(&self, idx: usize) -> Option<&Token> {
+        self.tokens[..self.len].get(idx)
+    }
+
+    /// Retrieve the raw bytes of the token at `idx`
+    ///
+    /// Strings and keys are returned without quotes and with escapes intact
+    pub fn bytes(&self, idx: usize) -> Option<&'b [u8]> {
+        self.token(idx)
+            .map(|t| &self.input[t.start as usize..t.end as usize])
+    }
+
+    fn byte(&self, pos: usize) -> Result<u8, JsonError> {
+        self.input
+            .get(pos)
+            .copied()
+            .ok_or(JsonError::Syntax { position: pos })
+    }
+
+    fn skip_whitespace(&mut self, mut pos: usize) -> usize {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.input.get(pos) {
+            self.whitespace = true;
+            pos += 1;
+        }
+
+        pos
+    }
+
+    fn push(&mut self, kind: TokenKind, start: usize, parent: u16) -> Result<usize, JsonError> {
+        let idx = self.len;
+        let token = self.tokens.get_mut(idx).ok_or(JsonError::TooManyTokens)?;
+        *token = Token {
+            kind,
+            start: start as u16,
+            parent,
+            ..Token::EMPTY
+        };
+        self.len += 1;
+
+        Ok(idx)
+    }
+
+    fn close(&mut self, idx: usize, end: usize) {
+        let next = self.len as u16;
+        let token = &mut self.tokens[idx];
+        token.end = end as u16;
+        token.next = next;
+    }
+
+    #[inline(never)]
+    /// Tokenize the value starting at `pos`, returning the position after it
+    fn value(&mut self, pos: usize, parent: u16, depth: usize) -> Result<usize, JsonError> {
+        match self.byte(pos)? {
+            open @ (b'{' | b'[') => {
+                if depth >= MAX_DEPTH {
+                    return Err(JsonError::TooDeep);
+                }
+
+                let is_object = open == b'{';
+                let (kind, close) = if is_object {
+                    (TokenKind::Object, b'}')
+                } else {
+                    (TokenKind::Array, b']')
+                };
+                let idx = self.push(kind, pos, parent)?;
+
+                let mut pos = self.skip_whitespace(pos + 1);
+                if self.byte(pos)? == close {
+                    self.close(idx, pos + 1);
+                    return Ok(pos + 1);
+                }
+
+                loop {
+                    if is_object {
+                        if self.byte(pos)? != b'"' {
+                            return Err(JsonError::Syntax { position: pos });
+                        }
+                        pos = self.string(pos, TokenKind::Key, idx as u16)?;
+                        pos = self.skip_whitespace(pos);
+                        if self.byte(pos)? != b':' {
+                            return Err(JsonError::Syntax { position: pos });
+                        }
+                        pos = self.skip_whitespace(pos + 1);
+                    }
+
+                    pos = self.value(pos, idx as u16, depth + 1)?;
+                    self.tokens[idx].size += 1;
+
+                    pos = self.skip_whitespace(pos);
+                    match self.byte(pos)? {
+                        b',' => pos = self.skip_whitespace(pos + 1),
+                        b if b == close => break,
+                        _ => return Err(JsonError::Syntax { position: pos }),
+                    }
+                }
+
+                self.close(idx, pos + 1);
+                Ok(pos + 1)
+            }
+            b'"' => self.string(pos, TokenKind::String, parent),
+            _ => self.primitive(pos, parent),
+        }
+    }
+
+    #[inline(never)]
+    fn string(&mut self, pos: usize, kind: TokenKind, parent: u16) -> Result<usize, JsonError> {
+        let idx = self.push(kind, pos + 1, parent)?;
+
+        let mut pos = pos + 1;
+        loop {
+            match self.byte(pos)? {
+                b'"' => break,
+                b'\\' => {
+                    pos += 1;
+                    match self.byte(pos)? {
+                        b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => {}
+                        b'u' => {
+                            for _ in 0..4 {
+                                pos += 1;
+                                if !self.byte(pos)?.is_ascii_hexdigit() {
+                                    return Err(JsonError::Syntax { position: pos });
+                                }
+                            }
+                        }
+                        _ => return Err(JsonError::Syntax { position: pos }),
+                    }
+                }
+                //control characters must be escaped
+                0..=0x1f => return Err(JsonError::Syntax { position: pos }),
+                _ => {}
+            }
+            pos += 1;
+        }
+
+        self.close(idx, pos);
+        Ok(pos + 1)
+    }
+
+    #[inline(never)]
+    fn primitive(&mut self, start: usize, parent: u16) -> Result<usize, JsonError> {
+        let rest = &self.input[start..];
+
+        let len = if rest.starts_with(b"true") || rest.starts_with(b"null") {
+            4
+        } else if rest.starts_with(b"false") {
+            5
+        } else {
+            number_len(rest).ok_or(JsonError::Syntax { position: start })?
+        };
+
+        let idx = self.push(TokenKind::Primitive, start, parent)?;
+        self.close(idx, start + len);
+
+        Ok(start + len)
+    }
+
+    #[inline(never)]
+    /// Ensure the document is canonical: no whitespace outside of strings
+    /// and the keys of every object sorted and unique
+    pub fn validate_canonical(&self) -> Result<(), JsonError> {
+        if self.whitespace {
+            return Err(JsonError::Whitespace);
+        }
+
+        for (idx, token) in self.tokens[..self.len].iter().enumerate() {
+            if token.kind != TokenKind::Object {
+                continue;
+            }
+
+            let mut prev: Option<&[u8]> = None;
+            let mut key = idx + 1;
+            for _ in 0..token.size {
+                let current = self.bytes(key).unwrap_or_default();
+                match prev.map(|prev| prev.cmp(current)) {
+                    Some(core::cmp::Ordering::Equal) => return Err(JsonError::DuplicateKey),
+                    Some(core::cmp::Ordering::Greater) => return Err(JsonError::UnsortedKeys),
+                    _ => {}
+                }
+                prev = Some(current);
+
+                //skip the value
+                key = self.tokens[key + 1].next as usize;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retrieve the index of the value of `key` in the object at `object`
+    pub fn object_get(&self, object: usize, key: &[u8]) -> Option<usize> {
+        let token = self.token(object)?;
+        if token.kind != TokenKind::Object {
+            return None;
+        }
+
+        let mut idx = object + 1;
+        for _ in 0..token.size {
+            if self.bytes(idx)? == key {
+                return Some(idx + 1);
+            }
+            idx = self.tokens[idx + 1].next as usize;
+        }
+
+        None
+    }
+
+    /// Retrieve the index of the `n`th element of the array at `array`
+    pub fn array_get(&self, array: usize, n: usize) -> Option<usize> {
+        let token = self.token(array)?;
+        if token.kind != TokenKind::Array || n >= token.size() {
+            return None;
+        }
+
+        let mut idx = array + 1;
+        for _ in 0..n {
+            idx = self.tokens[idx].next as usize;
+        }
+
+        Some(idx)
+    }
+
+    #[inline(never)]
+    /// Retrieve the index of the value at `path`, like `msgs[0].value.amount`
+    ///
+    /// An empty path refers to the root value
+    pub fn lookup(&self, path: &str) -> Result<usize, JsonError> {
+        let mut path = path.as_bytes();
+        let mut idx = 0;
+        let mut first = true;
+
+        while !path.is_empty() {
+            if let Some(rest) = path.strip_prefix(b"[") {
+                let end = rest
+                    .iter()
+                    .position(|&b| b == b']')
+                    .ok_or(JsonError::InvalidPath)?;
+                let n = parse_index(&rest[..end]).ok_or(JsonError::InvalidPath)?;
+
+                idx = self.array_get(idx, n).ok_or(JsonError::NotFound)?;
+                path = &rest[end + 1..];
+            } else {
+                let rest = if first {
+                    path
+                } else {
+                    path.strip_prefix(b".").ok_or(JsonError::InvalidPath)?
+                };
+                let end = rest
+                    .iter()
+                    .position(|&b| b == b'.' || b == b'[')
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    return Err(JsonError::InvalidPath);
+                }
+
+                idx = self
+                    .object_get(idx, &rest[..end])
+                    .ok_or(JsonError::NotFound)?;
+                path = &rest[end..];
+            }
+
+            first = false;
+        }
+
+        if self.is_empty() {
+            return Err(JsonError::NotFound);
+        }
+
+        Ok(idx)
+    }
+
+    /// Adapter to display every leaf of the document
+    pub fn leaves(&self) -> Leaves<'_, 'b, N> {
+        Leaves { json: self }
+    }
+}
+
+/// Length of the number at the start of `input`, if valid
+fn number_len(input: &[u8]) -> Option<usize> {
+    let digits = |from: usize| {
+        input[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+
+    let mut len = (input.first() == Some(&b'-')) as usize;
+
+    //no leading zeros
+    match input.get(len) {
+        Some(b'0') => len += 1,
+        Some(b'1'..=b'9') => len += digits(len),
+        _ => return None,
+    }
+
+    if input.get(len) == Some(&b'.') {
+        let fraction = digits(len + 1);
+        if fraction == 0 {
+            return None;
+        }
+        len += 1 + fraction;
+    }
+
+    if let Some(b'e' | b'E') = input.get(len) {
+        len += 1;
+        if let Some(b'+' | b'-') = input.get(len) {
+            len += 1;
+        }
+        let exponent = digits(len);
+        if exponent == 0 {
+            return None;
+        }
+        len += exponent;
+    }
+
+    Some(len)
+}
+
+fn parse_index(digits: &[u8]) -> Option<usize> {
+    if digits.is_empty() || (digits.len() > 1 && digits[0] == b'0') {
+        return None;
+    }
+
+    digits.iter().try_fold(0usize, |n, &d| {
+        if !d.is_ascii_digit() {
+            return None;
+        }
+        n.checked_mul(10)?.checked_add((d - b'0') as usize)
+    })
+}
+
+/// Writes into a buffer, silently truncating
+struct Truncating<'o> {
+    out: &'o mut [u8],
+    written: usize,
+}
+
+impl Truncating<'_> {
+    fn write(&mut self, bytes: &[u8]) {
+        let room = self.out.len() - self.written;
+        let len = bytes.len().min(room);
+        self.out[self.written..][..len].copy_from_slice(&bytes[..len]);
+        self.written += len;
+    }
+
+    fn write_usize(&mut self, mut n: usize) {
+        let mut digits = [0; 20];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            digits[i] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+
+        self.write(&digits[i..]);
+    }
+}
+
+/// [`DisplayableItem`] over every leaf of a [`Json`] document
+///
+/// Each leaf (strings, primitives and empty objects or arrays) is an item,
+/// titled with its path and paged with [`handle_message`](crate::ui::handle_message)
+pub struct Leaves<'j, 'b, const N: usize> {
+    json: &'j Json<'b, N>,
+}
+
+impl<'j, 'b, const N: usize> Leaves<'j, 'b, N> {
+    /// Retrieve the token index of the `n`th leaf
+    pub fn nth(&self, n: usize) -> Option<usize> {
+        self.json.tokens[..self.json.len]
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.is_leaf())
+            .nth(n)
+            .map(|(idx, _)| idx)
+    }
+
+    /// Write the path of the token at `idx`, truncating if needed
+    fn write_path(&self, idx: usize, out: &mut Truncating<'_>) {
+        let parent = self.json.tokens[idx].parent;
+        if parent == NONE {
+            return;
+        }
+        let parent = parent as usize;
+        self.write_path(parent, out);
+
+        match self.json.tokens[parent].kind {
+            TokenKind::Object => {
+                if out.written > 0 {
+                    out.write(b".");
+                }
+                //the key always precedes the value
+                out.write(self.json.bytes(idx - 1).unwrap_or_default());
+            }
+            _ => {
+                let mut n = 0;
+                let mut elem = parent + 1;
+                while elem != idx {
+                    elem = self.json.tokens[elem].next as usize;
+                    n += 1;
+                }
+
+                out.write(b"[");
+                out.write_usize(n);
+                out.write(b"]");
+            }
+        }
+    }
+}
+
+impl<'j, 'b, const N: usize> DisplayableItem for Leaves<'j, 'b, N> {
+    fn num_items(&self) -> usize {
+        self.json.tokens[..self.json.len]
+            .iter()
+            .filter(|t| t.is_leaf())
+            .count()
+    }
+
+    #[inline(never)]
+    fn render_item(
+        &self,
+        item_n: u8,
+        title: &mut [u8],
+        message: &mut [u8],
+        page: u8,
+    ) -> Result<u8, ViewError> {
+        let idx = self.nth(item_n as usize).ok_or(ViewError::NoData)?;
+
+        if let Some((_, path)) = title.split_last_mut() {
+            let mut out = Truncating {
+                out: path,
+                written: 0,
+            };
+            self.write_path(idx, &mut out);
+            let written = out.written;
+            title[written] = 0;
+        }
+
+        let value = self.json.bytes(idx).ok_or(ViewError::NoData)?;
+        crate::ui::handle_message(value, message, page).ok_or(ViewError::NoData)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGN_DOC: &[u8] = br#"{"account_number":"108","chain_id":"cosmoshub-4","fee":{"amount":[{"amount":"600","denom":"uatom"}],"gas":"200000"},"memo":"","msgs":[{"type":"cosmos-sdk/MsgSend","value":{"amount":[{"amount":"1000000","denom":"uatom"}],"from_address":"cosmos1a","to_address":"cosmos1b"}}],"sequence":"1"}"#;
+
+    type Doc<'b> = Json<'b, 64>;
+
+    #[test]
+    fn tokenize() {
+        let json = Doc::parse(br#" {"a": [1, -2.5e3, true, null], "b\"": {} } "#).unwrap();
+        assert_eq!(json.len(), 9);
+        assert_eq!(json.token(0).unwrap().size(), 2);
+        assert_eq!(json.bytes(3), Some(&b"1"[..]));
+        assert_eq!(json.bytes(4), Some(&b"-2.5e3"[..]));
+        assert_eq!(json.bytes(7), Some(&br#"b\""#[..]));
+        assert_eq!(json.bytes(8), Some(&b"{}"[..]));
+        assert_eq!(json.validate_canonical(), Err(JsonError::Whitespace));
+
+        assert_eq!(
+            Doc::parse(b"[01]").err(),
+            Some(JsonError::Syntax { position: 2 })
+        );
+        assert_eq!(
+            Doc::parse(b"{\"a\":1,}").err(),
+            Some(JsonError::Syntax { position: 7 })
+        );
+        assert_eq!(
+            Doc::parse(b"\"\x01\"").err(),
+            Some(JsonError::Syntax { position: 1 })
+        );
+        assert_eq!(
+            Doc::parse(b"[[[[[[[[[[[[[[[[[]]]]]]]]]]]]]]]]]").err(),
+            Some(JsonError::TooDeep)
+        );
+        assert_eq!(
+            Json::<2>::parse(b"[1,2]").err(),
+            Some(JsonError::TooManyTokens)
+        );
+    }
+
+    #[test]
+    fn canonical() {
+        let json = Doc::parse(SIGN_DOC).unwrap();
+        assert_eq!(json.validate_canonical(), Ok(()));
+
+        let json = Doc::parse(br#"{"b":1,"a":2}"#).unwrap();
+        assert_eq!(json.validate_canonical(), Err(JsonError::UnsortedKeys));
+
+        let json = Doc::parse(br#"{"a":{"x":1,"x":2}}"#).unwrap();
+        assert_eq!(json.validate_canonical(), Err(JsonError::DuplicateKey));
+    }
+
+    #[test]
+    fn lookup() {
+        let json = Doc::parse(SIGN_DOC).unwrap();
+
+        let amount = json.lookup("msgs[0].value.amount[0].amount").unwrap();
+        assert_eq!(json.bytes(amount), Some(&b"1000000"[..]));
+
+        let chain_id = json.lookup("chain_id").unwrap();
+        assert_eq!(json.bytes(chain_id), Some(&b"cosmoshub-4"[..]));
+
+        assert_eq!(json.lookup(""), Ok(0));
+        assert_eq!(json.lookup("msgs[1]"), Err(JsonError::NotFound));
+        assert_eq!(json.lookup("fee.nope"), Err(JsonError::NotFound));
+        assert_eq!(json.lookup("msgs[0"), Err(JsonError::InvalidPath));
+        assert_eq!(json.lookup("msgs..type"), Err(JsonError::InvalidPath));
+    }
+
+    #[test]
+    fn display() {
+        let json = Doc::parse(SIGN_DOC).unwrap();
+        let leaves = json.leaves();
+        assert_eq!(leaves.num_items(), 12);
+
+        let mut title = [0; 32];
+        let mut message = [0; 8];
+
+        let pages = leaves.render_item(7, &mut title, &mut message, 0);
+        assert_eq!(&title[..30], b"msgs[0].value.amount[0].amount");
+        assert_eq!(title[30], 0);
+        assert_eq!(&message[..8], b"1000000\0");
+        assert!(pages == Ok(1));
+
+        //truncated title
+        let mut title = [0; 10];
+        assert!(leaves.render_item(2, &mut title, &mut message, 0).is_ok());
+        assert_eq!(&title, b"fee.amoun\0");
+        assert_eq!(&message[..4], b"600\0");
+
+        assert!(leaves.render_item(12, &mut title, &mut message, 0) == Err(ViewError::NoData));
+    }
+}
@@ -17,26 +17,54 @@ use core::{marker::PhantomData, mem::MaybeUninit, ptr::addr_of_mut};
 use educe::Educe;
 
 use super::FromBytes;
-use crate::LedgerUnwrap;
+use crate::{ApduError, LedgerUnwrap};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Error returned when an element of an [`ObjectList`] failed to parse
+pub struct ObjectListError<E> {
+    /// Index of the element that failed to parse
+    pub index: usize,
+    /// The error of the element
+    pub error: E,
+}
+
+impl<E> From<ObjectListError<E>> for ApduError
+where
+    ApduError: From<E>,
+{
+    fn from(e: ObjectListError<E>) -> Self {
+        e.error.into()
+    }
+}
 
 #[derive(Educe)]
 #[cfg_attr(test, educe(Debug))]
 #[educe(Clone, Copy, PartialEq, Eq)]
 /// Represents an object list
-pub struct ObjectList<'b, Obj> {
+///
+/// `OFFSETS` is the number of object offsets to record while parsing,
+/// allowing random access to the first `OFFSETS` objects without
+/// parsing the objects preceding them
+pub struct ObjectList<'b, Obj, const OFFSETS: usize = 0> {
     // raw data containing serialized objects
     data: &'b [u8],
     // counter used to track the amount of bytes
     // that were read when parsing an inner element in the list
     #[educe(PartialEq(ignore))]
     read: usize,
+    // number of objects in the list
+    len: usize,
+    // starting offset in `data` of the first `OFFSETS` objects
+    #[educe(PartialEq(ignore))]
+    offsets: [usize; OFFSETS],
     // type of object that the ObjectList contains
     #[cfg_attr(test, educe(Debug(ignore)))]
     #[educe(PartialEq(ignore))]
     _phantom: PhantomData<Obj>,
 }
 
-impl<'b, Obj> ObjectList<'b, Obj>
+impl<'b, Obj, const OFFSETS: usize> ObjectList<'b, Obj, OFFSETS>
 where
     Obj: FromBytes<'b>,
 {
@@ -56,19 +84,44 @@ where
     ///
     /// Will fail if the input bytes are not properly encoded for the list or if any of the objects inside fail to parse.
     /// This also means accessing any inner objects shouldn't fail to parse
+    ///
+    /// See [`ObjectList::try_new_into`] to also retrieve the index of the object that failed to parse
     pub fn new_into(
         input: &'b [u8],
         num_objs: usize,
         out: &mut MaybeUninit<Self>,
     ) -> Result<&'b [u8], Obj::Error> {
+        Self::try_new_into(input, num_objs, out).map_err(|e| e.error)
+    }
+
+    #[inline(never)]
+    /// Same as [`ObjectList::new_into`], but the error includes the index
+    /// of the object that failed to parse
+    pub fn try_new_into(
+        input: &'b [u8],
+        num_objs: usize,
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], ObjectListError<Obj::Error>> {
         let mut len = input.len();
         let mut bytes_left = input;
         let mut object = MaybeUninit::uninit();
+        let mut offsets = [0; OFFSETS];
 
         // we are not saving parsed data but ensuring everything
         // parsed correctly.
-        for _ in 0..num_objs {
-            bytes_left = Obj::from_bytes_into(bytes_left, &mut object)?;
+        for index in 0..num_objs {
+            if let Some(offset) = offsets.get_mut(index) {
+                *offset = len - bytes_left.len();
+            }
+
+            bytes_left = Obj::from_bytes_into(bytes_left, &mut object)
+                .map_err(|error| ObjectListError { index, error })?;
+
+            // drop the object, this is safe
+            // as it was just initialized
+            unsafe {
+                object.as_mut_ptr().drop_in_place();
+            }
         }
 
         // this calculates the length in bytes of the list of objects
@@ -85,11 +138,65 @@ where
         unsafe {
             addr_of_mut!((*out).read).write(0);
             addr_of_mut!((*out).data).write(data);
+            addr_of_mut!((*out).len).write(num_objs);
+            addr_of_mut!((*out).offsets).write(offsets);
+            addr_of_mut!((*out)._phantom).write(PhantomData);
         }
 
         Ok(rem)
     }
 
+    /// Returns the number of objects in the list
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the list has no objects
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline(never)]
+    /// Parses the object at `index` into the given location.
+    ///
+    /// If the object's offset was recorded it is parsed directly,
+    /// otherwise the objects following the last recorded offset are parsed to reach it.
+    ///
+    /// If `index` is out of bounds, then None is returned.
+    ///
+    /// This function does not change the internal state.
+    pub fn get_into(&self, index: usize, out: &mut MaybeUninit<Obj>) -> Option<()> {
+        if index >= self.len {
+            return None;
+        }
+
+        //closest known starting point
+        let recorded = self.len.min(OFFSETS);
+        let (mut current, offset) = if index < recorded {
+            (index, self.offsets[index])
+        } else if recorded > 0 {
+            (recorded - 1, self.offsets[recorded - 1])
+        } else {
+            (0, 0)
+        };
+
+        let mut data = &self.data[offset..];
+        loop {
+            //ok to panic as we parsed beforehand
+            data = Obj::from_bytes_into(data, out).ledger_unwrap();
+            if current == index {
+                return Some(());
+            }
+
+            // drop the object, this is safe
+            // as user does not hold a reference to it
+            unsafe {
+                out.as_mut_ptr().drop_in_place();
+            }
+            current += 1;
+        }
+    }
+
     #[inline(never)]
     /// Parses an object into the given location, returning the amount of bytes read.
     ///
@@ -211,7 +318,7 @@ where
     }
 }
 
-impl<'b, Obj> ObjectList<'b, Obj>
+impl<'b, Obj, const OFFSETS: usize> ObjectList<'b, Obj, OFFSETS>
 where
     Obj: FromBytes<'b> + 'b,
 {
@@ -221,11 +328,11 @@ where
     }
 }
 
-struct ObjectListIterator<'b, Obj: FromBytes<'b>> {
-    list: ObjectList<'b, Obj>,
+struct ObjectListIterator<'b, Obj: FromBytes<'b>, const OFFSETS: usize> {
+    list: ObjectList<'b, Obj, OFFSETS>,
 }
 
-impl<'b, Obj> ObjectListIterator<'b, Obj>
+impl<'b, Obj, const OFFSETS: usize> ObjectListIterator<'b, Obj, OFFSETS>
 where
     Obj: FromBytes<'b>,
 {
@@ -233,7 +340,7 @@ where
     ///
     /// Iteration will always start from the beginning as the internal cursor
     /// of the copied list is reset
-    fn new(list: &ObjectList<'b, Obj>) -> Self {
+    fn new(list: &ObjectList<'b, Obj, OFFSETS>) -> Self {
         // we do not want to change the state
         // of the passed in list, as a result, we just
         // make a copy, so we can reset the read index,
@@ -248,7 +355,7 @@ where
     }
 }
 
-impl<'b, Obj> Iterator for ObjectListIterator<'b, Obj>
+impl<'b, Obj, const OFFSETS: usize> Iterator for ObjectListIterator<'b, Obj, OFFSETS>
 where
     Obj: FromBytes<'b>,
 {
@@ -326,4 +433,38 @@ mod tests {
 
         assert_eq!(num_objs, count);
     }
+
+    #[test]
+    fn random_access() {
+        let data = generate_data(10);
+        let number = |i: usize| u32::from_be_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+
+        let mut out = MaybeUninit::uninit();
+        let (_, list) = ObjectList::<Data, 4>::new(&data, 10).unwrap();
+        assert_eq!(list.len(), 10);
+        for i in [9, 0, 3, 4, 7] {
+            list.get_into(i, &mut out).unwrap();
+            assert_eq!(unsafe { out.assume_init_ref() }.number, number(i));
+        }
+        assert!(list.get_into(10, &mut out).is_none());
+
+        //no recorded offsets
+        let (_, list) = ObjectList::<Data>::new(&data, 10).unwrap();
+        list.get_into(5, &mut out).unwrap();
+        assert_eq!(unsafe { out.assume_init_ref() }.number, number(5));
+
+        //more offsets than objects
+        let (_, list) = ObjectList::<Data, 16>::new(&data, 3).unwrap();
+        list.get_into(2, &mut out).unwrap();
+        assert_eq!(unsafe { out.assume_init_ref() }.number, number(2));
+        assert!(list.get_into(3, &mut out).is_none());
+    }
+
+    #[test]
+    fn failing_index() {
+        let data = generate_data(3);
+        let mut list = MaybeUninit::uninit();
+        let err = ObjectList::<Data>::try_new_into(&data[..10], 3, &mut list).unwrap_err();
+        assert_eq!(err.index, 2);
+    }
 }
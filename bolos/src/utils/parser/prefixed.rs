@@ -0,0 +1,168 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Length prefixed byte vectors and sequences, shared by the formats
+//! that only differ in how the length is encoded, like [SCALE](super::scale) and [Borsh](super::borsh),
+//! together with the `0`/`1` tagged booleans and options both formats use
+
+use core::{marker::PhantomData, mem::MaybeUninit, ptr::addr_of_mut};
+use educe::Educe;
+
+use super::{be_u8, take, FromBytes, ObjectList, ParserError, ParserResult};
+
+/// Decoder of the length prefix of [`Bytes`] and [`Seq`]
+pub trait LengthPrefix {
+    /// Read the length, in bytes for [`Bytes`] and in items for [`Seq`]
+    fn length(input: &[u8]) -> ParserResult<'_, usize>;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Byte vector, prefixed with its length as read by `L`
+pub struct Bytes<'b, L>(pub &'b [u8], PhantomData<L>);
+
+impl<'b, L> Bytes<'b, L> {
+    pub const fn new(bytes: &'b [u8]) -> Self {
+        Self(bytes, PhantomData)
+    }
+}
+
+impl<'b, L: LengthPrefix> FromBytes<'b> for Bytes<'b, L> {
+    type Error = ParserError;
+
+    #[inline(never)]
+    fn from_bytes_into(
+        input: &'b [u8],
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], Self::Error> {
+        let (rem, len) = L::length(input)?;
+        let (rem, bytes) = take(rem, len)?;
+        out.write(Bytes::new(bytes));
+
+        Ok(rem)
+    }
+}
+
+#[derive(Educe)]
+#[cfg_attr(test, educe(Debug))]
+#[educe(Clone, Copy, PartialEq, Eq)]
+/// Sequence of `T`, prefixed with its count as read by `L`
+///
+/// All the items are validated when parsing, and can then be read from [`Seq::list`]
+pub struct Seq<'b, T, L> {
+    list: ObjectList<'b, T>,
+    _prefix: PhantomData<L>,
+}
+
+impl<'b, T: FromBytes<'b>, L> Seq<'b, T, L> {
+    /// Number of items in the sequence
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Returns true if the sequence has no items
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Retrieve the items of the sequence
+    pub fn list(&self) -> &ObjectList<'b, T> {
+        &self.list
+    }
+}
+
+impl<'b, T, L> FromBytes<'b> for Seq<'b, T, L>
+where
+    T: FromBytes<'b>,
+    T::Error: From<ParserError>,
+    L: LengthPrefix,
+{
+    type Error = T::Error;
+
+    #[inline(never)]
+    fn from_bytes_into(
+        input: &'b [u8],
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], Self::Error> {
+        let (rem, len) = L::length(input)?;
+
+        let out = out.as_mut_ptr();
+        //good ptr and no uninit reads
+        let list = unsafe { &mut *addr_of_mut!((*out).list).cast() };
+        let rem = ObjectList::<T>::new_into(rem, len, list)?;
+        unsafe { addr_of_mut!((*out)._prefix).write(PhantomData) };
+
+        Ok(rem)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Boolean encoded as a single byte, `0` for false and `1` for true
+pub struct Bool(pub bool);
+
+impl<'b> FromBytes<'b> for Bool {
+    type Error = ParserError;
+
+    #[inline]
+    fn from_bytes_into(
+        input: &'b [u8],
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], Self::Error> {
+        let (rem, tag) = be_u8(input)?;
+        match tag {
+            0 | 1 => {
+                out.write(Bool(tag == 1));
+                Ok(rem)
+            }
+            _ => Err(ParserError::InvalidTag(tag)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Optional `T`, encoded as a tag byte, `0` for [`None`] or `1` followed by the value for [`Some`]
+pub struct Tagged<T>(pub Option<T>);
+
+impl<'b, T> FromBytes<'b> for Tagged<T>
+where
+    T: FromBytes<'b>,
+    T::Error: From<ParserError>,
+{
+    type Error = T::Error;
+
+    #[inline(never)]
+    fn from_bytes_into(
+        input: &'b [u8],
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], Self::Error> {
+        let (rem, tag) = be_u8(input)?;
+        match tag {
+            0 => {
+                out.write(Tagged(None));
+                Ok(rem)
+            }
+            1 => {
+                let mut value = MaybeUninit::uninit();
+                let rem = T::from_bytes_into(rem, &mut value)?;
+                out.write(Tagged(Some(unsafe { value.assume_init() })));
+
+                Ok(rem)
+            }
+            _ => Err(ParserError::InvalidTag(tag).into()),
+        }
+    }
+}
@@ -0,0 +1,352 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Small set of allocation-free parsers to build [`FromBytes`](super::FromBytes)
+//! implementations with.
+//!
+//! All parsers take the input and return the remaining bytes alongside the parsed value,
+//! so they can be chained just like `nom` parsers.
+
+use core::mem::MaybeUninit;
+
+use super::{FromBytes, ToBytes};
+use crate::{ApduError, OutputBufferTooSmall};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Errors that the parsers in this module can produce
+pub enum ParserError {
+    /// The input ended before the value could be read,
+    /// `needed` is the number of missing bytes
+    UnexpectedEof { needed: usize },
+    /// The encoded varint doesn't fit in the requested integer type
+    VarintOverflow,
+    /// The varint was not encoded with the minimum number of bytes
+    NonCanonicalVarint,
+    /// The decoded length doesn't fit in `usize`
+    LengthOverflow,
+    /// The number of items to read exceeded the given bound
+    TooManyItems { count: usize, max: usize },
+    /// The tag of an enum, option or boolean had an unknown value
+    InvalidTag(u8),
+    /// The string was not valid UTF-8
+    InvalidUtf8,
+}
+
+impl From<ParserError> for ApduError {
+    fn from(_: ParserError) -> Self {
+        ApduError::DataInvalid
+    }
+}
+
+/// Result of a parser, containing the remaining input and the parsed value
+pub type ParserResult<'b, T> = Result<(&'b [u8], T), ParserError>;
+
+#[inline(never)]
+/// Split the first `n` bytes off `input`
+///
+/// Returns the remaining input and the `n` bytes taken
+pub fn take(input: &[u8], n: usize) -> ParserResult<'_, &[u8]> {
+    if input.len() < n {
+        return Err(ParserError::UnexpectedEof {
+            needed: n - input.len(),
+        });
+    }
+
+    let (taken, rem) = input.split_at(n);
+    Ok((rem, taken))
+}
+
+/// Read exactly `N` bytes from `input` into an array
+#[inline]
+pub fn take_array<const N: usize>(input: &[u8]) -> ParserResult<'_, [u8; N]> {
+    let (rem, bytes) = take(input, N)?;
+
+    let mut array = [0; N];
+    array.copy_from_slice(bytes);
+
+    Ok((rem, array))
+}
+
+macro_rules! int_parsers {
+    ($($ty:ty => $be:ident, $le:ident;)*) => {
+        $(
+            #[inline]
+            #[doc = concat!("Read a big endian `", stringify!($ty), "`")]
+            pub fn $be(input: &[u8]) -> ParserResult<'_, $ty> {
+                take_array(input).map(|(rem, bytes)| (rem, <$ty>::from_be_bytes(bytes)))
+            }
+
+            #[inline]
+            #[doc = concat!("Read a little endian `", stringify!($ty), "`")]
+            pub fn $le(input: &[u8]) -> ParserResult<'_, $ty> {
+                take_array(input).map(|(rem, bytes)| (rem, <$ty>::from_le_bytes(bytes)))
+            }
+        )*
+    };
+}
+
+int_parsers! {
+    u8 => be_u8, le_u8;
+    u16 => be_u16, le_u16;
+    u32 => be_u32, le_u32;
+    u64 => be_u64, le_u64;
+    u128 => be_u128, le_u128;
+    i8 => be_i8, le_i8;
+    i16 => be_i16, le_i16;
+    i32 => be_i32, le_i32;
+    i64 => be_i64, le_i64;
+    i128 => be_i128, le_i128;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+#[repr(transparent)]
+/// Integer encoded in little endian, as opposed to the big endian
+/// used by the plain integer [`ToBytes`] implementations
+pub struct Le<T>(pub T);
+
+macro_rules! impl_le {
+    ($($ty:ty => $le:ident),*) => {
+        $(
+            impl<'b> FromBytes<'b> for Le<$ty> {
+                type Error = ParserError;
+
+                #[inline]
+                fn from_bytes_into(
+                    input: &'b [u8],
+                    out: &mut MaybeUninit<Self>,
+                ) -> Result<&'b [u8], Self::Error> {
+                    let (rem, n) = $le(input)?;
+                    out.write(Le(n));
+
+                    Ok(rem)
+                }
+            }
+
+            impl ToBytes for Le<$ty> {
+                #[inline]
+                fn serialized_len(&self) -> usize {
+                    core::mem::size_of::<$ty>()
+                }
+
+                #[inline]
+                fn to_bytes_into(&self, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall> {
+                    self.0.to_le_bytes().to_bytes_into(out)
+                }
+            }
+        )*
+    };
+}
+
+impl_le! {
+    u8 => le_u8, u16 => le_u16, u32 => le_u32, u64 => le_u64, u128 => le_u128,
+    i8 => le_i8, i16 => le_i16, i32 => le_i32, i64 => le_i64, i128 => le_i128
+}
+
+#[inline(never)]
+/// Read an unsigned LEB128 encoded integer of at most `BITS` bits
+///
+/// Fails if the value doesn't fit in `BITS` bits or if it was padded with
+/// redundant zero groups
+fn uleb128<const BITS: u32>(input: &[u8]) -> ParserResult<'_, u64> {
+    let mut value = 0u64;
+    let mut shift = 0u32;
+
+    for (i, &byte) in input.iter().enumerate() {
+        let group = (byte & 0x7f) as u64;
+
+        //the group would not fit or would lose bits when shifted
+        if shift >= BITS || (BITS - shift < 7 && group >> (BITS - shift) != 0) {
+            return Err(ParserError::VarintOverflow);
+        }
+        value |= group << shift;
+
+        if byte & 0x80 == 0 {
+            //a final 0 group after the first one adds no information
+            if i > 0 && byte == 0 {
+                return Err(ParserError::NonCanonicalVarint);
+            }
+
+            return Ok((&input[i + 1..], value));
+        }
+
+        shift += 7;
+    }
+
+    Err(ParserError::UnexpectedEof { needed: 1 })
+}
+
+/// Read an unsigned LEB128 encoded `u32`
+pub fn uleb128_u32(input: &[u8]) -> ParserResult<'_, u32> {
+    uleb128::<32>(input).map(|(rem, v)| (rem, v as u32))
+}
+
+/// Read an unsigned LEB128 encoded `u64`
+pub fn uleb128_u64(input: &[u8]) -> ParserResult<'_, u64> {
+    uleb128::<64>(input)
+}
+
+#[inline(never)]
+/// Read a Bitcoin CompactSize encoded integer
+///
+/// Values must be encoded with the smallest possible representation
+pub fn compact_size(input: &[u8]) -> ParserResult<'_, u64> {
+    let (rem, tag) = be_u8(input)?;
+
+    let (rem, value, min) = match tag {
+        0xfd => le_u16(rem).map(|(rem, v)| (rem, v as u64, 0xfd))?,
+        0xfe => le_u32(rem).map(|(rem, v)| (rem, v as u64, 0x1_0000))?,
+        0xff => le_u64(rem).map(|(rem, v)| (rem, v, 0x1_0000_0000))?,
+        n => return Ok((rem, n as u64)),
+    };
+
+    if value < min {
+        return Err(ParserError::NonCanonicalVarint);
+    }
+
+    Ok((rem, value))
+}
+
+#[inline(never)]
+/// Read a slice whose length is prefixed to it, as read by `prefix`
+///
+/// Returns the remaining input and the slice (without the prefix)
+pub fn length_prefixed<'b, N, F>(input: &'b [u8], prefix: F) -> ParserResult<'b, &'b [u8]>
+where
+    F: FnOnce(&'b [u8]) -> ParserResult<'b, N>,
+    N: TryInto<usize>,
+{
+    let (rem, len) = prefix(input)?;
+    let len = len.try_into().map_err(|_| ParserError::LengthOverflow)?;
+
+    take(rem, len)
+}
+
+#[inline(never)]
+/// Apply `parser` `count` times, failing early if `count` exceeds `max`
+///
+/// `parser` receives the index of the item being parsed and the remaining input,
+/// and should return the input left after the item.
+///
+/// Returns the input left after all the items
+pub fn repeat<'b, E, F>(
+    input: &'b [u8],
+    count: usize,
+    max: usize,
+    mut parser: F,
+) -> Result<&'b [u8], E>
+where
+    F: FnMut(usize, &'b [u8]) -> Result<&'b [u8], E>,
+    E: From<ParserError>,
+{
+    if count > max {
+        return Err(ParserError::TooManyItems { count, max }.into());
+    }
+
+    let mut rem = input;
+    for i in 0..count {
+        rem = parser(i, rem)?;
+    }
+
+    Ok(rem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers() {
+        let input = [0x01, 0x02, 0x03, 0x04, 0xff];
+
+        assert_eq!(be_u32(&input), Ok((&input[4..], 0x0102_0304)));
+        assert_eq!(le_u16(&input), Ok((&input[2..], 0x0201)));
+        assert_eq!(be_i8(&input[4..]), Ok((&[][..], -1)));
+        assert_eq!(
+            be_u64(&input),
+            Err(ParserError::UnexpectedEof { needed: 3 })
+        );
+    }
+
+    #[test]
+    fn leb128() {
+        assert_eq!(uleb128_u64(&[0x00]), Ok((&[][..], 0)));
+        assert_eq!(
+            uleb128_u64(&[0xe5, 0x8e, 0x26, 0xaa]),
+            Ok((&[0xaa][..], 624485))
+        );
+        assert_eq!(
+            uleb128_u32(&[0xff, 0xff, 0xff, 0xff, 0x0f]),
+            Ok((&[][..], u32::MAX))
+        );
+        assert_eq!(
+            uleb128_u32(&[0xff, 0xff, 0xff, 0xff, 0x1f]),
+            Err(ParserError::VarintOverflow)
+        );
+        assert_eq!(
+            uleb128_u64(&[0x80, 0x00]),
+            Err(ParserError::NonCanonicalVarint)
+        );
+        assert_eq!(
+            uleb128_u64(&[0x80, 0x80]),
+            Err(ParserError::UnexpectedEof { needed: 1 })
+        );
+
+        let mut max = [0xff; 10];
+        max[9] = 0x01;
+        assert_eq!(uleb128_u64(&max), Ok((&[][..], u64::MAX)));
+        max[9] = 0x02;
+        assert_eq!(uleb128_u64(&max), Err(ParserError::VarintOverflow));
+    }
+
+    #[test]
+    fn compact() {
+        assert_eq!(compact_size(&[0xfc]), Ok((&[][..], 0xfc)));
+        assert_eq!(compact_size(&[0xfd, 0xfd, 0x00]), Ok((&[][..], 0xfd)));
+        assert_eq!(
+            compact_size(&[0xfe, 0x00, 0x00, 0x01, 0x00]),
+            Ok((&[][..], 0x1_0000))
+        );
+        assert_eq!(
+            compact_size(&[0xfd, 0x10, 0x00]),
+            Err(ParserError::NonCanonicalVarint)
+        );
+    }
+
+    #[test]
+    fn prefixed_and_repeated() {
+        let input = [3, 0xaa, 0xbb, 0xcc, 0xdd];
+
+        let (rem, data) = length_prefixed(&input, be_u8).unwrap();
+        assert_eq!(data, &[0xaa, 0xbb, 0xcc]);
+        assert_eq!(rem, &[0xdd]);
+
+        let mut sum = 0u32;
+        let rem = repeat::<ParserError, _>(&input[1..], 2, 2, |_, input| {
+            let (rem, n) = be_u8(input)?;
+            sum += n as u32;
+            Ok(rem)
+        })
+        .unwrap();
+        assert_eq!(sum, 0xaa + 0xbb);
+        assert_eq!(rem, &[0xcc, 0xdd]);
+
+        assert_eq!(
+            repeat::<ParserError, _>(&input, 3, 2, |_, input| Ok(input)),
+            Err(ParserError::TooManyItems { count: 3, max: 2 })
+        );
+    }
+}
@@ -0,0 +1,527 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Zero-copy reader for the protobuf wire format
+//!
+//! Messages can be described with [`protobuf_message!`](crate::protobuf_message),
+//! which generates the struct and its [`FromBytes`] implementation.
+
+use core::mem::MaybeUninit;
+
+use super::{le_u32, le_u64, take, uleb128_u64, FromBytes, ParserError};
+use crate::ApduError;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Errors that can occur when decoding protobuf messages
+pub enum ProtobufError {
+    /// Error reading the underlying data
+    Parser(ParserError),
+    /// The field key had a wire type that is unknown or unsupported (groups)
+    UnsupportedWireType(u8),
+    /// The field number was 0 or exceeded the maximum allowed
+    InvalidFieldNumber(u64),
+    /// The field was found more than once
+    DuplicateField(u32),
+    /// The field was found after a field with a higher number
+    OutOfOrderField(u32),
+    /// The field had a different wire type than the one expected for the schema
+    UnexpectedWireType { field: u32, found: WireType },
+    /// The decoded value doesn't fit in the field type
+    ValueOverflow(u32),
+    /// The field was a `bool` but its value was neither 0 or 1
+    InvalidBool(u32),
+    /// The field was a `string` but its contents were not valid UTF-8
+    InvalidUtf8(u32),
+    /// The field was an embedded message but not all of its bytes were used
+    TrailingData(u32),
+}
+
+impl From<ParserError> for ProtobufError {
+    fn from(e: ParserError) -> Self {
+        Self::Parser(e)
+    }
+}
+
+impl From<ProtobufError> for ApduError {
+    fn from(_: ProtobufError) -> Self {
+        ApduError::DataInvalid
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// The supported protobuf wire types
+pub enum WireType {
+    Varint = 0,
+    Fixed64 = 1,
+    LengthDelimited = 2,
+    Fixed32 = 5,
+}
+
+impl TryFrom<u8> for WireType {
+    type Error = ProtobufError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Varint),
+            1 => Ok(Self::Fixed64),
+            2 => Ok(Self::LengthDelimited),
+            5 => Ok(Self::Fixed32),
+            //3 and 4 are the deprecated group delimiters
+            wt => Err(ProtobufError::UnsupportedWireType(wt)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// The raw value of a field, as encoded on the wire
+pub enum Value<'b> {
+    Varint(u64),
+    Fixed64(u64),
+    LengthDelimited(&'b [u8]),
+    Fixed32(u32),
+}
+
+impl Value<'_> {
+    /// Retrieve the wire type of this value
+    pub fn wire_type(&self) -> WireType {
+        match self {
+            Self::Varint(_) => WireType::Varint,
+            Self::Fixed64(_) => WireType::Fixed64,
+            Self::LengthDelimited(_) => WireType::LengthDelimited,
+            Self::Fixed32(_) => WireType::Fixed32,
+        }
+    }
+}
+
+/// The largest field number allowed by protobuf
+pub const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// A single field of a message
+pub struct Field<'b> {
+    pub number: u32,
+    pub value: Value<'b>,
+}
+
+impl<'b> Field<'b> {
+    #[inline(never)]
+    /// Read a single field from `input`, returning the remaining bytes
+    pub fn read(input: &'b [u8]) -> Result<(&'b [u8], Self), ProtobufError> {
+        let (rem, key) = uleb128_u64(input)?;
+
+        let number = key >> 3;
+        if number == 0 || number > MAX_FIELD_NUMBER as u64 {
+            return Err(ProtobufError::InvalidFieldNumber(number));
+        }
+        let number = number as u32;
+
+        let (rem, value) = match WireType::try_from((key & 0x7) as u8)? {
+            WireType::Varint => uleb128_u64(rem).map(|(rem, v)| (rem, Value::Varint(v)))?,
+            WireType::Fixed64 => le_u64(rem).map(|(rem, v)| (rem, Value::Fixed64(v)))?,
+            WireType::Fixed32 => le_u32(rem).map(|(rem, v)| (rem, Value::Fixed32(v)))?,
+            WireType::LengthDelimited => {
+                let (rem, len) = uleb128_u64(rem)?;
+                let len = usize::try_from(len).map_err(|_| ParserError::LengthOverflow)?;
+
+                take(rem, len).map(|(rem, v)| (rem, Value::LengthDelimited(v)))?
+            }
+        };
+
+        Ok((rem, Self { number, value }))
+    }
+
+    fn unexpected(&self) -> ProtobufError {
+        ProtobufError::UnexpectedWireType {
+            field: self.number,
+            found: self.value.wire_type(),
+        }
+    }
+
+    fn varint(&self) -> Result<u64, ProtobufError> {
+        match self.value {
+            Value::Varint(v) => Ok(v),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Decode the field as `uint64`
+    pub fn uint64(&self) -> Result<u64, ProtobufError> {
+        self.varint()
+    }
+
+    /// Decode the field as `uint32`
+    pub fn uint32(&self) -> Result<u32, ProtobufError> {
+        u32::try_from(self.varint()?).map_err(|_| ProtobufError::ValueOverflow(self.number))
+    }
+
+    /// Decode the field as `int64`
+    pub fn int64(&self) -> Result<i64, ProtobufError> {
+        self.varint().map(|v| v as i64)
+    }
+
+    /// Decode the field as `int32`
+    ///
+    /// Negative values are expected to be sign extended to 64 bits, as per spec
+    pub fn int32(&self) -> Result<i32, ProtobufError> {
+        i32::try_from(self.int64()?).map_err(|_| ProtobufError::ValueOverflow(self.number))
+    }
+
+    /// Decode the field as `sint64` (zigzag encoded)
+    pub fn sint64(&self) -> Result<i64, ProtobufError> {
+        self.varint().map(|v| (v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+
+    /// Decode the field as `sint32` (zigzag encoded)
+    pub fn sint32(&self) -> Result<i32, ProtobufError> {
+        i32::try_from(self.sint64()?).map_err(|_| ProtobufError::ValueOverflow(self.number))
+    }
+
+    /// Decode the field as `bool`
+    pub fn bool(&self) -> Result<bool, ProtobufError> {
+        match self.varint()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ProtobufError::InvalidBool(self.number)),
+        }
+    }
+
+    /// Decode the field as `fixed64`
+    pub fn fixed64(&self) -> Result<u64, ProtobufError> {
+        match self.value {
+            Value::Fixed64(v) => Ok(v),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Decode the field as `sfixed64`
+    pub fn sfixed64(&self) -> Result<i64, ProtobufError> {
+        self.fixed64().map(|v| v as i64)
+    }
+
+    /// Decode the field as `fixed32`
+    pub fn fixed32(&self) -> Result<u32, ProtobufError> {
+        match self.value {
+            Value::Fixed32(v) => Ok(v),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Decode the field as `sfixed32`
+    pub fn sfixed32(&self) -> Result<i32, ProtobufError> {
+        self.fixed32().map(|v| v as i32)
+    }
+
+    /// Decode the field as `bytes`
+    pub fn bytes(&self) -> Result<&'b [u8], ProtobufError> {
+        match self.value {
+            Value::LengthDelimited(v) => Ok(v),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Decode the field as `string`
+    pub fn string(&self) -> Result<&'b str, ProtobufError> {
+        core::str::from_utf8(self.bytes()?).map_err(|_| ProtobufError::InvalidUtf8(self.number))
+    }
+
+    /// Decode the field as an embedded message
+    ///
+    /// The message must span the entire field
+    pub fn message<T>(&self) -> Result<T, ProtobufError>
+    where
+        T: FromBytes<'b>,
+        T::Error: Into<ProtobufError>,
+    {
+        let mut out = MaybeUninit::uninit();
+        let rem = T::from_bytes_into(self.bytes()?, &mut out).map_err(Into::into)?;
+        if !rem.is_empty() {
+            return Err(ProtobufError::TrailingData(self.number));
+        }
+
+        //SAFE: `from_bytes_into` initialized the object
+        Ok(unsafe { out.assume_init() })
+    }
+}
+
+#[derive(Clone, Copy)]
+/// Iterator over the fields of an encoded message
+///
+/// Iteration stops after the first error
+pub struct Reader<'b> {
+    data: &'b [u8],
+}
+
+impl<'b> Reader<'b> {
+    /// Create a new reader over the given encoded message
+    pub fn new(data: &'b [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'b> Iterator for Reader<'b> {
+    type Item = Result<Field<'b>, ProtobufError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        match Field::read(self.data) {
+            Ok((rem, field)) => {
+                self.data = rem;
+                Some(Ok(field))
+            }
+            Err(e) => {
+                self.data = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Keeps track of the field numbers found while decoding a message,
+/// to validate they appear in strictly increasing order
+#[doc(hidden)]
+pub struct FieldOrder(u32);
+
+impl FieldOrder {
+    pub fn new() -> Self {
+        Self(0)
+    }
+
+    /// Record field `number` as found, checking it's after the previous one
+    pub fn check(&mut self, number: u32) -> Result<(), ProtobufError> {
+        if number == self.0 {
+            Err(ProtobufError::DuplicateField(number))
+        } else if number < self.0 {
+            Err(ProtobufError::OutOfOrderField(number))
+        } else {
+            self.0 = number;
+            Ok(())
+        }
+    }
+}
+
+impl Default for FieldOrder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[macro_export]
+/// Declare a protobuf message and generate its [`FromBytes`](crate::FromBytes) implementation
+///
+/// Each field is declared as `number => name: kind`, where `kind` is one of the scalar
+/// protobuf types (`uint64`, `int32`, `sint64`, `bool`, `fixed32`, `bytes`, `string`, ...)
+/// or `message(Type)` for embedded messages, which are decoded with `Type`'s [`FromBytes`](crate::FromBytes).
+///
+/// Fields missing from the input are left with their default value (`None` for messages),
+/// unknown fields are skipped, while known fields appearing out of order or more than once
+/// are rejected. Repeated fields are not supported.
+///
+/// # Example
+/// ```rust
+/// bolos::protobuf_message! {
+///     pub struct SignDoc<'b> {
+///         1 => body_bytes: bytes,
+///         2 => auth_info_bytes: bytes,
+///         3 => chain_id: string,
+///         4 => account_number: uint64,
+///     }
+/// }
+/// ```
+macro_rules! protobuf_message {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident<$lt:lifetime> {
+            $( $num:literal => $field:ident : $kind:ident $(($inner:ty))? ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name<$lt> {
+            $( pub $field: $crate::__protobuf_field_ty!($lt, $kind $(($inner))?), )*
+        }
+
+        impl<$lt> $crate::FromBytes<$lt> for $name<$lt> {
+            type Error = $crate::parser::protobuf::ProtobufError;
+
+            #[inline(never)]
+            fn from_bytes_into(
+                input: &$lt [u8],
+                out: &mut ::core::mem::MaybeUninit<Self>,
+            ) -> ::core::result::Result<&$lt [u8], Self::Error> {
+                $( let mut $field = ::core::default::Default::default(); )*
+                let mut order = $crate::parser::protobuf::FieldOrder::new();
+
+                for field in $crate::parser::protobuf::Reader::new(input) {
+                    let field = field?;
+                    match field.number {
+                        $(
+                            $num => {
+                                order.check(field.number)?;
+                                $field = $crate::__protobuf_field_decode!(field, $kind $(($inner))?)?;
+                            }
+                        )*
+                        _ => {}
+                    }
+                }
+
+                out.write(Self { $( $field, )* });
+
+                //a message always spans the entire input
+                Ok(&input[input.len()..])
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __protobuf_field_ty {
+    ($lt:lifetime, message($inner:ty)) => { ::core::option::Option<$inner> };
+    ($lt:lifetime, bytes) => { &$lt [u8] };
+    ($lt:lifetime, string) => { &$lt str };
+    ($lt:lifetime, bool) => { bool };
+    ($lt:lifetime, uint64) => { u64 };
+    ($lt:lifetime, fixed64) => { u64 };
+    ($lt:lifetime, int64) => { i64 };
+    ($lt:lifetime, sint64) => { i64 };
+    ($lt:lifetime, sfixed64) => { i64 };
+    ($lt:lifetime, uint32) => { u32 };
+    ($lt:lifetime, fixed32) => { u32 };
+    ($lt:lifetime, int32) => { i32 };
+    ($lt:lifetime, sint32) => { i32 };
+    ($lt:lifetime, sfixed32) => { i32 };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __protobuf_field_decode {
+    ($field:ident, message($inner:ty)) => {
+        $field.message::<$inner>().map(::core::option::Option::Some)
+    };
+    ($field:ident, $kind:ident) => {
+        $field.$kind()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::protobuf_message! {
+        struct Coin<'b> {
+            1 => denom: string,
+            2 => amount: string,
+        }
+    }
+
+    crate::protobuf_message! {
+        struct Msg<'b> {
+            1 => id: uint32,
+            2 => delta: sint64,
+            4 => fee: message(Coin<'b>),
+            5 => memo: bytes,
+        }
+    }
+
+    const COIN: &[u8] = &[
+        0x0a, 0x05, b'u', b'a', b't', b'o', b'm', //denom
+        0x12, 0x02, b'4', b'2', //amount
+    ];
+
+    fn msg() -> std::vec::Vec<u8> {
+        let mut v = std::vec![0x08, 0x96, 0x01]; //id = 150
+        v.extend_from_slice(&[0x10, 0x03]); //delta = -2
+        v.extend_from_slice(&[0x1d, 1, 2, 3, 4]); //unknown fixed32 field 3
+        v.extend_from_slice(&[0x22, COIN.len() as u8]);
+        v.extend_from_slice(COIN);
+        v
+    }
+
+    #[test]
+    fn reader() {
+        let input = msg();
+        let fields: std::vec::Vec<_> = Reader::new(&input).collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0].value, Value::Varint(150));
+        assert_eq!(fields[2].number, 3);
+        assert_eq!(fields[2].value, Value::Fixed32(0x0403_0201));
+        assert_eq!(fields[3].value, Value::LengthDelimited(COIN));
+    }
+
+    #[test]
+    fn message() {
+        let input = msg();
+        let (rem, msg) = Msg::from_bytes(&input).unwrap();
+
+        assert!(rem.is_empty());
+        assert_eq!(msg.id, 150);
+        assert_eq!(msg.delta, -2);
+        assert_eq!(msg.memo, &[]);
+
+        let fee = msg.fee.unwrap();
+        assert_eq!(fee.denom, "uatom");
+        assert_eq!(fee.amount, "42");
+    }
+
+    #[test]
+    fn invalid_messages() {
+        //field 2 before field 1
+        let input = [0x10, 0x01, 0x08, 0x01];
+        assert_eq!(
+            Msg::from_bytes(&input).err(),
+            Some(ProtobufError::OutOfOrderField(1))
+        );
+
+        let input = [0x08, 0x01, 0x08, 0x01];
+        assert_eq!(
+            Msg::from_bytes(&input).err(),
+            Some(ProtobufError::DuplicateField(1))
+        );
+
+        //id encoded as length delimited
+        let input = [0x0a, 0x00];
+        assert_eq!(
+            Msg::from_bytes(&input).err(),
+            Some(ProtobufError::UnexpectedWireType {
+                field: 1,
+                found: WireType::LengthDelimited
+            })
+        );
+
+        //group start
+        let input = [0x0b];
+        assert_eq!(
+            Msg::from_bytes(&input).err(),
+            Some(ProtobufError::UnsupportedWireType(3))
+        );
+
+        //truncated memo
+        let input = [0x2a, 0x05, 0x00];
+        assert_eq!(
+            Msg::from_bytes(&input).err(),
+            Some(ProtobufError::Parser(ParserError::UnexpectedEof {
+                needed: 4
+            }))
+        );
+    }
+}
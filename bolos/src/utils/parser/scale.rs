@@ -0,0 +1,321 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Primitives to decode SCALE encoded data, as used by Substrate based chains
+//!
+//! Fixed width integers are read with [`Le`](super::Le), booleans with [`Bool`]
+//! and options with [`Tagged`],
+//! while enums can be declared with [`scale_enum!`](crate::scale_enum).
+
+use core::mem::MaybeUninit;
+
+use super::{
+    be_u8, le_u16, le_u32, prefixed, prefixed::LengthPrefix, take, FromBytes, ParserError,
+    ParserResult,
+};
+
+pub use super::prefixed::{Bool, Tagged};
+
+#[inline(never)]
+/// Read a compact encoded integer
+///
+/// Values must be encoded in the shortest possible mode and with no redundant bytes
+pub fn compact(input: &[u8]) -> ParserResult<'_, u128> {
+    let (_, first) = be_u8(input)?;
+
+    let (rem, value, min) = match first & 0b11 {
+        0b00 => return be_u8(input).map(|(rem, v)| (rem, (v >> 2) as u128)),
+        0b01 => le_u16(input).map(|(rem, v)| (rem, (v >> 2) as u128, 1 << 6))?,
+        0b10 => le_u32(input).map(|(rem, v)| (rem, (v >> 2) as u128, 1 << 14))?,
+        _ => {
+            let len = (first >> 2) as usize + 4;
+            if len > 16 {
+                return Err(ParserError::VarintOverflow);
+            }
+            let (rem, bytes) = take(&input[1..], len)?;
+
+            //the most significant byte would be redundant if zero
+            if bytes[len - 1] == 0 {
+                return Err(ParserError::NonCanonicalVarint);
+            }
+
+            let value = bytes
+                .iter()
+                .rev()
+                .fold(0u128, |value, &byte| (value << 8) | byte as u128);
+            (rem, value, 1 << 30)
+        }
+    };
+
+    if value < min {
+        return Err(ParserError::NonCanonicalVarint);
+    }
+
+    Ok((rem, value))
+}
+
+/// Read a compact encoded length
+pub fn compact_len(input: &[u8]) -> ParserResult<'_, usize> {
+    let (rem, len) = compact(input)?;
+    let len = usize::try_from(len).map_err(|_| ParserError::LengthOverflow)?;
+
+    Ok((rem, len))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+#[repr(transparent)]
+/// Compact encoded unsigned integer
+///
+/// Parsing fails if the value doesn't fit in `T`
+pub struct Compact<T>(pub T);
+
+macro_rules! impl_compact {
+    ($($ty:ty),*) => {
+        $(
+            impl<'b> FromBytes<'b> for Compact<$ty> {
+                type Error = ParserError;
+
+                #[inline]
+                fn from_bytes_into(
+                    input: &'b [u8],
+                    out: &mut MaybeUninit<Self>,
+                ) -> Result<&'b [u8], Self::Error> {
+                    let (rem, value) = compact(input)?;
+                    let value = <$ty>::try_from(value).map_err(|_| ParserError::VarintOverflow)?;
+                    out.write(Compact(value));
+
+                    Ok(rem)
+                }
+            }
+        )*
+    };
+}
+
+impl_compact!(u8, u16, u32, u64, u128);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// [`LengthPrefix`] of SCALE vectors, a compact encoded length
+pub struct CompactLen;
+
+impl LengthPrefix for CompactLen {
+    fn length(input: &[u8]) -> ParserResult<'_, usize> {
+        compact_len(input)
+    }
+}
+
+/// Byte vector, prefixed with its compact encoded length
+pub type Bytes<'b> = prefixed::Bytes<'b, CompactLen>;
+
+/// Sequence of `T`, prefixed with its compact encoded length,
+/// the SCALE equivalent of `Vec<T>`
+pub type Seq<'b, T> = prefixed::Seq<'b, T, CompactLen>;
+
+#[macro_export]
+/// Declare an enum whose variant is selected by a leading `u8` index,
+/// as SCALE encodes enums, and implement [`FromBytes`](crate::FromBytes) for it
+///
+/// Variants can have a single field, parsed right after the index,
+/// whose error must be convertible into [`ParserError`](crate::parser::ParserError).
+/// Unknown indices fail with [`ParserError::InvalidTag`](crate::parser::ParserError::InvalidTag).
+///
+/// # Example
+/// ```rust
+/// # use bolos::{scale_enum, parser::{Le, scale::{Bytes, Compact}}};
+/// scale_enum! {
+///     pub enum Call<'b> {
+///         0 => Remark(Bytes<'b>),
+///         3 => Transfer(Compact<u128>),
+///         4 => SetCode(Le<u32>),
+///         7 => Noop,
+///     }
+/// }
+/// ```
+macro_rules! scale_enum {
+    (
+        $(#[$attr:meta])*
+        $vis:vis enum $name:ident $(<$lt:lifetime>)? {
+            $( $idx:literal => $variant:ident $(($inner:ty))? ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis enum $name $(<$lt>)? {
+            $( $variant $(($inner))?, )*
+        }
+
+        $crate::__index_enum_from_bytes!($name $(<$lt>)? {
+            $( $idx => $variant $(($inner))? ),*
+        });
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __index_enum_from_bytes {
+    ($name:ident $(<$lt:lifetime>)? { $($body:tt)* }) => {
+        $crate::__from_bytes_impl!($name $(<$lt>)?, |input, out| {
+            $crate::__index_enum_from_bytes!(@body input, out, $name { $($body)* })
+        });
+    };
+    (@body $input:ident, $out:ident, $name:ident {
+        $( $idx:literal => $variant:ident $(($inner:ty))? ),*
+    }) => {{
+        let (rem, index) = $crate::parser::be_u8($input)?;
+        match index {
+            $(
+                $idx => $crate::__index_enum_from_bytes!(
+                    @variant rem, $out, $name::$variant $(($inner))?
+                ),
+            )*
+            _ => Err($crate::parser::ParserError::InvalidTag(index)),
+        }
+    }};
+    (@variant $rem:ident, $out:ident, $name:ident::$variant:ident ($inner:ty)) => {{
+        let mut inner = ::core::mem::MaybeUninit::<$inner>::uninit();
+        let rem = <$inner as $crate::FromBytes>::from_bytes_into($rem, &mut inner)?;
+        $out.write($name::$variant(unsafe { inner.assume_init() }));
+
+        Ok(rem)
+    }};
+    (@variant $rem:ident, $out:ident, $name:ident::$variant:ident) => {{
+        $out.write($name::$variant);
+
+        Ok($rem)
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+/// Implement [`FromBytes`](crate::FromBytes) with [`ParserError`](crate::parser::ParserError)
+/// as error for a type with either one or no lifetime parameters
+macro_rules! __from_bytes_impl {
+    ($name:ident <$lt:lifetime>, |$input:ident, $out:ident| $body:block) => {
+        impl<$lt> $crate::FromBytes<$lt> for $name<$lt> {
+            type Error = $crate::parser::ParserError;
+
+            #[inline(never)]
+            fn from_bytes_into(
+                $input: &$lt [u8],
+                $out: &mut ::core::mem::MaybeUninit<Self>,
+            ) -> ::core::result::Result<&$lt [u8], Self::Error> $body
+        }
+    };
+    ($name:ident, |$input:ident, $out:ident| $body:block) => {
+        impl<'b> $crate::FromBytes<'b> for $name {
+            type Error = $crate::parser::ParserError;
+
+            #[inline(never)]
+            fn from_bytes_into(
+                $input: &'b [u8],
+                $out: &mut ::core::mem::MaybeUninit<Self>,
+            ) -> ::core::result::Result<&'b [u8], Self::Error> $body
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Le;
+
+    #[test]
+    fn compact_ints() {
+        assert_eq!(compact(&[0x00]), Ok((&[][..], 0)));
+        assert_eq!(compact(&[0xa8]), Ok((&[][..], 42)));
+        assert_eq!(compact(&[0x15, 0x01]), Ok((&[][..], 69)));
+        assert_eq!(compact(&[0xfe, 0xff, 0x03, 0x00]), Ok((&[][..], 65535)));
+        assert_eq!(
+            compact(&[0x03, 0x00, 0x00, 0x00, 0x40]),
+            Ok((&[][..], 1 << 30))
+        );
+        assert_eq!(
+            compact(&[0x0b, 0x00, 0x40, 0x7a, 0x10, 0xf3, 0x5a]),
+            Ok((&[][..], 100_000_000_000_000))
+        );
+        assert_eq!(
+            Compact::<u64>::from_bytes(&[0x13, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Ok((&[][..], Compact(u64::MAX)))
+        );
+
+        assert_eq!(compact(&[0x01, 0x00]), Err(ParserError::NonCanonicalVarint));
+        assert_eq!(
+            compact(&[0x07, 0xff, 0xff, 0xff, 0x3f, 0x00]),
+            Err(ParserError::NonCanonicalVarint)
+        );
+        assert_eq!(
+            Compact::<u8>::from_bytes(&[0x01, 0x04]),
+            Err(ParserError::VarintOverflow)
+        );
+        //17 bytes don't fit in a u128, whatever follows
+        assert_eq!(compact(&[0x37, 0xff]), Err(ParserError::VarintOverflow));
+    }
+
+    #[test]
+    fn sequences() {
+        let input = [0x0c, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0xff];
+
+        let (rem, seq) = Seq::<Le<u16>>::from_bytes(&input).unwrap();
+        assert_eq!(rem, &[0xff]);
+        assert_eq!(seq.len(), 3);
+        assert!(seq.list().iter().map(|n| n.0).eq([1, 2, 3]));
+
+        assert_eq!(
+            Seq::<Le<u16>>::from_bytes(&input[..6]),
+            Err(ParserError::UnexpectedEof { needed: 1 })
+        );
+
+        assert_eq!(
+            Bytes::from_bytes(&[0x08, 0xaa, 0xbb]),
+            Ok((&[][..], Bytes::new(&[0xaa, 0xbb])))
+        );
+
+        assert_eq!(
+            Tagged::<Compact<u32>>::from_bytes(&[0x01, 0xa8]),
+            Ok((&[][..], Tagged(Some(Compact(42)))))
+        );
+        assert_eq!(
+            Tagged::<Compact<u32>>::from_bytes(&[0x00]),
+            Ok((&[][..], Tagged(None)))
+        );
+        assert_eq!(
+            Tagged::<Compact<u32>>::from_bytes(&[0x02]),
+            Err(ParserError::InvalidTag(2))
+        );
+    }
+
+    scale_enum! {
+        #[derive(Debug, PartialEq)]
+        enum Call<'b> {
+            0 => Remark(Bytes<'b>),
+            3 => Transfer(Compact<u128>),
+            7 => Noop,
+        }
+    }
+
+    #[test]
+    fn enums() {
+        assert_eq!(
+            Call::from_bytes(&[0x00, 0x04, 0xaa]),
+            Ok((&[][..], Call::Remark(Bytes::new(&[0xaa]))))
+        );
+        assert_eq!(
+            Call::from_bytes(&[0x03, 0xa8]),
+            Ok((&[][..], Call::Transfer(Compact(42))))
+        );
+        assert_eq!(Call::from_bytes(&[0x07]), Ok((&[][..], Call::Noop)));
+        assert_eq!(Call::from_bytes(&[0x01]), Err(ParserError::InvalidTag(1)));
+    }
+}
@@ -0,0 +1,238 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+use crate::{
+    crypto::{bip32::BIP32Path, ecfp256, stark},
+    OutputBufferTooSmall,
+};
+
+///This trait is the counterpart of [`FromBytes`](super::FromBytes),
+///giving objects a way to define their own serialization
+///into a caller provided buffer.
+///
+///Integers are written in big endian, slices and arrays are written
+///as the concatenation of their items, without any length prefix.
+///
+///Can be derived for structs with `#[derive(bolos::ToBytes)]`,
+///which will write each field in declaration order.
+pub trait ToBytes {
+    /// The number of bytes [`to_bytes_into`](Self::to_bytes_into) will write
+    fn serialized_len(&self) -> usize;
+
+    ///Main serialization method
+    ///`out` the memory where the serialized form of this object should be written
+    ///
+    /// returns the number of bytes written on success
+    ///
+    /// It's a good idea to always put `#[inline(never)]` on top of this
+    /// function's implementation
+    fn to_bytes_into(&self, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall>;
+
+    /// this method is avaliable for testing only, as the preferable
+    /// option is to write directly in the output buffer
+    #[cfg(test)]
+    fn to_bytes(&self) -> std::vec::Vec<u8> {
+        let mut out = std::vec![0; self.serialized_len()];
+        let written = match self.to_bytes_into(&mut out) {
+            Ok(written) => written,
+            Err(_) => panic!("serialized_len was too small"),
+        };
+        out.truncate(written);
+        out
+    }
+}
+
+macro_rules! impl_to_bytes_int {
+    ($($ty:ty),*) => {
+        $(
+            impl ToBytes for $ty {
+                #[inline]
+                fn serialized_len(&self) -> usize {
+                    core::mem::size_of::<$ty>()
+                }
+
+                #[inline]
+                fn to_bytes_into(&self, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall> {
+                    let bytes = self.to_be_bytes();
+                    out.get_mut(..bytes.len())
+                        .ok_or(OutputBufferTooSmall)?
+                        .copy_from_slice(&bytes);
+
+                    Ok(bytes.len())
+                }
+            }
+        )*
+    };
+}
+
+impl_to_bytes_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl ToBytes for bool {
+    #[inline]
+    fn serialized_len(&self) -> usize {
+        1
+    }
+
+    #[inline]
+    fn to_bytes_into(&self, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall> {
+        (*self as u8).to_bytes_into(out)
+    }
+}
+
+impl<T: ToBytes> ToBytes for [T] {
+    fn serialized_len(&self) -> usize {
+        self.iter().map(ToBytes::serialized_len).sum()
+    }
+
+    #[inline(never)]
+    fn to_bytes_into(&self, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall> {
+        let mut written = 0;
+        for item in self {
+            let out = out.get_mut(written..).ok_or(OutputBufferTooSmall)?;
+            written += item.to_bytes_into(out)?;
+        }
+
+        Ok(written)
+    }
+}
+
+impl<T: ToBytes, const N: usize> ToBytes for [T; N] {
+    #[inline]
+    fn serialized_len(&self) -> usize {
+        self.as_slice().serialized_len()
+    }
+
+    #[inline]
+    fn to_bytes_into(&self, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall> {
+        self.as_slice().to_bytes_into(out)
+    }
+}
+
+impl<T: ToBytes + ?Sized> ToBytes for &T {
+    #[inline]
+    fn serialized_len(&self) -> usize {
+        (**self).serialized_len()
+    }
+
+    #[inline]
+    fn to_bytes_into(&self, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall> {
+        (**self).to_bytes_into(out)
+    }
+}
+
+/// Written in the same format accepted by [`BIP32Path::read`]:
+/// the number of components followed by each component
+impl<const MAX_LEN: usize> ToBytes for BIP32Path<MAX_LEN> {
+    fn serialized_len(&self) -> usize {
+        1 + self.components().serialized_len()
+    }
+
+    #[inline(never)]
+    fn to_bytes_into(&self, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall> {
+        let components = self.components();
+        if out.len() < 1 + components.serialized_len() {
+            return Err(OutputBufferTooSmall);
+        }
+
+        out[0] = components.len() as u8;
+        components.to_bytes_into(&mut out[1..]).map(|w| 1 + w)
+    }
+}
+
+/// Written as the encoded point, as returned by `as_ref`
+impl ToBytes for ecfp256::PublicKey {
+    fn serialized_len(&self) -> usize {
+        self.len()
+    }
+
+    fn to_bytes_into(&self, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall> {
+        self.as_ref().to_bytes_into(out)
+    }
+}
+
+/// Written as the encoded point, as returned by `as_ref`
+impl ToBytes for stark::PublicKey {
+    fn serialized_len(&self) -> usize {
+        self.len()
+    }
+
+    fn to_bytes_into(&self, out: &mut [u8]) -> Result<usize, OutputBufferTooSmall> {
+        self.as_ref().to_bytes_into(out)
+    }
+}
+
+cfg_if! {
+    if #[cfg(__mock)] {
+        use super::FromBytes;
+        use core::mem::MaybeUninit;
+
+        /// Serialize `value` into `buf` and parse it back,
+        /// asserting the parsed object is the same as `value`
+        ///
+        /// Will also assert that [`ToBytes::serialized_len`] matches the written bytes
+        /// and that parsing consumes all of them.
+        ///
+        /// Only available when running with the mock.
+        pub fn assert_roundtrip<'b, T>(value: &T, buf: &'b mut [u8])
+        where
+            T: ToBytes + FromBytes<'b> + PartialEq,
+        {
+            let written = match value.to_bytes_into(buf) {
+                Ok(written) => written,
+                Err(_) => panic!("output buffer too small"),
+            };
+            assert_eq!(written, value.serialized_len(), "serialized_len mismatch");
+
+            let buf: &'b [u8] = buf;
+            let mut out = MaybeUninit::uninit();
+            let rem = match T::from_bytes_into(&buf[..written], &mut out) {
+                Ok(rem) => rem,
+                Err(_) => panic!("unable to parse serialized value"),
+            };
+            assert!(rem.is_empty(), "{} bytes left after parsing", rem.len());
+
+            let parsed = unsafe { out.assume_init() };
+            assert!(parsed == *value, "parsed value differs from the original");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitives() {
+        assert_eq!(0x0102u16.to_bytes(), [1, 2]);
+        assert_eq!((-2i32).to_bytes(), [0xff, 0xff, 0xff, 0xfe]);
+        assert_eq!(true.to_bytes(), [1]);
+        assert_eq!([0xaau8, 0xbb][..].to_bytes(), [0xaa, 0xbb]);
+        assert_eq!([0x0102u16; 2].to_bytes(), [1, 2, 1, 2]);
+
+        let mut out = [0; 3];
+        assert_eq!(0u32.to_bytes_into(&mut out), Err(OutputBufferTooSmall));
+    }
+
+    #[test]
+    fn bip32() {
+        let path = BIP32Path::<10>::new([0x8000_002c, 0x8000_0076, 0, 1]).unwrap();
+
+        let serialized = path.to_bytes();
+        assert_eq!(serialized.len(), path.serialized_len());
+        assert_eq!(serialized, path.serialize());
+
+        assert_roundtrip(&path, &mut [0; 1 + 4 * 10]);
+    }
+}
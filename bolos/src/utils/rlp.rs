@@ -0,0 +1,494 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! Recursive Length Prefix encoding, as used by Ethereum
+//!
+//! The decoder borrows from the input and only accepts canonical encodings,
+//! so that re-encoding a decoded item always yields the same bytes.
+//! The [`Encoder`] writes into a caller provided buffer.
+
+use core::mem::MaybeUninit;
+
+use crate::{
+    parser::{take, FromBytes, ParserError},
+    ApduError, OutputBufferTooSmall,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Errors that can occur when decoding RLP
+pub enum RlpError {
+    /// Error reading the underlying data
+    Parser(ParserError),
+    /// The item was not encoded in its shortest form
+    NonCanonical,
+    /// Expected a string but found a list
+    UnexpectedList,
+    /// Expected a list but found a string
+    UnexpectedString,
+    /// The integer has leading zeros or doesn't fit in the requested type
+    InvalidInteger,
+    /// There were more items in the list than expected
+    TrailingData,
+}
+
+impl From<ParserError> for RlpError {
+    fn from(e: ParserError) -> Self {
+        Self::Parser(e)
+    }
+}
+
+impl From<RlpError> for ApduError {
+    fn from(_: RlpError) -> Self {
+        ApduError::DataInvalid
+    }
+}
+
+const STRING_OFFSET: u8 = 0x80;
+const LIST_OFFSET: u8 = 0xc0;
+/// Payloads shorter than this have the length encoded in the header byte
+const SHORT_LEN: usize = 56;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// A single RLP item, borrowing its payload from the input
+pub enum Item<'b> {
+    String(&'b [u8]),
+    /// The payload of the list, its items can be read with [`Item::list`]
+    List(&'b [u8]),
+}
+
+impl<'b> Item<'b> {
+    /// Retrieve the payload of a string
+    pub fn bytes(&self) -> Result<&'b [u8], RlpError> {
+        match self {
+            Self::String(data) => Ok(data),
+            Self::List(_) => Err(RlpError::UnexpectedList),
+        }
+    }
+
+    /// Retrieve the items of a list
+    pub fn list(&self) -> Result<List<'b>, RlpError> {
+        match self {
+            Self::List(data) => Ok(List { data }),
+            Self::String(_) => Err(RlpError::UnexpectedString),
+        }
+    }
+
+    /// Retrieve a big endian unsigned integer of at most `N` bytes,
+    /// left padded with zeros
+    pub fn uint<const N: usize>(&self) -> Result<[u8; N], RlpError> {
+        let data = self.bytes()?;
+        if data.len() > N || data.first() == Some(&0) {
+            return Err(RlpError::InvalidInteger);
+        }
+
+        let mut out = [0; N];
+        out[N - data.len()..].copy_from_slice(data);
+        Ok(out)
+    }
+
+    /// Retrieve an unsigned integer that fits in a `u64`
+    pub fn u64(&self) -> Result<u64, RlpError> {
+        self.uint::<8>().map(u64::from_be_bytes)
+    }
+}
+
+#[inline(never)]
+/// Read the header of the first item in `input`,
+/// returning the remaining input, whether the item is a list and the payload length
+fn header(input: &[u8]) -> Result<(&[u8], bool, usize), RlpError> {
+    let (&prefix, rem) = input
+        .split_first()
+        .ok_or(ParserError::UnexpectedEof { needed: 1 })?;
+
+    let (is_list, short) = match prefix {
+        0..=0x7f => return Ok((input, false, 1)),
+        0x80..=0xbf => (false, prefix - STRING_OFFSET),
+        _ => (true, prefix - LIST_OFFSET),
+    };
+
+    //short form, length is in the prefix
+    if (short as usize) < SHORT_LEN {
+        return Ok((rem, is_list, short as usize));
+    }
+
+    //long form, the prefix has the length of the length
+    let len_of_len = (short as usize) - SHORT_LEN + 1;
+    let (rem, len_bytes) = take(rem, len_of_len)?;
+    if len_bytes[0] == 0 {
+        return Err(RlpError::NonCanonical);
+    }
+    if len_of_len > core::mem::size_of::<usize>() {
+        return Err(ParserError::LengthOverflow.into());
+    }
+
+    let len = len_bytes
+        .iter()
+        .fold(0usize, |len, &byte| (len << 8) | byte as usize);
+    if len < SHORT_LEN {
+        return Err(RlpError::NonCanonical);
+    }
+
+    Ok((rem, is_list, len))
+}
+
+#[inline(never)]
+/// Read the first item in `input`, returning the remaining input and the item
+pub fn item(input: &[u8]) -> Result<(&[u8], Item<'_>), RlpError> {
+    let (rem, is_list, len) = header(input)?;
+    let (rem, payload) = take(rem, len)?;
+
+    let item = if is_list {
+        Item::List(payload)
+    } else {
+        //a single byte below 0x80 is its own encoding
+        if len == 1 && payload[0] < STRING_OFFSET && input[0] == STRING_OFFSET + 1 {
+            return Err(RlpError::NonCanonical);
+        }
+
+        Item::String(payload)
+    };
+
+    Ok((rem, item))
+}
+
+impl<'b> FromBytes<'b> for Item<'b> {
+    type Error = RlpError;
+
+    fn from_bytes_into(
+        input: &'b [u8],
+        out: &mut MaybeUninit<Self>,
+    ) -> Result<&'b [u8], Self::Error> {
+        let (rem, it) = item(input)?;
+        out.write(it);
+
+        Ok(rem)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+/// Iterator over the items of an RLP list
+pub struct List<'b> {
+    data: &'b [u8],
+}
+
+impl<'b> List<'b> {
+    /// Returns the encoded items that are yet to be read
+    pub fn remaining(&self) -> &'b [u8] {
+        self.data
+    }
+
+    /// Read the next item, failing if there are none left
+    pub fn next_item(&mut self) -> Result<Item<'b>, RlpError> {
+        let (rem, item) = item(self.data)?;
+        self.data = rem;
+
+        Ok(item)
+    }
+
+    /// Parse the next encoded item as `T`
+    pub fn parse_next<T>(&mut self, out: &mut MaybeUninit<T>) -> Result<(), T::Error>
+    where
+        T: FromBytes<'b>,
+    {
+        self.data = T::from_bytes_into(self.data, out)?;
+
+        Ok(())
+    }
+
+    /// Ensure all the items have been read
+    pub fn finish(self) -> Result<(), RlpError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(RlpError::TrailingData)
+        }
+    }
+}
+
+impl<'b> Iterator for List<'b> {
+    type Item = Result<Item<'b>, RlpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let item = self.next_item();
+        if item.is_err() {
+            //stop at the first error
+            self.data = &[];
+        }
+
+        Some(item)
+    }
+}
+
+/// Number of bytes needed to write `len` in big endian without leading zeros
+const fn be_len(len: usize) -> usize {
+    (usize::BITS - len.leading_zeros()).div_ceil(8) as usize
+}
+
+/// Number of bytes the header of a payload of `len` bytes takes
+pub const fn header_len(len: usize) -> usize {
+    if len < SHORT_LEN {
+        1
+    } else {
+        1 + be_len(len)
+    }
+}
+
+/// Number of bytes `data` takes when encoded as a string
+pub fn bytes_len(data: &[u8]) -> usize {
+    match data {
+        [byte] if *byte < STRING_OFFSET => 1,
+        _ => header_len(data.len()) + data.len(),
+    }
+}
+
+/// Strip the leading zeros of a big endian unsigned integer
+fn trim_uint(be: &[u8]) -> &[u8] {
+    let zeros = be.iter().take_while(|&&b| b == 0).count();
+    &be[zeros..]
+}
+
+/// Writes RLP items into a caller provided buffer
+pub struct Encoder<'o> {
+    out: &'o mut [u8],
+    written: usize,
+}
+
+impl<'o> Encoder<'o> {
+    pub fn new(out: &'o mut [u8]) -> Self {
+        Self { out, written: 0 }
+    }
+
+    /// Number of bytes written so far
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    fn reserve(&mut self, len: usize) -> Result<&mut [u8], OutputBufferTooSmall> {
+        let out = self
+            .out
+            .get_mut(self.written..)
+            .and_then(|out| out.get_mut(..len))
+            .ok_or(OutputBufferTooSmall)?;
+        self.written += len;
+
+        Ok(out)
+    }
+
+    fn header(&mut self, offset: u8, len: usize) -> Result<(), OutputBufferTooSmall> {
+        let out = self.reserve(header_len(len))?;
+
+        if len < SHORT_LEN {
+            out[0] = offset + len as u8;
+        } else {
+            let len_of_len = out.len() - 1;
+            out[0] = offset + (SHORT_LEN - 1 + len_of_len) as u8;
+            out[1..]
+                .copy_from_slice(&len.to_be_bytes()[core::mem::size_of::<usize>() - len_of_len..]);
+        }
+
+        Ok(())
+    }
+
+    #[inline(never)]
+    /// Write `data` as a string
+    pub fn bytes(&mut self, data: &[u8]) -> Result<(), OutputBufferTooSmall> {
+        if !matches!(data, [byte] if *byte < STRING_OFFSET) {
+            self.header(STRING_OFFSET, data.len())?;
+        }
+
+        self.reserve(data.len())?.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Write a big endian unsigned integer, stripping any leading zeros
+    pub fn uint(&mut self, be: &[u8]) -> Result<(), OutputBufferTooSmall> {
+        self.bytes(trim_uint(be))
+    }
+
+    /// Write an unsigned integer
+    pub fn u64(&mut self, value: u64) -> Result<(), OutputBufferTooSmall> {
+        self.uint(&value.to_be_bytes())
+    }
+
+    /// Write already encoded items as they are
+    pub fn raw(&mut self, encoded: &[u8]) -> Result<(), OutputBufferTooSmall> {
+        self.reserve(encoded.len())?.copy_from_slice(encoded);
+        Ok(())
+    }
+
+    #[inline(never)]
+    /// Write a list, whose items are written by `f`
+    ///
+    /// The items are written first and then moved to make room for the header if needed
+    pub fn list<F>(&mut self, f: F) -> Result<(), OutputBufferTooSmall>
+    where
+        F: FnOnce(&mut Encoder<'_>) -> Result<(), OutputBufferTooSmall>,
+    {
+        let start = self.written;
+        //space for the short header
+        let out = self.out.get_mut(start + 1..).ok_or(OutputBufferTooSmall)?;
+
+        let mut inner = Encoder::new(out);
+        f(&mut inner)?;
+        let len = inner.written();
+
+        let header_len = header_len(len);
+        if start + header_len + len > self.out.len() {
+            return Err(OutputBufferTooSmall);
+        }
+        self.out
+            .copy_within(start + 1..start + 1 + len, start + header_len);
+
+        self.header(LIST_OFFSET, len)?;
+        self.written += len;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{Hasher, Keccak};
+
+    const LOREM: &[u8] = b"Lorem ipsum dolor sit amet, consectetur adipisicing elit";
+
+    #[test]
+    fn decode() {
+        assert_eq!(item(b"\x83dog"), Ok((&[][..], Item::String(b"dog"))));
+        assert_eq!(item(&[0x80]), Ok((&[][..], Item::String(&[]))));
+        assert_eq!(item(&[0x0f]), Ok((&[][..], Item::String(&[0x0f]))));
+        assert_eq!(item(&[0x82, 0x04, 0x00]).unwrap().1.u64(), Ok(1024));
+
+        let (_, list) = item(b"\xc8\x83cat\x83dog").unwrap();
+        let mut list = list.list().unwrap();
+        assert_eq!(list.next_item(), Ok(Item::String(b"cat")));
+        assert_eq!(list.next_item(), Ok(Item::String(b"dog")));
+        assert_eq!(list.finish(), Ok(()));
+
+        let mut encoded = std::vec![0xb8, 56];
+        encoded.extend_from_slice(LOREM);
+        assert_eq!(item(&encoded), Ok((&[][..], Item::String(LOREM))));
+    }
+
+    #[test]
+    fn non_canonical() {
+        assert_eq!(item(&[0x81, 0x05]), Err(RlpError::NonCanonical));
+        assert_eq!(item(&[0xb8, 0x01, 0xff]), Err(RlpError::NonCanonical));
+        assert_eq!(item(&[0xb9, 0x00, 0x38]), Err(RlpError::NonCanonical));
+        assert_eq!(
+            item(&[0x82, 0x00, 0x01]).unwrap().1.u64(),
+            Err(RlpError::InvalidInteger)
+        );
+        assert_eq!(
+            item(&[0x84, 0x61]),
+            Err(RlpError::Parser(ParserError::UnexpectedEof { needed: 3 }))
+        );
+    }
+
+    #[test]
+    fn encode() {
+        let mut out = [0; 64];
+
+        let mut encoder = Encoder::new(&mut out);
+        encoder
+            .list(|e| {
+                e.bytes(b"cat")?;
+                e.bytes(b"dog")
+            })
+            .unwrap();
+        let written = encoder.written();
+        assert_eq!(&out[..written], b"\xc8\x83cat\x83dog");
+
+        let mut encoder = Encoder::new(&mut out);
+        encoder.u64(0).unwrap();
+        encoder.u64(1024).unwrap();
+        let written = encoder.written();
+        assert_eq!(&out[..written], &[0x80, 0x82, 0x04, 0x00]);
+
+        //long list, header needs to be moved
+        let mut out = [0; 60];
+        let mut encoder = Encoder::new(&mut out);
+        encoder.list(|e| e.bytes(LOREM)).unwrap();
+        assert_eq!(encoder.written(), 60);
+        assert_eq!(&out[..4], &[0xf8, 58, 0xb8, 56]);
+
+        let mut out = [0; 59];
+        let mut encoder = Encoder::new(&mut out);
+        assert_eq!(encoder.list(|e| e.bytes(LOREM)), Err(OutputBufferTooSmall));
+    }
+
+    #[test]
+    fn eip155() {
+        //signing data from the EIP-155 example
+        let encoded = hex::decode(
+            "ec098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764000080018080",
+        )
+        .unwrap();
+
+        let (rem, tx) = Item::from_bytes(&encoded).unwrap();
+        assert!(rem.is_empty());
+
+        let mut fields = tx.list().unwrap();
+        let nonce = fields.next_item().unwrap().u64().unwrap();
+        let gas_price = fields.next_item().unwrap().u64().unwrap();
+        let gas = fields.next_item().unwrap().u64().unwrap();
+        let to = fields.next_item().unwrap().bytes().unwrap();
+        let value = fields.next_item().unwrap().uint::<32>().unwrap();
+        let data = fields.next_item().unwrap().bytes().unwrap();
+        let chain_id = fields.next_item().unwrap().u64().unwrap();
+        assert_eq!(fields.count(), 2);
+
+        assert_eq!(nonce, 9);
+        assert_eq!(gas_price, 20_000_000_000);
+        assert_eq!(gas, 21000);
+        assert_eq!(to, &[0x35; 20]);
+        assert_eq!(&value[24..], &1_000_000_000_000_000_000u64.to_be_bytes());
+        assert_eq!(chain_id, 1);
+
+        //rebuild the preimage and hash it
+        let mut out = [0; 64];
+        let mut encoder = Encoder::new(&mut out);
+        encoder
+            .list(|e| {
+                e.u64(nonce)?;
+                e.u64(gas_price)?;
+                e.u64(gas)?;
+                e.bytes(to)?;
+                e.uint(&value)?;
+                e.bytes(data)?;
+                e.u64(chain_id)?;
+                e.u64(0)?;
+                e.u64(0)
+            })
+            .unwrap();
+        let written = encoder.written();
+        assert_eq!(&out[..written], &encoded[..]);
+
+        let mut hash = [0; 32];
+        Keccak::<32>::digest_into(&out[..written], &mut hash).unwrap();
+        assert_eq!(
+            hex::encode(hash),
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+    }
+}
@@ -0,0 +1,235 @@
+/*******************************************************************************
+*   (c) 2023 Zondax AG
+*
+*  Licensed under the Apache License, Version 2.0 (the "License");
+*  you may not use this file except in compliance with the License.
+*  You may obtain a copy of the License at
+*
+*      http://www.apache.org/licenses/LICENSE-2.0
+*
+*  Unless required by applicable law or agreed to in writing, software
+*  distributed under the License is distributed on an "AS IS" BASIS,
+*  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+*  See the License for the specific language governing permissions and
+*  limitations under the License.
+********************************************************************************/
+//! secp256k1 ECDSA signature utilities
+//!
+//! Signatures returned by `SecretKey::sign` are DER encoded, these helpers
+//! normalise them to low-S and convert them to the recoverable `[r || s || v]` form
+
+use core::cmp::Ordering;
+
+use crate::{
+    convert_der_to_rs_unsigned,
+    crypto::ecfp256::{BitFlags, ECCInfo},
+    math, ApduError,
+};
+
+/// Order of the secp256k1 group, big endian
+pub const SECP256K1_N: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
+/// Half of the secp256k1 order, the highest low-S value
+pub const SECP256K1_HALF_N: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// Length of a recoverable `[r || s || v]` signature
+pub const RECOVERABLE_SIGNATURE_LEN: usize = 65;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "derive-debug"), derive(Debug))]
+pub enum SignatureError {
+    /// The signature is not valid DER or R and S don't fit 32 bytes
+    InvalidDER,
+    /// R or S is zero or not below the curve order
+    InvalidScalar,
+    /// The math syscall failed
+    Math,
+}
+
+impl From<crate::Error> for SignatureError {
+    fn from(_: crate::Error) -> Self {
+        Self::Math
+    }
+}
+
+impl From<SignatureError> for ApduError {
+    fn from(e: SignatureError) -> Self {
+        match e {
+            SignatureError::Math => ApduError::ExecutionError,
+            _ => ApduError::DataInvalid,
+        }
+    }
+}
+
+/// Check that `scalar` is in the range `[1, n)`
+fn check_scalar(scalar: &[u8; 32]) -> Result<(), SignatureError> {
+    if scalar.iter().all(|b| *b == 0) || math::cmp(scalar, &SECP256K1_N)? != Ordering::Less {
+        return Err(SignatureError::InvalidScalar);
+    }
+
+    Ok(())
+}
+
+/// Check whether `s` is at most half the curve order
+pub fn is_low_s(s: &[u8; 32]) -> Result<bool, SignatureError> {
+    check_scalar(s)?;
+
+    Ok(math::cmp(s, &SECP256K1_HALF_N)? != Ordering::Greater)
+}
+
+#[inline(never)]
+/// Replace `s` with `n - s` if it's above half the curve order,
+/// as required by Bitcoin and Cosmos
+///
+/// Will return whether `s` was changed, in which case the parity of
+/// the recovery id flips
+pub fn normalize_s(s: &mut [u8; 32]) -> Result<bool, SignatureError> {
+    if is_low_s(s)? {
+        return Ok(false);
+    }
+
+    let high = *s;
+    math::sub(s, &SECP256K1_N, &high)?;
+
+    Ok(true)
+}
+
+/// Compute the recovery id from the flags returned when signing
+pub fn recovery_id(info: BitFlags<ECCInfo>) -> u8 {
+    let mut v = 0;
+    if info.contains(ECCInfo::ParityOdd) {
+        v |= 1;
+    }
+    if info.contains(ECCInfo::XGTn) {
+        v |= 2;
+    }
+
+    v
+}
+
+#[inline(never)]
+/// Convert a DER signature into the low-S recoverable `[r || s || v]` form, written in `out`
+///
+/// `info` are the flags returned by `SecretKey::sign` along the signature, and `v` is
+/// the raw recovery id in `0..=3`, adjusted if S was normalised.
+/// Callers add the offset required by their chain, like 27 for Ethereum
+pub fn der_to_recoverable(
+    der: &[u8],
+    info: BitFlags<ECCInfo>,
+    out: &mut [u8; RECOVERABLE_SIGNATURE_LEN],
+) -> Result<(), SignatureError> {
+    let mut r = [0; 32];
+    let mut s = [0; 32];
+    convert_der_to_rs_unsigned(der, &mut r, &mut s).map_err(|_| SignatureError::InvalidDER)?;
+    check_scalar(&r)?;
+
+    let flipped = normalize_s(&mut s)?;
+
+    out[..32].copy_from_slice(&r);
+    out[32..64].copy_from_slice(&s);
+    out[64] = recovery_id(info) ^ flipped as u8;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{convert_rs_to_der, MAX_DER_SIGNATURE_LEN};
+
+    fn scalar(last: u8) -> [u8; 32] {
+        let mut s = [0; 32];
+        s[31] = last;
+        s
+    }
+
+    #[test]
+    fn low_s() {
+        let mut s = scalar(1);
+        assert_eq!(normalize_s(&mut s), Ok(false));
+        assert_eq!(s, scalar(1));
+
+        let mut s = SECP256K1_HALF_N;
+        assert_eq!(normalize_s(&mut s), Ok(false));
+
+        //n - 1
+        let mut s = SECP256K1_N;
+        s[31] -= 1;
+        assert_eq!(normalize_s(&mut s), Ok(true));
+        assert_eq!(s, scalar(1));
+
+        assert_eq!(
+            normalize_s(&mut SECP256K1_N.clone()),
+            Err(SignatureError::InvalidScalar)
+        );
+        assert_eq!(
+            normalize_s(&mut [0; 32]),
+            Err(SignatureError::InvalidScalar)
+        );
+    }
+
+    #[test]
+    fn recoverable() {
+        let r = scalar(42);
+        let mut high_s = SECP256K1_N;
+        high_s[31] -= 1;
+
+        let mut der = [0; MAX_DER_SIGNATURE_LEN];
+        let len = convert_rs_to_der(&r, &high_s, &mut der).unwrap();
+
+        let mut out = [0; RECOVERABLE_SIGNATURE_LEN];
+        der_to_recoverable(&der[..len], ECCInfo::ParityOdd.into(), &mut out).unwrap();
+        assert_eq!(&out[..32], &r[..]);
+        assert_eq!(&out[32..64], &scalar(1)[..]);
+        //parity flipped together with s
+        assert_eq!(out[64], 0);
+
+        let len = convert_rs_to_der(&r, &scalar(1), &mut der).unwrap();
+        der_to_recoverable(&der[..len], ECCInfo::XGTn.into(), &mut out).unwrap();
+        assert_eq!(out[64], 2);
+        der_to_recoverable(&der[..len], ECCInfo::XGTn | ECCInfo::ParityOdd, &mut out).unwrap();
+        assert_eq!(out[64], 3);
+
+        assert_eq!(
+            der_to_recoverable(&der[..len - 1], BitFlags::empty(), &mut out),
+            Err(SignatureError::InvalidDER)
+        );
+    }
+
+    #[test]
+    fn mock_signature() {
+        use crate::{
+            crypto::{bip32::BIP32Path, ecfp256::SecretKey, Curve, Mode},
+            hash::{Hasher, Sha256},
+        };
+
+        let path = BIP32Path::<5>::new([0x8000_002c, 0x8000_0076, 0x8000_0000, 0, 0]).unwrap();
+        let secret = SecretKey::new(Mode::BIP32, Curve::Secp256K1, path);
+        let digest = Sha256::digest(b"hello").unwrap();
+
+        let mut der = [0; MAX_DER_SIGNATURE_LEN];
+        let (info, len) = secret.sign::<Sha256>(&digest, &mut der).unwrap();
+
+        let mut out = [0; RECOVERABLE_SIGNATURE_LEN];
+        der_to_recoverable(&der[..len], info, &mut out).unwrap();
+        let mut s = [0; 32];
+        s.copy_from_slice(&out[32..64]);
+        assert_eq!(is_low_s(&s), Ok(true));
+
+        //the recovery id must lead back to the signer
+        let signature = k256::ecdsa::Signature::from_slice(&out[..64]).unwrap();
+        let id = k256::ecdsa::RecoveryId::from_byte(out[64]).unwrap();
+        let recovered =
+            k256::ecdsa::VerifyingKey::recover_from_prehash(&digest, &signature, id).unwrap();
+        assert_eq!(
+            recovered.to_encoded_point(false).as_bytes(),
+            secret.public().unwrap().as_ref()
+        );
+    }
+}
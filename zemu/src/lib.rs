@@ -80,3 +80,4 @@ mod ui;
 pub use ui::*;
 
 mod ui_toolkit;
+pub use ui_toolkit::asciify;
@@ -242,25 +242,10 @@ impl<B: UIBackend<KS>, const KS: usize> Zui<B, KS> {
             page_idx,
         );
 
-        const ASCII_RANGE: core::ops::RangeInclusive<u8> = 32..=0x7F;
-        let ascii_range = PIC::new(&ASCII_RANGE).into_inner();
-
         //asciify
         // this section makes the unsafe above safe!
-        message_bytes
-            .iter_mut()
-            .take_while(|&&mut c| c != 0)
-            .filter(|&&mut c| !ascii_range.contains(&c))
-            .for_each(|c| {
-                *c = b'.';
-            });
-        key_bytes
-            .iter_mut()
-            .take_while(|&&mut c| c != 0)
-            .filter(|&&mut c| !ascii_range.contains(&c))
-            .for_each(|c| {
-                *c = b'.';
-            });
+        asciify(message_bytes);
+        asciify(&mut key_bytes[..]);
 
         //update page count (or return error)
         self.page_count = match render_item_result {
@@ -456,3 +441,20 @@ fn c_strlen(s: *const u8, max: usize) -> Result<usize, StrNotNullTerminated> {
         count += 1;
     }
 }
+
+/// Replace the non printable bytes before the null terminator with `.`
+///
+/// Each byte is replaced on its own, so a multi-byte UTF-8 character becomes
+/// as many `.` as its length and the string keeps its length,
+/// which is what the paging of `bolos::ui::handle_message_with` counts lines in
+pub fn asciify(s: &mut [u8]) {
+    const ASCII_RANGE: core::ops::RangeInclusive<u8> = 32..=0x7F;
+    let ascii_range = PIC::new(&ASCII_RANGE).into_inner();
+
+    s.iter_mut()
+        .take_while(|&&mut c| c != 0)
+        .filter(|&&mut c| !ascii_range.contains(&c))
+        .for_each(|c| {
+            *c = b'.';
+        });
+}